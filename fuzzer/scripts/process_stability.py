@@ -24,7 +24,7 @@ def get_addresses(sanitizer_cov_path, unstable_coverage_path):
     with open(unstable_coverage_path) as f:
         for line in f:
             offset = line.strip()
-            if offset:
+            if offset and not offset.startswith("#"):
                 if offset in offset_to_addr:
                     addr_counts[offset_to_addr[offset]] += 1
                 else:
@@ -1,17 +1,27 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration};
 
-use clap::{self, Parser};
+use clap::{self, Args, Parser, Subcommand};
 
+use libafl::Error;
 use libafl_bolts::core_affinity::Cores;
 
+use crate::{
+    config::CampaignConfig,
+    runner::input::model::{InputModel, MutatorGroup},
+};
+
 /// The commandline args this fuzzer accepts
 #[derive(Debug, Parser)]
 #[command(
     name = "zephyr_net_fuzzer",
     about = "A fuzzer for the TCP/IP stack of Zephyr",
-    author = "Valentin Huber <contact@valentinhuber.me>"
+    author = "Valentin Huber <contact@valentinhuber.me>",
+    subcommand_negates_reqs = true
 )]
 pub struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     #[arg(
     short,
     long,
@@ -56,7 +66,7 @@ pub struct Cli {
         name = "ZEPHYR_EXEC_PATH",
         required = true
     )]
-    zephyr_exec_dir: PathBuf,
+    zephyr_exec_dir: Option<PathBuf>,
 
     #[arg(
         short,
@@ -93,6 +103,14 @@ pub struct Cli {
     )]
     solutions_dir: PathBuf,
 
+    #[arg(
+        long,
+        action,
+        help = "Resume the campaign stored in the corpus and solutions directories instead of generating the initial inputs. Restores the scheduler and MOpt metadata if available.",
+        name = "RESUME"
+    )]
+    resume: bool,
+
     #[arg(short, long, help = "Set the stdout path", name = "STDOUT")]
     stdout: Option<PathBuf>,
 
@@ -116,15 +134,333 @@ pub struct Cli {
         name = "STATE_DIFF"
     )]
     state_diff: bool,
+
+    #[arg(
+        long,
+        action,
+        help = "Count how often each state (or state transition) is reached and bucket the counts AFL-style, instead of only marking reached states.",
+        name = "STATE_HITCOUNTS"
+    )]
+    state_hitcounts: bool,
+
+    #[arg(
+        long,
+        action,
+        help = "After each input, check that Zephyr still accepts a new connection and echoes data. Failing this probe counts as an objective.",
+        name = "LIVENESS_PROBE"
+    )]
+    liveness_probe: bool,
+
+    #[arg(
+        long,
+        action,
+        help = "Have Zephyr export network buffer pool stats. New extremes are interesting, resources leaked at the end of an execution count as an objective.",
+        name = "NET_STATS"
+    )]
+    net_stats: bool,
+
+    #[arg(
+        long,
+        action,
+        help = "Use the state annotations (FTZ_STATE) written by Zephyr as feedback, in addition to the state map inferred from the packets.",
+        name = "STATE_ANNOTATIONS"
+    )]
+    state_annotations: bool,
+
+    #[arg(
+        long,
+        action,
+        help = "Have Zephyr log the operands of its comparisons (needs -fsanitize-coverage=trace-cmp), which an input-to-state mutator writes into the header fields of the packets they were compared with.",
+        name = "CMPLOG"
+    )]
+    cmplog: bool,
+
+    #[arg(
+        long,
+        help = "Keep n Zephyr instances booting in the background for each client, so each input runs on one that finished booting instead of waiting for its setup. Coverage of the boot is not part of the executions.",
+        name = "PREWARM",
+        default_value = "0"
+    )]
+    prewarm: usize,
+
+    #[arg(
+        long,
+        action,
+        help = "Schedule AFLNet-style: pick a rarely reached or productive protocol state first, then an input reaching it, and only mutate after the prefix leading to that state.",
+        name = "STATE_SCHEDULER"
+    )]
+    state_scheduler: bool,
+
+    #[arg(
+        long,
+        action,
+        help = "Minimize each corpus entry the first time it is scheduled, keeping the state map entries it sets.",
+        name = "MINIMIZE"
+    )]
+    minimize: bool,
+
+    #[arg(
+        long,
+        help = "Campaign config (TOML) with the monitor, timing, MOpt, replaying and feedback settings. Uses the defaults if not set.",
+        name = "CONFIG"
+    )]
+    config: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Input representation as <container>:<part>, with list, stateful or multipart containers of bytes, parsed or etherparse parts.",
+        name = "INPUT_MODEL",
+        default_value = "list:etherparse"
+    )]
+    input_model: InputModel,
+
+    #[arg(
+        long,
+        help = "Mutator groups passed to MOpt.",
+        name = "MUTATOR_GROUPS",
+        value_delimiter = ',',
+        default_value = "last-entry,appending"
+    )]
+    mutator_groups: Vec<MutatorGroup>,
+
+    #[arg(
+        long,
+        help = "Seed of the campaign, each client derives its own seed from this and its id. Drawn from the current time if not set, the seeds are logged either way.",
+        name = "SEED"
+    )]
+    seed: Option<u64>,
+
+    #[arg(
+        long,
+        help = "Stop all clients after this many seconds and write a summary.",
+        name = "MAX_TIME"
+    )]
+    max_time: Option<u64>,
+
+    #[arg(
+        long,
+        help = "Stop all clients once they executed this many inputs in total and write a summary.",
+        name = "MAX_EXECS"
+    )]
+    max_execs: Option<u64>,
+
+    #[arg(
+        long,
+        help = "Stop all clients once they found this many solutions in total and write a summary.",
+        name = "STOP_AFTER_SOLUTIONS"
+    )]
+    stop_after_solutions: Option<u64>,
+}
+
+/// Tools working on the output of a campaign, fuzzing is the default without any subcommand.
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Write the packets captured for each testcase of corpus or solutions directories to pcap files.
+    ExtractPcaps(ExtractPcapsArgs),
+    /// Execute a single testcase against Zephyr and check whether its behaviour reproduces.
+    Replay(ReplayArgs),
+    /// Shrink a testcase while it still crashes Zephyr the same way or sets the same state map entries.
+    Minimize(MinimizeArgs),
+    /// Replay the testcases of several campaigns and keep a minimal subset covering the same coverage and state map entries.
+    Distill(DistillArgs),
+    /// Replay corpora and map the coverage back to Zephyr's source, written as lcov and per-function summaries.
+    CoverageReport(CoverageReportArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct ExtractPcapsArgs {
+    #[arg(
+        help = "Corpus or solutions directories to extract, searched recursively",
+        name = "DIRS",
+        required = true
+    )]
+    dirs: Vec<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Directory to write the pcaps to, mirroring the structure of the input directories",
+        name = "OUT_DIR",
+        default_value = "pcaps"
+    )]
+    out_dir: PathBuf,
+
+    #[arg(
+        long,
+        action,
+        help = "Write pcapng with directions, states and mutators instead of classic pcap",
+        name = "PCAPNG"
+    )]
+    pcapng: bool,
+
+    #[arg(
+        long,
+        help = "Additionally merge all testcases into this file, starting each one a second after the previous one",
+        name = "MERGE"
+    )]
+    merge: Option<PathBuf>,
+}
+
+impl ExtractPcapsArgs {
+    pub fn dirs(&self) -> &[PathBuf] {
+        &self.dirs
+    }
+
+    pub fn out_dir(&self) -> &PathBuf {
+        &self.out_dir
+    }
+
+    pub fn pcapng(&self) -> bool {
+        self.pcapng
+    }
+
+    pub fn merge(&self) -> Option<&PathBuf> {
+        self.merge.as_ref()
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct ReplayArgs {
+    #[arg(help = "Corpus entry or solution to replay", name = "TESTCASE")]
+    testcase: PathBuf,
+
+    #[arg(
+        long,
+        help = "Execute the testcase n times and compare the results",
+        name = "REPEAT",
+        default_value = "1"
+    )]
+    repeat: usize,
+
+    #[arg(
+        long,
+        help = "Write the packets of the first execution to this pcap file",
+        name = "PCAP",
+        default_value = "replay.pcap"
+    )]
+    pcap: PathBuf,
+}
+
+impl ReplayArgs {
+    pub fn testcase(&self) -> &PathBuf {
+        &self.testcase
+    }
+
+    pub fn repeat(&self) -> usize {
+        self.repeat
+    }
+
+    pub fn pcap(&self) -> &PathBuf {
+        &self.pcap
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct MinimizeArgs {
+    #[arg(help = "Corpus entry or solution to minimize", name = "TESTCASE")]
+    testcase: PathBuf,
+
+    #[arg(
+        long,
+        help = "Write the minimized testcase to this file, defaults to <TESTCASE>.min.json",
+        name = "OUT"
+    )]
+    out: Option<PathBuf>,
+}
+
+impl MinimizeArgs {
+    pub fn testcase(&self) -> &PathBuf {
+        &self.testcase
+    }
+
+    pub fn out(&self) -> PathBuf {
+        self.out
+            .clone()
+            .unwrap_or_else(|| self.testcase.with_extension("min.json"))
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct DistillArgs {
+    #[arg(
+        help = "Corpus directories of the campaigns to distill, searched recursively",
+        name = "DIRS",
+        required = true
+    )]
+    dirs: Vec<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Directory to write the distilled corpus to",
+        name = "OUT_DIR",
+        default_value = "distilled"
+    )]
+    out_dir: PathBuf,
+}
+
+impl DistillArgs {
+    pub fn dirs(&self) -> &[PathBuf] {
+        &self.dirs
+    }
+
+    pub fn out_dir(&self) -> &PathBuf {
+        &self.out_dir
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct CoverageReportArgs {
+    #[arg(
+        help = "Corpus directories to replay, searched recursively",
+        name = "DIRS",
+        required = true
+    )]
+    dirs: Vec<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Directory to write coverage.lcov and functions.csv to",
+        name = "OUT_DIR",
+        default_value = "coverage-report"
+    )]
+    out_dir: PathBuf,
+
+    #[arg(
+        long,
+        help = "Only report source files whose path contains this, empty for all of Zephyr",
+        name = "FILTER",
+        default_value = "subsys/net/ip/"
+    )]
+    filter: String,
+}
+
+impl CoverageReportArgs {
+    pub fn dirs(&self) -> &[PathBuf] {
+        &self.dirs
+    }
+
+    pub fn out_dir(&self) -> &PathBuf {
+        &self.out_dir
+    }
+
+    pub fn filter(&self) -> &str {
+        &self.filter
+    }
 }
 
 impl Cli {
+    pub fn command(&self) -> Option<&Command> {
+        self.command.as_ref()
+    }
+
     pub fn cores(&self) -> &Cores {
         &self.cores
     }
 
-    pub fn zephyr_exec_dir(&self) -> &PathBuf {
-        &self.zephyr_exec_dir
+    /// Only optional for subcommands not running Zephyr, clap enforces it when fuzzing.
+    pub fn zephyr_exec_dir(&self) -> Result<&PathBuf, Error> {
+        self.zephyr_exec_dir.as_ref().ok_or(Error::illegal_argument(
+            "Missing the Zephyr executable path, pass it using --zephyr-exec-dir",
+        ))
     }
 
     pub fn stdout(&self) -> Option<&PathBuf> {
@@ -163,6 +499,38 @@ impl Cli {
         self.state_diff
     }
 
+    pub fn state_hitcounts(&self) -> bool {
+        self.state_hitcounts
+    }
+
+    pub fn liveness_probe(&self) -> bool {
+        self.liveness_probe
+    }
+
+    pub fn net_stats(&self) -> bool {
+        self.net_stats
+    }
+
+    pub fn state_annotations(&self) -> bool {
+        self.state_annotations
+    }
+
+    pub fn cmplog(&self) -> bool {
+        self.cmplog
+    }
+
+    pub fn prewarm(&self) -> usize {
+        self.prewarm
+    }
+
+    pub fn state_scheduler(&self) -> bool {
+        self.state_scheduler
+    }
+
+    pub fn minimize(&self) -> bool {
+        self.minimize
+    }
+
     pub fn corpus_dir(&self) -> &PathBuf {
         &self.corpus_dir
     }
@@ -170,4 +538,48 @@ impl Cli {
     pub fn solutions_dir(&self) -> &PathBuf {
         &self.solutions_dir
     }
+
+    pub fn resume(&self) -> bool {
+        self.resume
+    }
+
+    pub fn input_model(&self) -> InputModel {
+        self.input_model
+    }
+
+    pub fn mutator_groups(&self) -> &[MutatorGroup] {
+        &self.mutator_groups
+    }
+
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    pub fn max_time(&self) -> Option<Duration> {
+        self.max_time.map(Duration::from_secs)
+    }
+
+    pub fn max_execs(&self) -> Option<u64> {
+        self.max_execs
+    }
+
+    pub fn stop_after_solutions(&self) -> Option<u64> {
+        self.stop_after_solutions
+    }
+
+    /// Load the campaign config passed with `--config`, or the defaults.
+    ///
+    /// Directed targets can not be combined with `--state-scheduler`, which picks corpus entries by their protocol state instead of their distance.
+    pub fn campaign_config(&self) -> Result<CampaignConfig, Error> {
+        let config = self
+            .config
+            .as_ref()
+            .map_or_else(|| Ok(CampaignConfig::default()), CampaignConfig::load)?;
+        if self.state_scheduler && config.directed().is_active() {
+            return Err(Error::illegal_argument(
+                "--state-scheduler picks corpus entries by protocol state and would bypass the directed scheduler, remove it or the [directed] targets",
+            ));
+        }
+        Ok(config)
+    }
 }
@@ -0,0 +1,234 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write as _,
+    fs,
+};
+
+use libafl::Error;
+use serde::de::DeserializeOwned;
+
+use crate::{
+    cli::{Cli, CoverageReportArgs},
+    runner::{
+        coverage::{guard_index, symbolize_guards, Location},
+        input::{ZephyrInput, ZephyrInputPart},
+    },
+};
+
+use super::{collect_testcase_paths, load_testcase, with_standalone_executor};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FunctionCoverage {
+    first_line: u32,
+    guards: usize,
+    covered_guards: usize,
+    /// Testcases covering the most covered guard of the function
+    testcases: usize,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct FileCoverage {
+    functions: BTreeMap<String, FunctionCoverage>,
+    /// Testcases covering the most covered guard on each line
+    lines: BTreeMap<u32, usize>,
+}
+
+impl FileCoverage {
+    fn guards(&self) -> usize {
+        self.functions.values().map(|f| f.guards).sum()
+    }
+
+    fn covered_guards(&self) -> usize {
+        self.functions.values().map(|f| f.covered_guards).sum()
+    }
+}
+
+/// Replay all testcases in the corpus directories and map the sancov guards they cover back to the source of Zephyr.
+///
+/// Writes `coverage.lcov` and `functions.csv` for the source files matching `--filter` to `--out-dir`.
+pub fn coverage_report(opt: &Cli, args: &CoverageReportArgs) -> Result<(), Error> {
+    with_input_model!(opt.input_model(), coverage_report_model(opt, args))
+}
+
+fn coverage_report_model<I, II>(opt: &Cli, args: &CoverageReportArgs) -> Result<(), Error>
+where
+    I: ZephyrInput<II> + DeserializeOwned + Clone,
+    II: ZephyrInputPart,
+    Vec<u8>: From<II>,
+{
+    let locations = symbolize_guards(opt.zephyr_exec_dir()?)?;
+
+    let mut inputs = vec![];
+    for dir in args.dirs() {
+        for path in collect_testcase_paths(dir)? {
+            let input =
+                load_testcase::<I, _>(&path)?
+                    .input()
+                    .clone()
+                    .ok_or(Error::illegal_argument(format!(
+                        "Testcase {path:?} does not contain an input"
+                    )))?;
+            inputs.push(input);
+        }
+    }
+    println!("Replaying {} testcases", inputs.len());
+
+    let mut testcases_per_guard = vec![0; locations.len()];
+    with_standalone_executor::<I, II, _>(opt, |executor| {
+        for input in &inputs {
+            let guards = executor
+                .run(input)?
+                .coverage_entries
+                .into_iter()
+                .map(guard_index)
+                .collect::<BTreeSet<_>>();
+            for guard in guards {
+                if let Some(testcases) = testcases_per_guard.get_mut(guard) {
+                    *testcases += 1;
+                }
+            }
+        }
+        Ok(())
+    })?;
+
+    let files = group_by_file(&locations, &testcases_per_guard, args.filter());
+    if files.is_empty() {
+        return Err(Error::illegal_argument(format!(
+            "No coverage guards in source files matching {:?}",
+            args.filter()
+        )));
+    }
+
+    fs::create_dir_all(args.out_dir())?;
+    fs::write(args.out_dir().join("coverage.lcov"), to_lcov(&files))?;
+    fs::write(args.out_dir().join("functions.csv"), to_csv(&files))?;
+
+    println!("Guards covered per file:");
+    for (file, coverage) in &files {
+        println!(
+            "  {file}: {}/{} ({:.1}%)",
+            coverage.covered_guards(),
+            coverage.guards(),
+            100.0 * coverage.covered_guards() as f64 / coverage.guards() as f64
+        );
+    }
+    println!("Report written to {:?}", args.out_dir());
+    Ok(())
+}
+
+fn group_by_file(
+    locations: &[Option<Location>],
+    testcases_per_guard: &[usize],
+    filter: &str,
+) -> BTreeMap<String, FileCoverage> {
+    let mut files = BTreeMap::<String, FileCoverage>::new();
+    for (location, &testcases) in locations.iter().zip(testcases_per_guard) {
+        let Some(location) = location.as_ref().filter(|l| l.file.contains(filter)) else {
+            continue;
+        };
+        let file = files.entry(location.file.clone()).or_default();
+
+        let function =
+            file.functions
+                .entry(location.function.clone())
+                .or_insert(FunctionCoverage {
+                    first_line: location.line,
+                    guards: 0,
+                    covered_guards: 0,
+                    testcases: 0,
+                });
+        function.first_line = function.first_line.min(location.line);
+        function.guards += 1;
+        function.covered_guards += usize::from(testcases > 0);
+        function.testcases = function.testcases.max(testcases);
+
+        let line = file.lines.entry(location.line).or_default();
+        *line = (*line).max(testcases);
+    }
+    files
+}
+
+/// Tracefile in the format of `lcov`, which `genhtml` turns into an HTML report.
+fn to_lcov(files: &BTreeMap<String, FileCoverage>) -> String {
+    let mut lcov = String::new();
+    for (file, coverage) in files {
+        writeln!(lcov, "TN:\nSF:{file}").unwrap();
+        for (name, function) in &coverage.functions {
+            writeln!(lcov, "FN:{},{name}", function.first_line).unwrap();
+        }
+        for (name, function) in &coverage.functions {
+            writeln!(lcov, "FNDA:{},{name}", function.testcases).unwrap();
+        }
+        writeln!(
+            lcov,
+            "FNF:{}\nFNH:{}",
+            coverage.functions.len(),
+            coverage
+                .functions
+                .values()
+                .filter(|f| f.testcases > 0)
+                .count()
+        )
+        .unwrap();
+        for (line, testcases) in &coverage.lines {
+            writeln!(lcov, "DA:{line},{testcases}").unwrap();
+        }
+        writeln!(
+            lcov,
+            "LF:{}\nLH:{}\nend_of_record",
+            coverage.lines.len(),
+            coverage.lines.values().filter(|t| **t > 0).count()
+        )
+        .unwrap();
+    }
+    lcov
+}
+
+fn to_csv(files: &BTreeMap<String, FileCoverage>) -> String {
+    let mut csv = "file,function,line,guards,covered_guards,testcases\n".to_string();
+    for (file, coverage) in files {
+        for (name, function) in &coverage.functions {
+            writeln!(
+                csv,
+                "{file},{name},{},{},{},{}",
+                function.first_line, function.guards, function.covered_guards, function.testcases
+            )
+            .unwrap();
+        }
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::runner::coverage::parse_location;
+
+    use super::{group_by_file, to_lcov};
+
+    #[test]
+    fn lcov_of_filtered_locations() {
+        let locations = [
+            ("tcp_in", "/zephyr/subsys/net/ip/tcp.c:2510"),
+            (
+                "tcp_in",
+                "/zephyr/subsys/net/ip/tcp.c:2514 (discriminator 3)",
+            ),
+            ("tcp_out", "/zephyr/subsys/net/ip/tcp.c:800"),
+            ("k_sleep", "/zephyr/kernel/sched.c:120"),
+            ("??", "??:0"),
+        ]
+        .map(|(f, l)| parse_location(f, l));
+        assert!(locations[4].is_none());
+
+        let files = group_by_file(&locations, &[3, 0, 0, 1, 1], "subsys/net/ip/");
+        assert_eq!(
+            to_lcov(&files),
+            "TN:\nSF:/zephyr/subsys/net/ip/tcp.c\n\
+             FN:2510,tcp_in\nFN:800,tcp_out\n\
+             FNDA:3,tcp_in\nFNDA:0,tcp_out\n\
+             FNF:2\nFNH:1\n\
+             DA:800,0\nDA:2510,3\nDA:2514,0\n\
+             LF:3\nLH:1\nend_of_record\n"
+        );
+    }
+}
@@ -0,0 +1,205 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::PathBuf,
+};
+
+use libafl::{
+    corpus::{CorpusId, Testcase},
+    inputs::Input,
+    Error, HasMetadata as _, SerdeAny,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cli::{Cli, DistillArgs},
+    runner::{
+        input::{generate_filename, serialize_input, PartsInput, ZephyrInput, ZephyrInputPart},
+        observer::packet::PacketMetadata,
+    },
+};
+
+use super::{collect_testcase_paths, load_testcase, with_standalone_executor};
+
+/// Where an entry of a distilled corpus was originally found.
+#[derive(Debug, Clone, SerdeAny, Serialize, Deserialize)]
+pub struct ProvenanceMetadata {
+    /// Corpus directory passed to `distill`
+    campaign: String,
+    /// Path of the testcase in that campaign
+    source: PathBuf,
+}
+
+impl ProvenanceMetadata {
+    pub fn campaign(&self) -> &str {
+        &self.campaign
+    }
+
+    pub fn source(&self) -> &PathBuf {
+        &self.source
+    }
+}
+
+/// Something a testcase covers, which the distilled corpus needs to keep covering.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Feature {
+    Coverage(usize),
+    StateMap(usize),
+}
+
+/// Replay all testcases in the corpus directories and write a minimal subset covering the union of their coverage and state map entries to `--out-dir`.
+///
+/// Returns the number of testcases written.
+pub fn distill(opt: &Cli, args: &DistillArgs) -> Result<usize, Error> {
+    with_input_model!(opt.input_model(), distill_model(opt, args))
+}
+
+fn distill_model<I, II>(opt: &Cli, args: &DistillArgs) -> Result<usize, Error>
+where
+    I: ZephyrInput<II> + PartsInput + Input,
+    II: ZephyrInputPart,
+    Vec<u8>: From<II>,
+{
+    let mut candidates = vec![];
+    for dir in args.dirs() {
+        let campaign = dir.display().to_string();
+        for path in collect_testcase_paths(dir)? {
+            let testcase = load_testcase::<I, _>(&path)?;
+            // keep the origin of testcases that were already distilled
+            let provenance = testcase
+                .metadata::<ProvenanceMetadata>()
+                .cloned()
+                .unwrap_or_else(|_| ProvenanceMetadata {
+                    campaign: campaign.clone(),
+                    source: path.clone(),
+                });
+            let input = testcase
+                .input()
+                .clone()
+                .ok_or(Error::illegal_argument(format!(
+                    "Testcase {path:?} does not contain an input"
+                )))?;
+            candidates.push((input, provenance));
+        }
+    }
+    println!("Replaying {} testcases", candidates.len());
+
+    let executions = with_standalone_executor::<I, II, _>(opt, |executor| {
+        candidates
+            .iter()
+            .map(|(input, _)| executor.run(input))
+            .collect::<Result<Vec<_>, _>>()
+    })?;
+
+    let features = executions
+        .iter()
+        .map(|execution| {
+            execution
+                .coverage_entries
+                .iter()
+                .copied()
+                .map(Feature::Coverage)
+                .chain(
+                    execution
+                        .metadata
+                        .state_map_entries()
+                        .into_iter()
+                        .map(Feature::StateMap),
+                )
+                .collect::<BTreeSet<_>>()
+        })
+        .collect::<Vec<_>>();
+    let sizes = candidates
+        .iter()
+        .map(|(input, _)| input.parts().len())
+        .collect::<Vec<_>>();
+    let selected = select_covering(&features, &sizes);
+
+    fs::create_dir_all(args.out_dir())?;
+    let mut executions = executions.into_iter().map(Some).collect::<Vec<_>>();
+    for (new_id, &i) in selected.iter().enumerate() {
+        let (input, provenance) = &candidates[i];
+        let metadata: PacketMetadata = executions[i].take().unwrap().metadata;
+
+        let mut testcase = Testcase::new(input.clone());
+        testcase.add_metadata(metadata);
+        testcase.add_metadata(provenance.clone());
+        let path = args
+            .out_dir()
+            .join(generate_filename(&testcase, &CorpusId(new_id)));
+        fs::write(path, serialize_input(&testcase, &()))?;
+    }
+
+    let total = features.iter().flatten().collect::<BTreeSet<_>>().len();
+    println!(
+        "Kept {} of {} testcases covering all {total} coverage and state map entries, written to {:?}",
+        selected.len(),
+        candidates.len(),
+        args.out_dir()
+    );
+    // by the stored provenance, already distilled testcases count for the campaign they were found in
+    let mut kept = candidates
+        .iter()
+        .map(|(_, provenance)| (provenance.campaign(), 0))
+        .collect::<BTreeMap<_, _>>();
+    for &i in &selected {
+        *kept.get_mut(candidates[i].1.campaign()).unwrap() += 1;
+    }
+    for (campaign, kept) in kept {
+        println!("  {kept} from {campaign}");
+    }
+    Ok(selected.len())
+}
+
+/// Greedy set cover: Repeatedly pick the candidate covering the most features not covered yet, preferring smaller ones on ties.
+///
+/// Returns the indices of the picked candidates.
+fn select_covering(features: &[BTreeSet<Feature>], sizes: &[usize]) -> Vec<usize> {
+    let mut uncovered = features.iter().flatten().copied().collect::<BTreeSet<_>>();
+    let mut selected = vec![];
+    while !uncovered.is_empty() {
+        let best = features
+            .iter()
+            .enumerate()
+            .map(|(i, f)| (i, f.intersection(&uncovered).count()))
+            .filter(|(_, new)| *new > 0)
+            .max_by(|(a, new_a), (b, new_b)| new_a.cmp(new_b).then(sizes[*b].cmp(&sizes[*a])));
+        let Some((best, _)) = best else {
+            break;
+        };
+        features[best].iter().for_each(|f| {
+            uncovered.remove(f);
+        });
+        selected.push(best);
+    }
+    selected
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::{select_covering, Feature};
+
+    #[test]
+    fn select_covering_prefers_small_supersets() {
+        let features = [
+            vec![Feature::Coverage(0), Feature::Coverage(1)],
+            vec![
+                Feature::Coverage(0),
+                Feature::Coverage(1),
+                Feature::StateMap(0),
+            ],
+            vec![
+                Feature::Coverage(0),
+                Feature::Coverage(1),
+                Feature::StateMap(0),
+            ],
+            vec![Feature::StateMap(1)],
+        ]
+        .map(|f| f.into_iter().collect::<BTreeSet<_>>());
+        let sizes = [1, 5, 3, 2];
+
+        assert_eq!(select_covering(&features, &sizes), vec![2, 3]);
+    }
+}
@@ -0,0 +1,109 @@
+use std::{
+    fs::{self, File},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use libafl::{Error, HasMetadata as _};
+
+use crate::{
+    cli::ExtractPcapsArgs,
+    direction::Source,
+    pcap::{write_pcap, write_pcapng},
+    runner::observer::packet::PacketMetadata,
+};
+
+use super::{collect_testcase_paths, load_testcase};
+
+/// Gap between two testcases in a merged capture.
+const MERGE_SEPARATION: Duration = Duration::from_secs(1);
+
+/// Write the packets captured for each testcase to `<out_dir>/<input dir name>/<path of the testcase>.pcap[ng]`.
+///
+/// Returns the number of testcases written. Testcases without any captured packets are reported at the end.
+pub fn extract_pcaps(args: &ExtractPcapsArgs) -> Result<usize, Error> {
+    let extension = if args.pcapng() { "pcapng" } else { "pcap" };
+    let mut merged: Vec<(Duration, Source<Vec<u8>>, Vec<String>)> = vec![];
+    let mut without_traffic = vec![];
+    let mut written = 0;
+
+    for dir in args.dirs() {
+        let dir_name = dir
+            .canonicalize()?
+            .file_name()
+            .map(PathBuf::from)
+            .unwrap_or_default();
+
+        for path in collect_testcase_paths(dir)? {
+            // only the captured packets are needed, so this works for testcases of any input model
+            let testcase = load_testcase::<serde_json::Value, _>(&path)?;
+            let annotated = match testcase.metadata::<PacketMetadata>() {
+                Ok(metadata) => metadata.annotated_packets()?,
+                Err(_) => vec![],
+            };
+            if annotated.is_empty() {
+                without_traffic.push(path);
+                continue;
+            }
+
+            let relative = path.strip_prefix(dir).unwrap_or(&path);
+            let out_path = args
+                .out_dir()
+                .join(&dir_name)
+                .join(relative)
+                .with_extension(extension);
+            write_capture(&out_path, &annotated, args.pcapng())?;
+            written += 1;
+
+            if args.merge().is_some() {
+                let offset = merged
+                    .last()
+                    .map_or(Duration::ZERO, |(d, _, _)| *d + MERGE_SEPARATION);
+                for (i, (duration, packet, mut comments)) in annotated.into_iter().enumerate() {
+                    if i == 0 {
+                        comments.insert(0, format!("testcase: {}", path.display()));
+                    }
+                    merged.push((offset + duration, packet, comments));
+                }
+            }
+        }
+    }
+
+    if let Some(merge_path) = args.merge() {
+        write_capture(merge_path, &merged, args.pcapng())?;
+    }
+
+    println!("Wrote {written} {extension} files to {:?}", args.out_dir());
+    if !without_traffic.is_empty() {
+        println!(
+            "{} testcases without captured packets:",
+            without_traffic.len()
+        );
+        for path in without_traffic {
+            println!("  {}", path.display());
+        }
+    }
+    Ok(written)
+}
+
+fn write_capture(
+    path: &Path,
+    packets: &[(Duration, Source<Vec<u8>>, Vec<String>)],
+    pcapng: bool,
+) -> Result<usize, Error> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file =
+        File::create(path).map_err(|e| Error::os_error(e, format!("Could not create {path:?}")))?;
+
+    if pcapng {
+        write_pcapng(packets, &mut file)
+    } else {
+        let packets = packets
+            .iter()
+            .map(|(duration, packet, _)| (duration, &**packet))
+            .collect::<Vec<_>>();
+        write_pcap(&packets, &mut file)
+    }
+}
@@ -0,0 +1,64 @@
+use std::fs;
+
+use libafl::{corpus::Testcase, Error, HasMetadata as _};
+
+use crate::{
+    cli::{Cli, MinimizeArgs},
+    runner::{
+        input::{list::ListZephyrInputType, model::InputModel, serialize_input, EtherparseInput},
+        minimizer::{minimization_seeds, minimize_input, MinimizationGoal},
+    },
+};
+
+use super::{load_testcase, with_standalone_executor};
+
+/// Shrink a stored testcase using [`minimize_input`] and write it, along with the packets captured while executing it, to `--out`.
+///
+/// Only supported for the default input model, like `--minimize`. Crashing testcases need to keep crashing after Zephyr last sent a packet in the same state, all others need to keep setting the same state map entries.
+pub fn minimize(opt: &Cli, args: &MinimizeArgs) -> Result<(), Error> {
+    if opt.input_model() != InputModel::default() {
+        return Err(Error::illegal_argument(format!(
+            "Minimizing is only supported for {}, got {}",
+            InputModel::default(),
+            opt.input_model()
+        )));
+    }
+    let testcase = load_testcase::<ListZephyrInputType, _>(args.testcase())?;
+    let input = testcase.input().clone().ok_or(Error::illegal_argument(
+        "Testcase does not contain an input",
+    ))?;
+
+    let seeds = minimization_seeds(opt.campaign_config()?.network());
+    let (minimized, metadata) =
+        with_standalone_executor::<_, EtherparseInput, _>(opt, |executor| {
+            let original = executor.run(&input)?;
+            let goal = MinimizationGoal::new(original.exit_kind, &original.metadata);
+            println!(
+                "Minimizing {} packets, preserving {goal:?}",
+                input.parts().len()
+            );
+
+            let mut executions = 0;
+            let minimized = minimize_input(&input, &seeds, |candidate| {
+                executions += 1;
+                let execution = executor.run(candidate)?;
+                Ok(goal.is_met(execution.exit_kind, &execution.metadata))
+            })?;
+            println!(
+                "Minimized to {} packets in {executions} executions",
+                minimized.parts().len()
+            );
+
+            let execution = executor.run(&minimized)?;
+            if !goal.is_met(execution.exit_kind, &execution.metadata) {
+                log::warn!("The minimized testcase did not reproduce in its final execution");
+            }
+            Ok((minimized, execution.metadata))
+        })?;
+
+    let mut minimized = Testcase::new(minimized);
+    minimized.add_metadata(metadata);
+    fs::write(args.out(), serialize_input(&minimized, &()))?;
+    println!("Wrote the minimized testcase to {:?}", args.out());
+    Ok(())
+}
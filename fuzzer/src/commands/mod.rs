@@ -0,0 +1,218 @@
+//! Subcommands of the fuzzer binary working on the output of a campaign, see [`crate::cli::Command`].
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use libafl::{
+    corpus::Testcase,
+    executors::{ExitKind, HasObservers as _},
+    observers::MapObserver as _,
+    state::NopState,
+    Error,
+};
+use libafl_bolts::{
+    shmem::{MmapShMem, ShMem as _},
+    tuples::{tuple_list, tuple_list_type, Handle, Handled as _},
+};
+use serde::de::DeserializeOwned;
+
+use crate::{
+    cli::Cli,
+    runner::{
+        coverage::{coverage_observer, probe_coverage_map_size, CoverageObserver},
+        input::{deserialize_input, ZephyrInput, ZephyrInputPart},
+        minimizer::run_observed,
+        observer::packet::PacketMetadata,
+        PacketObserver, ZepyhrExecutor,
+    },
+    shmem::get_shmem,
+    NETWORK_SHMEM_SIZE,
+};
+
+/// Call the generic function `$f::<I, II>` with the input type selected by `--input-model`, like [`crate::runner::fuzz`] launches the clients.
+macro_rules! with_input_model {
+    ($model:expr, $f:ident($($arg:expr),*)) => {{
+        use libafl::inputs::{BytesInput, MultipartInput};
+        use $crate::runner::input::{
+            list::ListInput,
+            model::{ContainerModel, PartModel},
+            stateful::ReplayingStatefulInput,
+            EtherparseInput, ParsedZephyrInput,
+        };
+
+        let model = $model;
+        match (model.container(), model.part()) {
+            (ContainerModel::List, PartModel::Bytes) => {
+                $f::<ListInput<BytesInput>, BytesInput>($($arg),*)
+            }
+            (ContainerModel::List, PartModel::Parsed) => {
+                $f::<ListInput<ParsedZephyrInput>, ParsedZephyrInput>($($arg),*)
+            }
+            (ContainerModel::List, PartModel::Etherparse) => {
+                $f::<ListInput<EtherparseInput>, EtherparseInput>($($arg),*)
+            }
+            (ContainerModel::Stateful, PartModel::Bytes) => {
+                $f::<ReplayingStatefulInput<BytesInput>, BytesInput>($($arg),*)
+            }
+            (ContainerModel::Stateful, PartModel::Parsed) => {
+                $f::<ReplayingStatefulInput<ParsedZephyrInput>, ParsedZephyrInput>($($arg),*)
+            }
+            (ContainerModel::Stateful, PartModel::Etherparse) => {
+                $f::<ReplayingStatefulInput<EtherparseInput>, EtherparseInput>($($arg),*)
+            }
+            (ContainerModel::Multipart, PartModel::Bytes) => {
+                $f::<MultipartInput<BytesInput>, BytesInput>($($arg),*)
+            }
+            (ContainerModel::Multipart, PartModel::Parsed) => {
+                $f::<MultipartInput<ParsedZephyrInput>, ParsedZephyrInput>($($arg),*)
+            }
+            (ContainerModel::Multipart, PartModel::Etherparse) => {
+                $f::<MultipartInput<EtherparseInput>, EtherparseInput>($($arg),*)
+            }
+        }
+    }};
+}
+
+pub mod coverage_report;
+pub mod distill;
+pub mod extract_pcaps;
+pub mod minimize;
+pub mod replay;
+
+pub use {
+    coverage_report::coverage_report, distill::distill, extract_pcaps::extract_pcaps,
+    minimize::minimize, replay::replay,
+};
+
+type StandaloneObservers = tuple_list_type!(CoverageObserver, PacketObserver);
+type StandaloneState<I> = NopState<I>;
+
+/// Result of executing an input with [`with_standalone_executor`].
+pub struct Execution {
+    pub exit_kind: ExitKind,
+    pub coverage_hash: u64,
+    pub covered_edges: usize,
+    /// Indices of the entries set in the coverage map
+    pub coverage_entries: Vec<usize>,
+    pub metadata: PacketMetadata,
+}
+
+/// Executes single inputs of the model selected by `--input-model` against Zephyr outside of a campaign, observing the coverage and the packets.
+pub struct StandaloneExecutor<'a, I, II> {
+    executor: ZepyhrExecutor<'a, StandaloneState<I>, StandaloneObservers, II>,
+    state: StandaloneState<I>,
+    cov_observer: Handle<CoverageObserver>,
+    packet_observer: Handle<PacketObserver>,
+}
+
+impl<I, II> StandaloneExecutor<'_, I, II>
+where
+    I: ZephyrInput<II>,
+    II: ZephyrInputPart,
+    Vec<u8>: From<II>,
+{
+    pub fn run(&mut self, input: &I) -> Result<Execution, Error> {
+        let exit_kind = run_observed(&mut (), &mut self.executor, &mut self.state, &mut (), input)?;
+
+        let observers = self.executor.observers();
+        let cov_observer = &observers[&self.cov_observer];
+        let coverage_entries = cov_observer
+            .to_vec()
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| **e != 0)
+            .map(|(i, _)| i)
+            .collect();
+        Ok(Execution {
+            exit_kind,
+            coverage_hash: cov_observer.hash_simple(),
+            covered_edges: cov_observer.count_bytes() as usize,
+            coverage_entries,
+            metadata: observers[&self.packet_observer].get_metadata()?,
+        })
+    }
+}
+
+/// Set up Zephyr and the observers with the options and config of the campaign, then pass the executor to `f`.
+///
+/// Zephyr's output is streamed to the terminal, unless `--zephyr-out-dir` is set. The shared memory is separate from the one of the clients, so this does not interfere with a running campaign.
+pub fn with_standalone_executor<I, II, R>(
+    opt: &Cli,
+    f: impl FnOnce(&mut StandaloneExecutor<'_, I, II>) -> Result<R, Error>,
+) -> Result<R, Error> {
+    let id = std::process::id() as usize;
+    let config = opt.campaign_config()?;
+
+    let coverage_map_size = probe_coverage_map_size(opt.zephyr_exec_dir()?)?;
+    let mut cov_shmem: MmapShMem = get_shmem(coverage_map_size, id, "standalone-cov")?;
+    let cov_shmem_description = cov_shmem.description();
+    let cov_observer = coverage_observer(&mut cov_shmem);
+    let cov_observer_handle = cov_observer.handle();
+    let packet_observer = PacketObserver::new(opt.state_diff(), opt.state_hitcounts());
+    let packet_observer_handle = packet_observer.handle();
+
+    let mut observers = tuple_list!(cov_observer, packet_observer);
+    let executor = ZepyhrExecutor::new(
+        &mut observers,
+        packet_observer_handle.clone(),
+        &cov_shmem_description,
+        opt.zephyr_exec_dir()?.to_path_buf(),
+        opt.zephyr_out_dir().map(PathBuf::to_owned),
+        NETWORK_SHMEM_SIZE,
+        id,
+        opt.zephyr_rt_ratio(),
+    )?
+    .with_inherited_zephyr_output()
+    .with_timing(config.timing())
+    .with_network_profile(config.network().clone())?;
+
+    f(&mut StandaloneExecutor {
+        executor,
+        state: NopState::new(),
+        cov_observer: cov_observer_handle,
+        packet_observer: packet_observer_handle,
+    })
+}
+
+/// All testcases stored in `dir` or its subdirectories, e.g. in a per-client directory of the corpus, ordered by their path.
+///
+/// Hidden files, like the dumped state metadata, are skipped.
+pub fn collect_testcase_paths<P: AsRef<Path>>(dir: P) -> Result<Vec<PathBuf>, Error> {
+    let mut paths = vec![];
+    let mut pending = vec![dir.as_ref().to_path_buf()];
+    while let Some(current) = pending.pop() {
+        for entry in fs::read_dir(&current)
+            .map_err(|e| Error::os_error(e, format!("Could not read directory {current:?}")))?
+        {
+            let path = entry?.path();
+            let hidden = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .is_none_or(|n| n.starts_with('.'));
+            if hidden {
+                continue;
+            }
+            if path.is_dir() {
+                pending.push(path);
+            } else if path.extension().is_some_and(|e| e == "json") {
+                paths.push(path);
+            }
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Read a testcase in the format written by the corpora of the fuzzer.
+///
+/// List and stateful containers are stored the same way, so the input model can not be told from the file, it needs to be passed with `--input-model` like for the campaign.
+pub fn load_testcase<I: DeserializeOwned, P: AsRef<Path>>(path: P) -> Result<Testcase<I>, Error> {
+    deserialize_input(&fs::read(path.as_ref())?).map_err(|e| {
+        Error::serialize(format!(
+            "Could not load testcase {:?}, check that --input-model matches the one of its campaign: {e:?}",
+            path.as_ref()
+        ))
+    })
+}
@@ -0,0 +1,128 @@
+use std::fs;
+
+use libafl::Error;
+use serde::de::DeserializeOwned;
+
+use crate::{
+    cli::{Cli, ReplayArgs},
+    direction::Source,
+    layers::data_link::parse_eth,
+    pcap::write_pcap,
+    runner::{
+        input::{ZephyrInput, ZephyrInputPart},
+        observer::packet::PacketMetadata,
+    },
+};
+
+use super::{load_testcase, with_standalone_executor};
+
+/// Execute a stored testcase `--repeat` times with Zephyr's output streamed to the terminal.
+///
+/// Prints the packets exchanged in the first execution and writes them to the pcap path, then reports whether the exit kind, coverage and state map were the same in all executions. Returns whether they were.
+pub fn replay(opt: &Cli, args: &ReplayArgs) -> Result<bool, Error> {
+    with_input_model!(opt.input_model(), replay_model(opt, args))
+}
+
+fn replay_model<I, II>(opt: &Cli, args: &ReplayArgs) -> Result<bool, Error>
+where
+    I: ZephyrInput<II> + DeserializeOwned + Clone,
+    II: ZephyrInputPart,
+    Vec<u8>: From<II>,
+{
+    let testcase = load_testcase::<I, _>(args.testcase())?;
+    let input = testcase.input().clone().ok_or(Error::illegal_argument(
+        "Testcase does not contain an input",
+    ))?;
+
+    let results = with_standalone_executor::<I, II, _>(opt, |executor| {
+        (0..args.repeat())
+            .map(|repetition| {
+                log::info!("Replaying {:?}, repetition {}", args.testcase(), repetition);
+                executor.run(&input)
+            })
+            .collect::<Result<Vec<_>, _>>()
+    })?;
+
+    let first = results
+        .first()
+        .ok_or(Error::illegal_argument("--repeat needs to be at least 1"))?;
+
+    print_exchange(&first.metadata)?;
+
+    let packets = first.metadata.packets()?;
+    let mut pcap_file = fs::File::create(args.pcap())
+        .map_err(|e| Error::os_error(e, format!("Could not create {:?}", args.pcap())))?;
+    write_pcap(
+        &packets.iter().map(|(d, p)| (d, p)).collect::<Vec<_>>(),
+        &mut pcap_file,
+    )?;
+    println!(
+        "Wrote the packets of the first execution to {:?}",
+        args.pcap()
+    );
+
+    let exit_kinds_match = results.iter().all(|r| r.exit_kind == first.exit_kind);
+    let coverage_matches = results
+        .iter()
+        .all(|r| r.coverage_hash == first.coverage_hash);
+    let state_map_matches = results
+        .iter()
+        .all(|r| r.metadata.state_map() == first.metadata.state_map());
+
+    println!("Results of {} executions:", results.len());
+    println!(
+        "  exit kind: {:?} ({})",
+        first.exit_kind,
+        reproduced(exit_kinds_match)
+    );
+    println!(
+        "  coverage:  {} edges ({})",
+        first.covered_edges,
+        reproduced(coverage_matches)
+    );
+    println!("  state map: {}", reproduced(state_map_matches));
+    if !coverage_matches || !state_map_matches {
+        for (i, result) in results.iter().enumerate() {
+            println!(
+                "    #{i}: {:?}, {} edges, coverage hash {:016x}, state map {}",
+                result.exit_kind,
+                result.covered_edges,
+                result.coverage_hash,
+                result.metadata.state_map()
+            );
+        }
+    }
+
+    Ok(exit_kinds_match && coverage_matches && state_map_matches)
+}
+
+fn reproduced(matches: bool) -> &'static str {
+    if matches {
+        "reproduced in all executions"
+    } else {
+        "differs between executions"
+    }
+}
+
+fn print_exchange(metadata: &PacketMetadata) -> Result<(), Error> {
+    for (duration, packet, comments) in metadata.annotated_packets()? {
+        let direction = match packet {
+            Source::Client(_) => "fuzzer -> zephyr",
+            Source::Server(_) => "zephyr -> fuzzer",
+        };
+        println!(
+            "[{:>10.3?}] {direction} ({} bytes) {}",
+            duration,
+            packet.len(),
+            comments.join(", ")
+        );
+        match parse_eth(&packet) {
+            Ok(parsed) => match parsed.upper() {
+                Some(upper) => println!("{upper:?}"),
+                None => println!("{:?}", parsed.net()),
+            },
+            Err(e) => println!("Could not parse packet: {e:?}"),
+        }
+    }
+    Ok(())
+}
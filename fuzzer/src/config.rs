@@ -0,0 +1,402 @@
+//! Campaign configuration loaded from a TOML file, see [`CampaignConfig`].
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use libafl::Error;
+use serde::{Deserialize, Serialize};
+
+use crate::runner::{
+    coverage::CoverageFilter,
+    network::{JUMBO_MTU, MIN_MTU},
+    NetworkProfile, Timing, INTER_SEND_WAIT, SETUP_TIMEOUT,
+};
+
+/// Longer waits the original experiments used with `coverage_stability`, so the executions are more reproducible.
+pub const STABILITY_TIMING: Timing = Timing {
+    setup_timeout: Duration::from_millis(500),
+    inter_send_wait: Duration::from_millis(200),
+};
+
+/// Everything that differs between experiments, so a single binary can run all of them.
+///
+/// All keys are optional and default to the configuration of the original experiments. The effective configuration is recorded next to the monitor JSON.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CampaignConfig {
+    monitor: MonitorKind,
+    /// Calibrate the coverage of each corpus entry and log unstable entries to `unstable-coverage.txt`.
+    ///
+    /// Uses a random scheduler, since the weighted one is not compatible with calibration. Like in the original experiments, the timing defaults to [`STABILITY_TIMING`] with this.
+    coverage_stability: bool,
+    /// Defaults to [`Timing::default`], or [`STABILITY_TIMING`] with `coverage_stability`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timing: Option<TimingConfig>,
+    mopt: MOptConfig,
+    replaying: ReplayingConfig,
+    feedbacks: FeedbackConfig,
+    /// Addresses of Zephyr and the client, need to match Zephyr's `prj.conf`
+    network: NetworkProfile,
+    /// Sancov guards excluded from the coverage feedback, e.g. to ignore timer-driven kernel code
+    coverage_filter: CoverageFilter,
+    stability: StabilityConfig,
+    directed: DirectedConfig,
+}
+
+impl CampaignConfig {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let content = fs::read_to_string(path.as_ref()).map_err(|e| {
+            Error::os_error(e, format!("Could not read config {:?}", path.as_ref()))
+        })?;
+        let config: Self = toml::from_str(&content).map_err(|e| {
+            Error::illegal_argument(format!("Could not parse config {:?}: {e}", path.as_ref()))
+        })?;
+        if config.network.mtu() > JUMBO_MTU {
+            return Err(Error::illegal_argument(format!(
+                "MTU {} exceeds the {JUMBO_MTU} bytes Zephyr's shmem driver can receive",
+                config.network.mtu()
+            )));
+        }
+        if config.network.mtu() < MIN_MTU {
+            return Err(Error::illegal_argument(format!(
+                "MTU {} is below the {MIN_MTU} bytes IPv6 needs",
+                config.network.mtu()
+            )));
+        }
+        Ok(config)
+    }
+
+    /// Write the effective configuration, e.g. next to the results of a campaign.
+    pub fn store<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let timing = self.timing();
+        let effective = Self {
+            timing: Some(TimingConfig {
+                setup_timeout_ms: timing.setup_timeout.as_millis() as u64,
+                inter_send_wait_ms: timing.inter_send_wait.as_millis() as u64,
+            }),
+            ..self.clone()
+        };
+        let content = toml::to_string_pretty(&effective)
+            .map_err(|e| Error::serialize(format!("Could not serialize config: {e}")))?;
+        fs::write(path.as_ref(), content)
+            .map_err(|e| Error::os_error(e, format!("Could not write config {:?}", path.as_ref())))
+    }
+
+    pub fn monitor(&self) -> MonitorKind {
+        self.monitor
+    }
+
+    pub fn coverage_stability(&self) -> bool {
+        self.coverage_stability
+    }
+
+    pub fn timing(&self) -> Timing {
+        match &self.timing {
+            Some(timing) => Timing {
+                setup_timeout: Duration::from_millis(timing.setup_timeout_ms),
+                inter_send_wait: Duration::from_millis(timing.inter_send_wait_ms),
+            },
+            None if self.coverage_stability => STABILITY_TIMING,
+            None => Timing::default(),
+        }
+    }
+
+    pub fn mopt(&self) -> &MOptConfig {
+        &self.mopt
+    }
+
+    pub fn replaying(&self) -> &ReplayingConfig {
+        &self.replaying
+    }
+
+    pub fn feedbacks(&self) -> &FeedbackConfig {
+        &self.feedbacks
+    }
+
+    pub fn network(&self) -> &NetworkProfile {
+        &self.network
+    }
+
+    pub fn coverage_filter(&self) -> &CoverageFilter {
+        &self.coverage_filter
+    }
+
+    pub fn stability(&self) -> &StabilityConfig {
+        &self.stability
+    }
+
+    pub fn directed(&self) -> &DirectedConfig {
+        &self.directed
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MonitorKind {
+    Tui,
+    Stdout,
+    #[default]
+    None,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct TimingConfig {
+    /// Time waited for Zephyr to boot before sending the first packet
+    setup_timeout_ms: u64,
+    /// Time waited after the last packet before sending the next one
+    inter_send_wait_ms: u64,
+}
+
+impl Default for TimingConfig {
+    fn default() -> Self {
+        Self {
+            setup_timeout_ms: SETUP_TIMEOUT.as_millis() as u64,
+            inter_send_wait_ms: INTER_SEND_WAIT.as_millis() as u64,
+        }
+    }
+}
+
+/// Parameters of `StdMOptMutator`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MOptConfig {
+    max_stack_pow: usize,
+    swarm_num: usize,
+}
+
+impl MOptConfig {
+    pub fn max_stack_pow(&self) -> usize {
+        self.max_stack_pow
+    }
+
+    pub fn swarm_num(&self) -> usize {
+        self.swarm_num
+    }
+}
+
+impl Default for MOptConfig {
+    fn default() -> Self {
+        Self {
+            max_stack_pow: 7,
+            swarm_num: 5,
+        }
+    }
+}
+
+/// Parameters of `ReplayingFuzzer`, which re-executes each input until one result is measured decisively more often than all others.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReplayingConfig {
+    /// How many times more often the most common result needs to be measured than any other
+    min_count_diff: usize,
+    /// By which factor the most common result needs to be measured more often than any other
+    min_count_ratio: f64,
+    /// Executions after which an input without a decisive result is discarded
+    max_trials: usize,
+}
+
+impl ReplayingConfig {
+    pub fn min_count_diff(&self) -> usize {
+        self.min_count_diff
+    }
+
+    pub fn min_count_ratio(&self) -> f64 {
+        self.min_count_ratio
+    }
+
+    pub fn max_trials(&self) -> usize {
+        self.max_trials
+    }
+}
+
+impl Default for ReplayingConfig {
+    fn default() -> Self {
+        Self {
+            min_count_diff: 3,
+            min_count_ratio: 2.0,
+            max_trials: 10,
+        }
+    }
+}
+
+/// Calibration of new corpus entries, whose unstable edges are masked out of the coverage feedback.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StabilityConfig {
+    /// Executions of each corpus entry the first time it is scheduled, less than 2 disables calibrating
+    calibration_runs: usize,
+    /// Unstable edges shared between campaigns, loaded on startup and updated whenever new ones are found
+    unstable_edges: PathBuf,
+}
+
+impl StabilityConfig {
+    pub fn calibration_runs(&self) -> usize {
+        self.calibration_runs
+    }
+
+    pub fn unstable_edges(&self) -> &Path {
+        &self.unstable_edges
+    }
+}
+
+impl Default for StabilityConfig {
+    fn default() -> Self {
+        Self {
+            calibration_runs: 4,
+            unstable_edges: PathBuf::from("unstable-edges.txt"),
+        }
+    }
+}
+
+/// Targets of directed fuzzing, which favours corpus entries getting closer to them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DirectedConfig {
+    /// Function names or `<file>:<line>`, may contain wildcards. Empty to disable directed fuzzing
+    targets: Vec<String>,
+    /// Where the distance of each coverage guard to the targets is written on startup
+    distance_map: PathBuf,
+    /// Time after which entries are mostly picked by their distance instead of exploring
+    exploitation_time_secs: u64,
+}
+
+impl DirectedConfig {
+    pub fn is_active(&self) -> bool {
+        !self.targets.is_empty()
+    }
+
+    pub fn targets(&self) -> &[String] {
+        &self.targets
+    }
+
+    pub fn distance_map(&self) -> &Path {
+        &self.distance_map
+    }
+
+    pub fn exploitation_time(&self) -> Duration {
+        Duration::from_secs(self.exploitation_time_secs)
+    }
+}
+
+impl Default for DirectedConfig {
+    fn default() -> Self {
+        Self {
+            targets: vec![],
+            distance_map: PathBuf::from("distances.txt"),
+            exploitation_time_secs: 45 * 60,
+        }
+    }
+}
+
+/// Which feedbacks decide whether an input is interesting, in addition to the ones enabled on the commandline.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeedbackConfig {
+    /// Guide on Zephyr's coverage, otherwise it is only logged
+    coverage: bool,
+    /// Guide on the state map of the packet observer
+    state: bool,
+    /// Let the last client log memory usage and corpus size
+    memory_stats: bool,
+}
+
+impl FeedbackConfig {
+    pub fn coverage(&self) -> bool {
+        self.coverage
+    }
+
+    pub fn state(&self) -> bool {
+        self.state
+    }
+
+    pub fn memory_stats(&self) -> bool {
+        self.memory_stats
+    }
+}
+
+impl Default for FeedbackConfig {
+    fn default() -> Self {
+        Self {
+            coverage: false,
+            state: true,
+            memory_stats: true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{CampaignConfig, MonitorKind, STABILITY_TIMING};
+    use crate::runner::{Timing, SETUP_TIMEOUT};
+
+    #[test]
+    fn partial_config() {
+        let config: CampaignConfig = toml::from_str(
+            r#"
+            monitor = "tui"
+
+            [replaying]
+            max_trials = 1500
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.monitor(), MonitorKind::Tui);
+        assert_eq!(config.replaying().max_trials(), 1500);
+        assert_eq!(config.replaying().min_count_diff(), 3);
+        assert!(config.feedbacks().state());
+        assert_eq!(config.stability().calibration_runs(), 4);
+        assert!(!config.directed().is_active());
+
+        let stored = toml::to_string_pretty(&config).unwrap();
+        let restored: CampaignConfig = toml::from_str(&stored).unwrap();
+        assert_eq!(restored.replaying().max_trials(), 1500);
+    }
+
+    #[test]
+    fn coverage_stability_defaults_to_longer_timing() {
+        let config: CampaignConfig = toml::from_str("coverage_stability = true").unwrap();
+        assert_eq!(config.timing(), STABILITY_TIMING);
+
+        let config: CampaignConfig = toml::from_str(
+            r#"
+            coverage_stability = true
+
+            [timing]
+            inter_send_wait_ms = 50
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.timing().setup_timeout, SETUP_TIMEOUT);
+        assert_eq!(config.timing().inter_send_wait, Duration::from_millis(50));
+
+        let config: CampaignConfig = toml::from_str("").unwrap();
+        assert_eq!(config.timing(), Timing::default());
+    }
+
+    #[test]
+    fn mtu_is_validated_on_load() {
+        let path = std::env::temp_dir().join(format!("mtu-config-{}.toml", std::process::id()));
+        for (mtu, valid) in [
+            (0, false),
+            (1279, false),
+            (1280, true),
+            (9000, true),
+            (9001, false),
+        ] {
+            std::fs::write(&path, format!("[network]\nmtu = {mtu}\n")).unwrap();
+            assert_eq!(CampaignConfig::load(&path).is_ok(), valid, "mtu {mtu}");
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        assert!(toml::from_str::<CampaignConfig>("monitor_tui = true").is_err());
+    }
+}
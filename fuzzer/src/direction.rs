@@ -57,6 +57,15 @@ impl<T> From<Source<T>> for Direction<T> {
     }
 }
 
+impl<T> From<Direction<T>> for Source<T> {
+    fn from(direction: Direction<T>) -> Self {
+        match direction {
+            Direction::Outgoing(e) => Source::Client(e),
+            Direction::Incoming(e) => Source::Server(e),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Direction<T> {
     Outgoing(T),
@@ -21,7 +21,11 @@ use smoltcp::wire::{IpAddress, Ipv6Address};
 use std::net::Ipv6Addr;
 
 #[allow(unused)]
-pub fn respond_to_arp(incoming: &DataLinkLayerPacket, client_mac: [u8; 6]) -> Vec<u8> {
+pub fn respond_to_arp(
+    incoming: &DataLinkLayerPacket,
+    client_mac: [u8; 6],
+    router_mac: [u8; 6],
+) -> Vec<u8> {
     let arp = match incoming.net() {
         NetworkLayerPacketType::Arp(p) => p,
         _ => panic!("Can not create an ARP response to {:?}", incoming),
@@ -29,7 +33,7 @@ pub fn respond_to_arp(incoming: &DataLinkLayerPacket, client_mac: [u8; 6]) -> Ve
 
     let res_arp = Arp {
         operation: ArpOperations::Reply,
-        sender_hw_addr: MacAddr::new(0x02, 0x00, 0x5e, 0x00, 0x53, 0x31),
+        sender_hw_addr: router_mac.into(),
         sender_proto_addr: arp.target_proto_addr,
         target_hw_addr: arp.sender_hw_addr,
         target_proto_addr: arp.sender_proto_addr,
@@ -73,13 +77,17 @@ pub fn create_response_to_icmpv6_neighbor_solicitation(
         return None;
     }
 
+    let mut payload = vec![
+        0, 0, 0, 2, 4, 0, 0, 0, 255, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 255, 0, 83, 255, 4, 0, 0, 0,
+        255, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 255, 0, 0, 2,
+    ];
+    // the first record joins the solicited-node multicast group of the link local address
+    payload[21..24].copy_from_slice(&ipv6_link_local_addr.as_bytes()[13..16]);
+
     let res_icmpv6 = Icmpv6 {
         icmpv6_type: Icmpv6Type(143),
         icmpv6_code: Icmpv6Codes::NoCode,
-        payload: vec![
-            0, 0, 0, 2, 4, 0, 0, 0, 255, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 255, 0, 83, 255, 4, 0, 0,
-            0, 255, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 255, 0, 0, 2,
-        ],
+        payload,
         checksum: 0,
     };
 
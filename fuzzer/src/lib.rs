@@ -4,6 +4,8 @@
 use runner::{connect_to_zephyr, fuzz};
 
 pub mod cli;
+pub mod commands;
+pub mod config;
 pub mod direction;
 pub mod layers;
 pub mod packets;
@@ -12,8 +14,11 @@ pub mod runner;
 pub mod shmem;
 pub mod smoltcp;
 
-pub const NETWORK_SHMEM_SIZE: usize = 1600;
-pub const COV_SHMEM_SIZE: usize = 26860; // manually extracted
+/// Bytes of frames each direction of the network shmem can hold.
+pub const NETWORK_SHMEM_SIZE: usize = 1 << 15;
+pub const NET_STATS_SHMEM_SIZE: usize = 4096;
+pub const STATE_ANNOTATION_SHMEM_SIZE: usize = 4096;
+pub const CMPLOG_SHMEM_SIZE: usize = 1 << 19;
 pub const PCAP_PATH: &str = "./pcap.pcap";
 
 #[allow(unused)]
@@ -1,11 +1,37 @@
-use fuzzer::runner::fuzz;
+use std::process;
+
+use clap::Parser as _;
+use fuzzer::{
+    cli::{Cli, Command},
+    commands::{coverage_report, distill, extract_pcaps, minimize, replay},
+    runner::fuzz,
+};
 
 fn main() {
     env_logger::builder()
         .target(env_logger::Target::Stdout)
         .init();
 
-    fuzz();
+    let opt = Cli::parse();
+    let result = match opt.command() {
+        Some(Command::ExtractPcaps(args)) => extract_pcaps(args).map(|_| ()),
+        Some(Command::Replay(args)) => replay(&opt, args).map(|reproduced| {
+            if !reproduced {
+                process::exit(1);
+            }
+        }),
+        Some(Command::Minimize(args)) => minimize(&opt, args),
+        Some(Command::Distill(args)) => distill(&opt, args).map(|_| ()),
+        Some(Command::CoverageReport(args)) => coverage_report(&opt, args),
+        None => {
+            fuzz(opt);
+            Ok(())
+        }
+    };
+    if let Err(e) = result {
+        eprintln!("Error: {e}");
+        process::exit(1);
+    }
     // let opt = Cli::parse();
     // let packets = connect_to_zephyr(
     //     b"Hello, World!",
@@ -1,4 +1,5 @@
 use std::{
+    borrow::Cow,
     fs::OpenOptions,
     io::Write,
     ops::Deref,
@@ -8,13 +9,31 @@ use std::{
 };
 
 use pcap_file::{
-    pcap::{PcapPacket, PcapWriter},
-    PcapError,
+    pcap::{PcapPacket, PcapReader, PcapWriter},
+    pcapng::{
+        blocks::{
+            enhanced_packet::{EnhancedPacketBlock, EnhancedPacketOption},
+            interface_description::{InterfaceDescriptionBlock, InterfaceDescriptionOption},
+        },
+        PcapNgWriter,
+    },
+    DataLink, PcapError,
 };
 
 use libafl::Error;
 
-use crate::direction::Direction;
+use crate::{
+    direction::{Direction, Source},
+    runner::observer::state::PacketState,
+};
+
+/// pcapng interface ids, packets sent by the fuzzer and by Zephyr are captured on separate interfaces.
+const CLIENT_INTERFACE_ID: u32 = 0;
+const SERVER_INTERFACE_ID: u32 = 1;
+
+/// EPB flags, bits 0-1 are the direction.
+const EPB_FLAG_INBOUND: u32 = 0b01;
+const EPB_FLAG_OUTBOUND: u32 = 0b10;
 
 #[allow(clippy::type_complexity)]
 static PACKETS: LazyLock<Mutex<Vec<Direction<(Duration, Vec<u8>)>>>> =
@@ -47,6 +66,31 @@ pub fn dump_global_packets_to_pcap_file<P: AsRef<Path>>(
     dump_packets_to_pcap_file(&packets, path, append)
 }
 
+/// Like [`dump_global_packets_to_pcap_file`], but writes pcapng with directions and inferred states.
+#[allow(unused)]
+pub fn dump_global_packets_to_pcapng_file<P: AsRef<Path>>(path: P) -> Result<usize, Error> {
+    let packets = PACKETS.lock().unwrap();
+    let packets = packets
+        .iter()
+        .map(|packet| {
+            let (duration, source) = match packet {
+                Direction::Outgoing((d, p)) => (*d, Source::Client(p.as_slice())),
+                Direction::Incoming((d, p)) => (*d, Source::Server(p.as_slice())),
+            };
+            let comments = vec![format!("state: {:?}", PacketState::from(*source))];
+            (duration, source, comments)
+        })
+        .collect::<Vec<_>>();
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(path)
+        .map_err(|e| Error::os_error(e, "Could not create .pcapng file"))?;
+    write_pcapng(&packets, &mut file)
+}
+
 #[allow(unused)]
 pub fn dump_packets_to_pcap_file<P: AsRef<Path>>(
     packets: &[(&Duration, &Vec<u8>)],
@@ -102,3 +146,151 @@ fn map_pcap_err(message: &str) -> impl Fn(PcapError) -> Error + use<'_> {
         e => Error::unknown(format!("{}: {:?}", message, e)),
     }
 }
+
+/// Read the packets of a classic pcap file, e.g. one written by [`write_pcap`].
+pub fn read_pcap(pcap: &[u8]) -> Result<Vec<(Duration, Vec<u8>)>, Error> {
+    let mut reader = PcapReader::new(pcap).map_err(map_pcap_err("Could not create pcap reader"))?;
+    let mut packets = vec![];
+    while let Some(packet) = reader.next_packet() {
+        let packet = packet.map_err(map_pcap_err("Could not read pcap entry"))?;
+        packets.push((packet.timestamp, packet.data.into_owned()));
+    }
+    Ok(packets)
+}
+
+/// Write packets as pcapng.
+///
+/// Packets sent by the client are written as outbound on a separate interface from the inbound ones sent by Zephyr. Each packet gets its comments attached.
+pub fn write_pcapng<W: Write, P: AsRef<[u8]>>(
+    packets: &[(Duration, Source<P>, Vec<String>)],
+    file: &mut W,
+) -> Result<usize, Error> {
+    let mut pcapng_writer =
+        PcapNgWriter::new(file).map_err(map_pcap_err("Could not create pcapng writer"))?;
+
+    // in the order of CLIENT_INTERFACE_ID and SERVER_INTERFACE_ID
+    for (name, description) in [
+        ("fuzzer", "Packets sent by the fuzzer to Zephyr"),
+        ("zephyr", "Packets sent by Zephyr"),
+    ] {
+        pcapng_writer
+            .write_pcapng_block(InterfaceDescriptionBlock {
+                linktype: DataLink::ETHERNET,
+                snaplen: 0,
+                options: vec![
+                    InterfaceDescriptionOption::IfName(Cow::Borrowed(name)),
+                    InterfaceDescriptionOption::IfDescription(Cow::Borrowed(description)),
+                ],
+            })
+            .map_err(map_pcap_err("Could not write pcapng interface description"))?;
+    }
+
+    let lens = packets
+        .iter()
+        .map(|(duration, packet, comments)| {
+            let data = (**packet).as_ref();
+            let (interface_id, flags) = match packet {
+                Source::Client(_) => (CLIENT_INTERFACE_ID, EPB_FLAG_OUTBOUND),
+                Source::Server(_) => (SERVER_INTERFACE_ID, EPB_FLAG_INBOUND),
+            };
+            let options = [EnhancedPacketOption::Flags(flags)]
+                .into_iter()
+                .chain(
+                    comments
+                        .iter()
+                        .map(|c| EnhancedPacketOption::Comment(Cow::Borrowed(c.as_str()))),
+                )
+                .collect();
+
+            pcapng_writer
+                .write_pcapng_block(EnhancedPacketBlock {
+                    interface_id,
+                    timestamp: *duration,
+                    original_len: data.len().try_into().expect("Could not parse usize to u32"),
+                    data: Cow::Borrowed(data),
+                    options,
+                })
+                .map_err(map_pcap_err("Could not write pcapng entry"))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(lens.iter().sum())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use pcap_file::pcapng::{blocks::enhanced_packet::EnhancedPacketOption, Block, PcapNgReader};
+
+    use super::{
+        write_pcapng, CLIENT_INTERFACE_ID, EPB_FLAG_INBOUND, EPB_FLAG_OUTBOUND, SERVER_INTERFACE_ID,
+    };
+    use crate::direction::Source;
+
+    #[test]
+    fn pcapng_round_trip() {
+        let packets = vec![
+            (
+                Duration::from_micros(1_500),
+                Source::Client(vec![1, 2, 3]),
+                vec!["state: Tcp(2)".to_string(), "input part: 0".to_string()],
+            ),
+            (
+                Duration::from_micros(2_750),
+                Source::Server(vec![4, 5]),
+                vec!["state: Tcp(18)".to_string()],
+            ),
+        ];
+        let mut pcapng = vec![];
+        write_pcapng(&packets, &mut pcapng).unwrap();
+
+        let mut reader = PcapNgReader::new(pcapng.as_slice()).unwrap();
+        let mut read = vec![];
+        let mut interfaces = 0;
+        while let Some(block) = reader.next_block() {
+            match block.unwrap() {
+                Block::InterfaceDescription(_) => interfaces += 1,
+                Block::EnhancedPacket(epb) => {
+                    let mut flags = None;
+                    let mut comments = vec![];
+                    for option in epb.options {
+                        match option {
+                            EnhancedPacketOption::Flags(f) => flags = Some(f),
+                            EnhancedPacketOption::Comment(c) => comments.push(c.into_owned()),
+                            _ => {}
+                        }
+                    }
+                    read.push((
+                        epb.interface_id,
+                        flags,
+                        epb.timestamp,
+                        epb.data.into_owned(),
+                        comments,
+                    ));
+                }
+                _ => {}
+            }
+        }
+
+        assert_eq!(interfaces, 2);
+        assert_eq!(
+            read,
+            vec![
+                (
+                    CLIENT_INTERFACE_ID,
+                    Some(EPB_FLAG_OUTBOUND),
+                    packets[0].0,
+                    vec![1, 2, 3],
+                    packets[0].2.clone()
+                ),
+                (
+                    SERVER_INTERFACE_ID,
+                    Some(EPB_FLAG_INBOUND),
+                    packets[1].0,
+                    vec![4, 5],
+                    packets[1].2.clone()
+                ),
+            ]
+        );
+    }
+}
@@ -16,28 +16,26 @@ use smoltcp::{
     iface::{Config, Interface, SocketSet},
     socket::tcp::Socket,
     storage::RingBuffer,
-    wire::{EthernetAddress, HardwareAddress, IpAddress, IpCidr, Ipv4Address, Ipv6Address},
+    wire::{EthernetAddress, HardwareAddress},
 };
 
 use crate::{
     direction::Source,
-    runner::{
-        get_path, CLIENT_MAC_ADDR, CLIENT_PORT, IPV6_LINK_LOCAL_ADDR, ZEPHYR_IP, ZEPHYR_PORT,
-    },
+    runner::{coverage::probe_coverage_map_size, get_path, NetworkProfile, Timing},
     shmem::get_shmem,
     smoltcp::{
         shmem_net_device::ShmemNetworkDevice, smoltcp_shmem_net_device::SmoltcpShmemNetworkDevice,
     },
-    COV_SHMEM_SIZE,
 };
 
 /// Initialize shared memory for coverage and network
 fn init_shared_memory(
+    coverage_map_size: usize,
     network_buf_size: usize,
     id: usize,
 ) -> Result<(ShMemDescription, ShmemNetworkDevice), Error> {
     // Create coverage shared memory
-    let cov_shmem = get_shmem(COV_SHMEM_SIZE, id, "cov")?;
+    let cov_shmem = get_shmem(coverage_map_size, id, "cov")?;
     let cov_shmem_description = cov_shmem.description();
 
     // Create network device with its own shared memory
@@ -105,7 +103,9 @@ pub fn connect_to_zephyr(
     timeout: Duration,
 ) -> Result<Vec<(Duration, Vec<u8>)>, Error> {
     // Initialize shared memory
-    let (cov_shmem_description, mut device) = init_shared_memory(network_buf_size, id)?;
+    let coverage_map_size = probe_coverage_map_size(zephyr_exec_path)?;
+    let (cov_shmem_description, mut device) =
+        init_shared_memory(coverage_map_size, network_buf_size, id)?;
     let net_shmem_desc = device.get_shmem_description();
 
     // Setup environment variables and start Zephyr
@@ -116,33 +116,69 @@ pub fn connect_to_zephyr(
     let packets = Arc::new(Mutex::new(Vec::new()));
     let packets_clone = packets.clone();
 
-    device.init_zephyr(|p| packets.lock().unwrap().push((start_time.elapsed(), p)))?;
+    let network = NetworkProfile::default();
+    device.init_zephyr(Timing::default(), &network, |p| {
+        packets.lock().unwrap().push((start_time.elapsed(), p))
+    })?;
 
     let mut device = SmoltcpShmemNetworkDevice::new(device, move |packet| {
-        let elapsed = start_time.elapsed();
-        let packet = match packet {
-            crate::direction::Direction::Outgoing(e) => Source::Client(e),
-            crate::direction::Direction::Incoming(e) => Source::Server(e),
-        };
-        packets_clone.lock().unwrap().push((elapsed, packet));
+        packets_clone
+            .lock()
+            .unwrap()
+            .push((start_time.elapsed(), Source::from(packet)));
     });
 
+    exchange_message(
+        &mut device,
+        &mut child,
+        message,
+        timeout,
+        &network,
+        network.client_port(),
+    )?;
+
+    // Cleanup
+    log::info!("Socket no longer active, shutting down");
+    child.kill().unwrap();
+    child.wait().unwrap();
+    let packets = packets
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(t, p)| (*t, Source::deref(p).clone()))
+        .collect();
+    Ok(packets)
+}
+
+/// Open a fresh TCP connection to Zephyr's echo server, send `message` and collect everything echoed back.
+///
+/// Connects from `local_port`. Stops once the connection is closed, the `timeout` is reached or the Zephyr process exits.
+pub fn exchange_message(
+    device: &mut SmoltcpShmemNetworkDevice,
+    child: &mut Child,
+    message: &[u8],
+    timeout: Duration,
+    network: &NetworkProfile,
+    local_port: u16,
+) -> Result<Vec<u8>, Error> {
     // Setup network interface
-    let mut iface = create_iface(&mut device, CLIENT_MAC_ADDR, *IPV6_LINK_LOCAL_ADDR)?;
+    let mut iface = create_iface(device, network)?;
 
     let mut socket = Socket::new(
         RingBuffer::new([0; 100000].to_vec()),
         RingBuffer::new([0; 100000].to_vec()),
     );
 
-    log::info!("Connecting to socket on {}:{}", ZEPHYR_IP, ZEPHYR_PORT);
+    let (zephyr_ip, zephyr_port) = network.zephyr_endpoint();
+    log::info!("Connecting to socket on {}:{}", zephyr_ip, zephyr_port);
     socket
-        .connect(iface.context(), (ZEPHYR_IP, ZEPHYR_PORT), CLIENT_PORT)
+        .connect(iface.context(), (zephyr_ip, zephyr_port), local_port)
         .map_err(|e| Error::unknown(format!("Could not connect socket: {:#?}", e)))?;
 
     let mut sockets = SocketSet::new(Vec::new());
     let handle = sockets.add(socket);
     let mut iters_since_sending = 0;
+    let mut echoed = Vec::new();
     let start_time = Instant::now();
 
     while sockets.get::<Socket<'_>>(handle).is_active() {
@@ -151,19 +187,20 @@ pub fn connect_to_zephyr(
             break;
         }
 
-        iface.poll(Instant::now().into(), &mut device, &mut sockets);
+        iface.poll(Instant::now().into(), device, &mut sockets);
 
         let socket: &mut Socket<'_> = sockets.get_mut(handle);
 
         let received = (socket.may_recv() && socket.can_recv())
-            .then(|| socket.recv(|data| (data.len(), data)).unwrap())
+            .then(|| socket.recv(|data| (data.len(), data.to_vec())).unwrap())
             .and_then(|data| (!data.is_empty()).then_some(data));
 
         if let Some(data) = received {
             log::info!(
                 "Received data: {:?}",
-                str::from_utf8(data).unwrap_or("(invalid utf8)")
+                str::from_utf8(&data).unwrap_or("(invalid utf8)")
             );
+            echoed.extend(data);
             continue;
         }
 
@@ -198,49 +235,34 @@ pub fn connect_to_zephyr(
         }
     }
 
-    // Cleanup
-    log::info!("Socket no longer active, shutting down");
-    child.kill().unwrap();
-    child.wait().unwrap();
-    let packets = packets
-        .lock()
-        .unwrap()
-        .iter()
-        .map(|(t, p)| (*t, Source::deref(p).clone()))
-        .collect();
-    Ok(packets)
+    Ok(echoed)
 }
 
 fn create_iface(
     device: &mut SmoltcpShmemNetworkDevice,
-    client_mac: [u8; 6],
-    ipv6_link_local_addr: IpAddress,
+    network: &NetworkProfile,
 ) -> Result<Interface, Error> {
     let mut iface = Interface::new(
-        Config::new(HardwareAddress::Ethernet(EthernetAddress(client_mac))),
+        Config::new(HardwareAddress::Ethernet(EthernetAddress(
+            network.client_mac(),
+        ))),
         device,
         smoltcp::time::Instant::ZERO,
     );
 
     iface.update_ip_addrs(|ip_addrs| {
-        ip_addrs
-            .push(IpCidr::new(IpAddress::v4(192, 0, 2, 2), 24))
-            .unwrap();
-        ip_addrs
-            .push(IpCidr::new(ipv6_link_local_addr, 64))
-            .unwrap();
-        ip_addrs
-            .push(IpCidr::new(IpAddress::v6(0xfdaa, 0, 0, 0, 0, 0, 0, 1), 64))
-            .unwrap();
+        for cidr in network.client_cidrs() {
+            ip_addrs.push(cidr).unwrap();
+        }
     });
 
     iface
         .routes_mut()
-        .add_default_ipv4_route(Ipv4Address::new(192, 0, 2, 1))
+        .add_default_ipv4_route(network.ipv4_gateway())
         .map_err(|e| Error::unknown(format!("Could not add ipv4 route: {e:#?}")))?;
     iface
         .routes_mut()
-        .add_default_ipv6_route(Ipv6Address::new(0xfe80, 0, 0, 0, 0, 0, 0, 0x100))
+        .add_default_ipv6_route(network.ipv6_gateway())
         .map_err(|e| Error::unknown(format!("Could not add ipv6 route: {e:#?}")))?;
 
     Ok(iface)
@@ -0,0 +1,100 @@
+use std::borrow::Cow;
+
+use libafl::{
+    corpus::{Corpus as _, HasCurrentCorpusId},
+    executors::{Executor, HasObservers},
+    observers::ObserversTuple,
+    stages::{RetryCountRestartHelper, Stage},
+    state::HasCorpus,
+    Error, HasMetadata, HasNamedMetadata,
+};
+use libafl_bolts::tuples::{Handle, MatchName};
+
+use super::{
+    minimizer::run_observed,
+    observer::cmplog::{CmpLogMetadata, CmpLogObserver},
+};
+
+/// Stage providing the operands Zephyr compared while executing the current corpus entry as [`CmpLogMetadata`] of the state, for the [`super::input::i2s::I2SFieldMutator`].
+///
+/// Each entry is executed once the first time it is scheduled, its operands are kept in the metadata of the testcase afterwards.
+pub struct CmpLogTracingStage {
+    cmplog_observer: Handle<CmpLogObserver>,
+    active: bool,
+    name: Cow<'static, str>,
+}
+
+impl CmpLogTracingStage {
+    pub fn new(cmplog_observer: Handle<CmpLogObserver>, active: bool) -> Self {
+        Self {
+            cmplog_observer,
+            active,
+            name: Cow::Borrowed("CmpLogTracingStage"),
+        }
+    }
+}
+
+impl<E, EM, I, S, Z> Stage<E, EM, S, Z> for CmpLogTracingStage
+where
+    E: Executor<EM, I, S, Z> + HasObservers,
+    E::Observers: ObserversTuple<I, S> + MatchName,
+    I: Clone,
+    S: HasCorpus<I> + HasCurrentCorpusId + HasMetadata + HasNamedMetadata,
+{
+    fn should_restart(&mut self, state: &mut S) -> Result<bool, Error> {
+        RetryCountRestartHelper::no_retry(state, &self.name)
+    }
+
+    fn clear_progress(&mut self, state: &mut S) -> Result<(), Error> {
+        RetryCountRestartHelper::clear_progress(state, &self.name)
+    }
+
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut S,
+        manager: &mut EM,
+    ) -> Result<(), Error> {
+        if !self.active {
+            return Ok(());
+        }
+        let Some(id) = state.current_corpus_id()? else {
+            return Ok(());
+        };
+
+        let cached = state
+            .corpus()
+            .get(id)?
+            .borrow()
+            .metadata::<CmpLogMetadata>()
+            .ok()
+            .cloned();
+        if let Some(metadata) = cached {
+            state.add_metadata(metadata);
+            return Ok(());
+        }
+
+        let input = state
+            .corpus()
+            .get(id)?
+            .borrow()
+            .input()
+            .clone()
+            .ok_or(Error::empty_optional("Corpus entry has no input"))?;
+
+        run_observed(fuzzer, executor, state, manager, &input)?;
+        let metadata = CmpLogMetadata::new(executor.observers()[&self.cmplog_observer].operands());
+        log::debug!(
+            "Corpus entry {id} compared {} distinct operands",
+            metadata.operands().len()
+        );
+        state
+            .corpus()
+            .get(id)?
+            .borrow_mut()
+            .add_metadata(metadata.clone());
+        state.add_metadata(metadata);
+        Ok(())
+    }
+}
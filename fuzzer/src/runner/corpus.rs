@@ -0,0 +1,266 @@
+use std::{
+    cell::RefCell,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use libafl::{
+    corpus::{Corpus, CorpusId, InMemoryCorpus, Testcase},
+    inputs::Input,
+    Error,
+};
+use serde::{Deserialize, Serialize};
+
+use super::input::{deserialize_corpus_entry, generate_filename, serialize_corpus_entry};
+
+/// Corpus keeping all testcases in memory, while also storing each of them as a JSON file containing the input and its metadata.
+///
+/// See [`serialize_corpus_entry`] for the format and [`generate_filename`] for the file names. The stored testcases can be read back using [`JsonOnDiskCorpus::load_dir`], e.g. to resume a campaign.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(bound = "I: serde::de::DeserializeOwned + Serialize")]
+pub struct JsonOnDiskCorpus<I> {
+    inner: InMemoryCorpus<I>,
+    dir: PathBuf,
+}
+
+/// Corpora storing their testcases along with the metadata, which need to store them again when a stage adds metadata to an existing corpus entry.
+pub trait StoresMetadata {
+    /// Store the testcase `id` again, with its current metadata.
+    fn store_metadata(&self, id: CorpusId) -> Result<(), Error>;
+}
+
+/// A testcase read back by [`JsonOnDiskCorpus::load_dir`].
+pub struct StoredTestcase<I> {
+    pub path: PathBuf,
+    pub testcase: Testcase<I>,
+    /// Whether it was added with [`Corpus::add_disabled`]
+    pub disabled: bool,
+}
+
+impl<I: Input> JsonOnDiskCorpus<I> {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Result<Self, Error> {
+        fs::create_dir_all(dir.as_ref())?;
+        Ok(Self {
+            inner: InMemoryCorpus::new(),
+            dir: dir.as_ref().to_path_buf(),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Path of the JSON file of a testcase in this corpus.
+    pub fn path_of(&self, id: CorpusId) -> Result<PathBuf, Error> {
+        let testcase = self.inner.get_from_all(id)?.borrow();
+        Ok(self.dir.join(generate_filename(&*testcase, &id)))
+    }
+
+    fn store(&self, id: CorpusId, disabled: bool) -> Result<(), Error> {
+        let testcase = self.inner.get_from_all(id)?.borrow();
+        let path = self.dir.join(generate_filename(&*testcase, &id));
+        fs::write(path, serialize_corpus_entry(&*testcase, disabled))?;
+        Ok(())
+    }
+
+    fn delete(&self, id: CorpusId, testcase: &Testcase<I>) -> Result<(), Error> {
+        let path = self.dir.join(generate_filename(testcase, &id));
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    /// Read all testcases stored in `dir`, ordered by the id they had in their corpus.
+    pub fn load_dir<P: AsRef<Path>>(dir: P) -> Result<Vec<StoredTestcase<I>>, Error> {
+        if !dir.as_ref().exists() {
+            return Ok(vec![]);
+        }
+
+        let mut paths = fs::read_dir(dir)?
+            .flat_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| {
+                p.extension().is_some_and(|e| e == "json")
+                    && p.file_name()
+                        .and_then(|n| n.to_str())
+                        .is_some_and(|n| !n.starts_with('.'))
+            })
+            .collect::<Vec<_>>();
+        paths.sort_by_key(|p| {
+            p.file_name()
+                .and_then(|n| n.to_str())
+                .and_then(|n| n.split('-').next())
+                .and_then(|id| id.parse::<usize>().ok())
+                .unwrap_or(usize::MAX)
+        });
+
+        paths
+            .into_iter()
+            .map(|path| {
+                let (testcase, disabled) =
+                    deserialize_corpus_entry(&fs::read(&path)?).map_err(|e| {
+                        Error::serialize(format!("Could not load testcase {path:?}: {e:?}"))
+                    })?;
+                Ok(StoredTestcase {
+                    path,
+                    testcase,
+                    disabled,
+                })
+            })
+            .collect()
+    }
+}
+
+impl<I: Input> StoresMetadata for JsonOnDiskCorpus<I> {
+    fn store_metadata(&self, id: CorpusId) -> Result<(), Error> {
+        let disabled = self.inner.get(id).is_err();
+        self.store(id, disabled)
+    }
+}
+
+impl<I: Input> Corpus<I> for JsonOnDiskCorpus<I> {
+    fn count(&self) -> usize {
+        self.inner.count()
+    }
+
+    fn count_disabled(&self) -> usize {
+        self.inner.count_disabled()
+    }
+
+    fn count_all(&self) -> usize {
+        self.inner.count_all()
+    }
+
+    fn add(&mut self, testcase: Testcase<I>) -> Result<CorpusId, Error> {
+        let id = self.inner.add(testcase)?;
+        self.store(id, false)?;
+        Ok(id)
+    }
+
+    fn add_disabled(&mut self, testcase: Testcase<I>) -> Result<CorpusId, Error> {
+        let id = self.inner.add_disabled(testcase)?;
+        self.store(id, true)?;
+        Ok(id)
+    }
+
+    fn replace(&mut self, id: CorpusId, testcase: Testcase<I>) -> Result<Testcase<I>, Error> {
+        let prev = self.inner.replace(id, testcase)?;
+        self.delete(id, &prev)?;
+        self.store(id, false)?;
+        Ok(prev)
+    }
+
+    fn remove(&mut self, id: CorpusId) -> Result<Testcase<I>, Error> {
+        let testcase = self.inner.remove(id)?;
+        self.delete(id, &testcase)?;
+        Ok(testcase)
+    }
+
+    fn get(&self, id: CorpusId) -> Result<&RefCell<Testcase<I>>, Error> {
+        self.inner.get(id)
+    }
+
+    fn get_from_all(&self, id: CorpusId) -> Result<&RefCell<Testcase<I>>, Error> {
+        self.inner.get_from_all(id)
+    }
+
+    fn peek_free_id(&self) -> CorpusId {
+        self.inner.peek_free_id()
+    }
+
+    fn current(&self) -> &Option<CorpusId> {
+        self.inner.current()
+    }
+
+    fn current_mut(&mut self) -> &mut Option<CorpusId> {
+        self.inner.current_mut()
+    }
+
+    fn next(&self, id: CorpusId) -> Option<CorpusId> {
+        self.inner.next(id)
+    }
+
+    fn prev(&self, id: CorpusId) -> Option<CorpusId> {
+        self.inner.prev(id)
+    }
+
+    fn first(&self) -> Option<CorpusId> {
+        self.inner.first()
+    }
+
+    fn last(&self) -> Option<CorpusId> {
+        self.inner.last()
+    }
+
+    fn nth_from_all(&self, nth: usize) -> CorpusId {
+        self.inner.nth_from_all(nth)
+    }
+
+    fn load_input_into(&self, testcase: &mut Testcase<I>) -> Result<(), Error> {
+        self.inner.load_input_into(testcase)
+    }
+
+    fn store_input_from(&self, testcase: &Testcase<I>) -> Result<(), Error> {
+        self.inner.store_input_from(testcase)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, time::Duration};
+
+    use libafl::{
+        corpus::{Corpus, Testcase},
+        inputs::BytesInput,
+        HasMetadata as _,
+    };
+
+    use super::{JsonOnDiskCorpus, StoresMetadata as _};
+    use crate::runner::stability::CalibratedMetadata;
+
+    #[test]
+    fn load_dir_keeps_disabled_and_exec_time() {
+        let dir = env::temp_dir().join(format!("ftz-corpus-test-{}", std::process::id()));
+        let mut corpus = JsonOnDiskCorpus::new(&dir).unwrap();
+        let mut enabled = Testcase::new(BytesInput::new(vec![1]));
+        enabled.set_exec_time(Duration::from_millis(42));
+        corpus.add(enabled).unwrap();
+        corpus
+            .add_disabled(Testcase::new(BytesInput::new(vec![2])))
+            .unwrap();
+
+        let stored = JsonOnDiskCorpus::<BytesInput>::load_dir(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            stored.iter().map(|s| s.disabled).collect::<Vec<_>>(),
+            vec![false, true]
+        );
+        assert_eq!(
+            *stored[0].testcase.exec_time(),
+            Some(Duration::from_millis(42))
+        );
+        assert_eq!(*stored[1].testcase.exec_time(), None);
+    }
+
+    #[test]
+    fn store_metadata_keeps_metadata_added_later() {
+        let dir = env::temp_dir().join(format!("ftz-corpus-metadata-test-{}", std::process::id()));
+        let mut corpus = JsonOnDiskCorpus::new(&dir).unwrap();
+        let id = corpus
+            .add_disabled(Testcase::new(BytesInput::new(vec![1])))
+            .unwrap();
+        corpus
+            .get_from_all(id)
+            .unwrap()
+            .borrow_mut()
+            .add_metadata(CalibratedMetadata);
+        corpus.store_metadata(id).unwrap();
+
+        let stored = JsonOnDiskCorpus::<BytesInput>::load_dir(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(stored.len(), 1);
+        assert!(stored[0].disabled);
+        assert!(stored[0].testcase.has_metadata::<CalibratedMetadata>());
+    }
+}
@@ -0,0 +1,443 @@
+//! Coverage map shared with Zephyr, sized by asking Zephyr for its number of sancov guards, see [`CoverageLayout`].
+
+use std::{
+    borrow::Cow,
+    env, fs,
+    path::Path,
+    process::{Command, Stdio},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use libafl::{
+    executors::ExitKind,
+    observers::{HitcountsMapObserver, Observer, StdMapObserver},
+    Error, HasMetadata,
+};
+use libafl_bolts::{
+    shmem::{MmapShMem, MmapShMemProvider, ShMem as _, ShMemDescription, ShMemProvider as _},
+    Named,
+};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    directed::{call_graph, GuardDistances},
+    get_path,
+    stability::UnstableEdges,
+};
+
+/// Observer of the coverage map, one `u32` per sancov guard of which Zephyr only sets the lowest byte.
+pub type CoverageObserver = HitcountsMapObserver<StdMapObserver<'static, u8, false>>;
+
+/// Bytes of the coverage map per sancov guard.
+const BYTES_PER_GUARD: usize = 4;
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Run Zephyr once with `SHMEM_COVERAGE_PROBE_NAME` set, so it writes the number of its sancov guards into that shmem and exits right away, see `coverage.c`.
+///
+/// Returns the size of the coverage map Zephyr expects in `SHMEM_COVERAGE_SIZE`, which changes with every build.
+pub fn probe_coverage_map_size(zephyr_exec_path: &Path) -> Result<usize, Error> {
+    Ok(run_probe(zephyr_exec_path, &[])? as usize * BYTES_PER_GUARD)
+}
+
+/// Run the probe of [`probe_coverage_map_size`] with `COVERAGE_PC_TABLE_PATH` set, so Zephyr additionally writes the PC of each sancov guard to that file.
+///
+/// Requires Zephyr to be built with `-fsanitize-coverage=trace-pc-guard,pc-table`. Returns the PCs indexed by guard, see [`guard_index`].
+pub fn probe_pc_table(zephyr_exec_path: &Path) -> Result<Vec<u64>, Error> {
+    let table_path = env::temp_dir().join(format!("ftz-pc-table-{}.txt", std::process::id()));
+    let guards = run_probe(
+        zephyr_exec_path,
+        &[("COVERAGE_PC_TABLE_PATH", table_path.display().to_string())],
+    )?;
+    let table = fs::read_to_string(&table_path).map_err(|e| {
+        Error::os_error(
+            e,
+            "Zephyr did not write a PC table, make sure it is built with -fsanitize-coverage=trace-pc-guard,pc-table",
+        )
+    })?;
+    fs::remove_file(&table_path)?;
+
+    let pcs = parse_pc_table(&table)?;
+    if pcs.len() != guards as usize {
+        return Err(Error::illegal_state(format!(
+            "Zephyr reported {guards} coverage guards, but {} PCs",
+            pcs.len()
+        )));
+    }
+    Ok(pcs)
+}
+
+/// Parse the lines `<guard>: <pc>` written by `coverage.c`, the same format as its `sanitizer_cov.txt`.
+fn parse_pc_table(table: &str) -> Result<Vec<u64>, Error> {
+    table
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .enumerate()
+        .map(|(i, line)| {
+            let (guard, pc) = line
+                .split_once(": ")
+                .ok_or_else(|| Error::serialize(format!("Invalid PC table entry {line:?}")))?;
+            if guard.trim().parse::<usize>().ok() != Some(i) {
+                return Err(Error::serialize(format!(
+                    "PC table entry {line:?} is out of order"
+                )));
+            }
+            u64::from_str_radix(pc.trim().trim_start_matches("0x"), 16)
+                .map_err(|e| Error::serialize(format!("Invalid PC in {line:?}: {e}")))
+        })
+        .collect()
+}
+
+/// Source location of a sancov guard, resolved from the DWARF data of zephyr.exe.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub function: String,
+    pub file: String,
+    pub line: u32,
+}
+
+/// Source locations of all sancov guards of Zephyr, indexed by guard, see [`probe_pc_table`].
+pub fn symbolize_guards(zephyr_exec_path: &Path) -> Result<Vec<Option<Location>>, Error> {
+    let pcs = probe_pc_table(zephyr_exec_path)?;
+    symbolize(zephyr_exec_path, &pcs)
+}
+
+/// Resolve the PCs of the guards to source locations using `addr2line`, `None` for PCs without debug information.
+fn symbolize(zephyr_exec_path: &Path, pcs: &[u64]) -> Result<Vec<Option<Location>>, Error> {
+    let output = Command::new("addr2line")
+        .arg("-e")
+        .arg(zephyr_exec_path)
+        .args(["-f", "-C"])
+        .args(pcs.iter().map(|pc| format!("{pc:#x}")))
+        .output()
+        .map_err(|e| Error::os_error(e, "Could not run addr2line"))?;
+    if !output.status.success() {
+        return Err(Error::unknown(format!(
+            "addr2line failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        )));
+    }
+
+    // two lines per PC: the function, then file:line
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines = stdout.lines().collect::<Vec<_>>();
+    if lines.len() != 2 * pcs.len() {
+        return Err(Error::unknown(format!(
+            "addr2line resolved {} lines for {} PCs",
+            lines.len(),
+            pcs.len()
+        )));
+    }
+    Ok(lines
+        .chunks(2)
+        .map(|l| parse_location(l[0], l[1]))
+        .collect())
+}
+
+/// Parse the output of `addr2line -f` for a single PC.
+pub fn parse_location(function: &str, location: &str) -> Option<Location> {
+    let location = location.split(" (discriminator").next()?;
+    let (file, line) = location.rsplit_once(':')?;
+    let line = line.parse().ok().filter(|l| *l != 0)?;
+    (file != "??").then(|| Location {
+        function: function.to_string(),
+        file: file.to_string(),
+        line,
+    })
+}
+
+/// The sancov guard an entry of the coverage map belongs to.
+pub fn guard_index(entry: usize) -> usize {
+    entry / BYTES_PER_GUARD
+}
+
+fn run_probe(zephyr_exec_path: &Path, envs: &[(&str, String)]) -> Result<u32, Error> {
+    let probe_shmem = MmapShMemProvider::default().new_shmem(size_of::<u32>())?;
+    let mut child = Command::new(zephyr_exec_path)
+        .env(
+            "SHMEM_COVERAGE_PROBE_NAME",
+            get_path(&probe_shmem.description())?,
+        )
+        .envs(envs.iter().map(|(k, v)| (k, v)))
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| Error::unknown(format!("Could not start command: {e:?}")))?;
+
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if start.elapsed() > PROBE_TIMEOUT {
+            child.kill()?;
+            child.wait()?;
+            return Err(Error::unknown(
+                "Zephyr did not exit after reporting its coverage map size, make sure it is built with the coverage probe of coverage.c",
+            ));
+        }
+        sleep(Duration::from_millis(10));
+    };
+
+    let guards = u32::from_ne_bytes(probe_shmem[..size_of::<u32>()].try_into().unwrap());
+    if !status.success() || guards == 0 {
+        return Err(Error::unknown(format!(
+            "Zephyr did not report its coverage map size (exited with {status}), make sure it is built with sancov and the coverage probe of coverage.c"
+        )));
+    }
+    log::info!("Zephyr has {guards} coverage guards");
+    Ok(guards)
+}
+
+/// Which sancov guards drive the coverage feedback, matched against the source path and function of each guard.
+///
+/// Patterns may contain `*` for any number of characters and `?` for a single one. A guard is kept if it matches any pattern of `allow` (or `allow` is empty) and no pattern of `deny`. Guards without debug information can only be kept by an empty `allow`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CoverageFilter {
+    allow: Vec<String>,
+    deny: Vec<String>,
+}
+
+impl CoverageFilter {
+    /// Whether any guard may be masked, otherwise Zephyr does not need to be symbolized.
+    pub fn is_active(&self) -> bool {
+        !self.allow.is_empty() || !self.deny.is_empty()
+    }
+
+    fn keeps(&self, location: Option<&Location>) -> bool {
+        let matches = |patterns: &[String]| {
+            location.is_some_and(|l| {
+                patterns
+                    .iter()
+                    .any(|p| glob_match(p, &l.file) || glob_match(p, &l.function))
+            })
+        };
+        (self.allow.is_empty() || matches(&self.allow)) && !matches(&self.deny)
+    }
+
+    /// The guards excluded by the filter.
+    pub fn masked_guards(&self, locations: &[Option<Location>]) -> Vec<usize> {
+        locations
+            .iter()
+            .enumerate()
+            .filter(|(_, l)| !self.keeps(l.as_ref()))
+            .map(|(guard, _)| guard)
+            .collect()
+    }
+}
+
+/// Match `text` against a pattern with the wildcards `*` and `?`.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let (pattern, text) = (pattern.as_bytes(), text.as_bytes());
+    let (mut p, mut t) = (0, 0);
+    // position of the last `*` in the pattern and where in the text it started matching
+    let mut backtrack = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == b'?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, start)) => {
+                    p = star + 1;
+                    t = start + 1;
+                    backtrack = Some((star, start + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// Size of Zephyr's coverage map and the guards masked by the [`CoverageFilter`], probed once before launching the clients.
+///
+/// Also holds the [`UnstableEdges`] stored by previous campaigns, each client starts with, and the [`GuardDistances`] to the targets of a directed campaign.
+#[derive(Debug)]
+pub struct CoverageLayout {
+    map_size: usize,
+    masked_guards: Vec<usize>,
+    unstable_edges: UnstableEdges,
+    target_distances: Option<GuardDistances>,
+}
+
+impl CoverageLayout {
+    /// Zephyr is only symbolized if the filter is active or there are directed `targets`.
+    pub fn probe(
+        zephyr_exec_path: &Path,
+        filter: &CoverageFilter,
+        targets: &[String],
+    ) -> Result<Self, Error> {
+        if !filter.is_active() && targets.is_empty() {
+            let guards = probe_coverage_map_size(zephyr_exec_path)? / BYTES_PER_GUARD;
+            return Ok(Self {
+                map_size: guards * BYTES_PER_GUARD,
+                masked_guards: vec![],
+                unstable_edges: UnstableEdges::new(guards),
+                target_distances: None,
+            });
+        }
+
+        let locations = symbolize_guards(zephyr_exec_path)?;
+        let masked_guards = if filter.is_active() {
+            let masked_guards = filter.masked_guards(&locations);
+            if masked_guards.len() == locations.len() {
+                return Err(Error::illegal_argument(
+                    "The coverage filter masks all coverage guards",
+                ));
+            }
+            log::info!(
+                "Masking {} of {} coverage guards",
+                masked_guards.len(),
+                locations.len()
+            );
+            masked_guards
+        } else {
+            vec![]
+        };
+
+        let target_distances = if targets.is_empty() {
+            None
+        } else {
+            let distances =
+                GuardDistances::compute(&locations, &call_graph(zephyr_exec_path)?, targets)?;
+            log::info!(
+                "Targets are reachable from {} of {} coverage guards",
+                distances.reachable(),
+                distances.guards()
+            );
+            Some(distances)
+        };
+
+        Ok(Self {
+            map_size: locations.len() * BYTES_PER_GUARD,
+            masked_guards,
+            unstable_edges: UnstableEdges::new(locations.len()),
+            target_distances,
+        })
+    }
+
+    pub fn map_size(&self) -> usize {
+        self.map_size
+    }
+
+    /// Guards excluded from the coverage feedback by the filter, see [`CoverageMaskObserver`].
+    pub fn masked_guards(&self) -> &[usize] {
+        &self.masked_guards
+    }
+
+    pub fn unstable_edges(&self) -> &UnstableEdges {
+        &self.unstable_edges
+    }
+
+    pub fn unstable_edges_mut(&mut self) -> &mut UnstableEdges {
+        &mut self.unstable_edges
+    }
+
+    /// Distances to the targets of a directed campaign, see [`crate::config::DirectedConfig`].
+    pub fn target_distances(&self) -> Option<&GuardDistances> {
+        self.target_distances.as_ref()
+    }
+}
+
+/// Zeroes the guards masked by the filter and the [`UnstableEdges`] in the metadata of the state in the coverage map after each execution, before the feedbacks see it.
+///
+/// Maps the coverage shmem a second time, so it does not alias the memory of the [`CoverageObserver`].
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CoverageMaskObserver {
+    #[serde(skip)]
+    shmem: Option<MmapShMem>,
+    masked_guards: Vec<usize>,
+}
+
+impl CoverageMaskObserver {
+    pub fn new(
+        cov_shmem_description: &ShMemDescription,
+        coverage: &CoverageLayout,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            shmem: Some(
+                MmapShMemProvider::default().shmem_from_description(*cov_shmem_description)?,
+            ),
+            masked_guards: coverage.masked_guards().to_vec(),
+        })
+    }
+}
+
+impl<I, S: HasMetadata> Observer<I, S> for CoverageMaskObserver {
+    fn post_exec(&mut self, state: &mut S, _input: &I, _exit_kind: &ExitKind) -> Result<(), Error> {
+        let Some(shmem) = self.shmem.as_mut() else {
+            return Ok(());
+        };
+        let unstable = state
+            .metadata::<UnstableEdges>()
+            .map(UnstableEdges::unstable_guards)
+            .unwrap_or_default();
+        for &guard in self.masked_guards.iter().chain(unstable) {
+            shmem[guard * BYTES_PER_GUARD..(guard + 1) * BYTES_PER_GUARD].fill(0);
+        }
+        Ok(())
+    }
+}
+
+impl Named for CoverageMaskObserver {
+    fn name(&self) -> &Cow<'static, str> {
+        &Cow::Borrowed("coverage-mask-observer")
+    }
+}
+
+/// Observe the coverage map in `shmem`, which needs to outlive the observer.
+pub fn coverage_observer(shmem: &mut MmapShMem) -> CoverageObserver {
+    let len = shmem.len();
+    HitcountsMapObserver::new(unsafe {
+        StdMapObserver::from_mut_ptr("coverage-observer", shmem.as_mut_ptr(), len)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{glob_match, guard_index, parse_location, parse_pc_table, CoverageFilter};
+
+    #[test]
+    fn pc_table() {
+        let table = "0: 0x8049a10\n1: 0x8049a2c\n2: 0x804a001\n";
+        assert_eq!(
+            parse_pc_table(table).unwrap(),
+            vec![0x8049a10, 0x8049a2c, 0x804a001]
+        );
+        assert!(parse_pc_table("1: 0x8049a10\n").is_err());
+        assert_eq!(guard_index(9), 2);
+    }
+
+    #[test]
+    fn coverage_filter() {
+        assert!(glob_match("*/subsys/net/*", "/zephyr/subsys/net/ip/tcp.c"));
+        assert!(glob_match("tcp_*_in?", "tcp_data_in2"));
+        assert!(!glob_match("*/subsys/net/*", "/zephyr/kernel/sched.c"));
+        assert!(!glob_match("tcp_*", "net_tcp_in"));
+
+        let locations = [
+            parse_location("tcp_in", "/zephyr/subsys/net/ip/tcp.c:2510"),
+            parse_location("net_if_tx", "/zephyr/subsys/net/ip/net_if.c:300"),
+            parse_location("z_timer_expiration", "/zephyr/kernel/timeout.c:80"),
+            parse_location("??", "??:0"),
+        ];
+        let filter: CoverageFilter = toml::from_str(
+            r#"
+            allow = ["*/subsys/net/*"]
+            deny = ["net_if_*"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(filter.masked_guards(&locations), vec![1, 2, 3]);
+        assert!(CoverageFilter::default()
+            .masked_guards(&locations)
+            .is_empty());
+    }
+}
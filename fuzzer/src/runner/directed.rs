@@ -0,0 +1,523 @@
+//! AFLGo-style directed fuzzing towards functions or lines of Zephyr, see [`GuardDistances`] and [`DirectedScheduler`].
+
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    fmt::Write as _,
+    fs,
+    path::Path,
+    process::Command,
+    time::{Duration, Instant},
+};
+
+use libafl::{
+    corpus::{Corpus, CorpusId, Testcase},
+    executors::ExitKind,
+    feedbacks::{Feedback, StateInitializer},
+    observers::MapObserver as _,
+    schedulers::{RemovableScheduler, Scheduler},
+    state::{HasCorpus, HasRand},
+    Error, HasMetadata, SerdeAny,
+};
+use libafl_bolts::{
+    tuples::{Handle, MatchName, MatchNameRef},
+    Named,
+};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    coverage::{glob_match, guard_index, CoverageObserver, Location},
+    scheduler::weighted_choice,
+};
+
+/// Distance added per call between the function of a guard and a target function, like AFLGo's weighting of call graph against CFG distances.
+const CALL_DISTANCE: u32 = 10;
+
+/// Functions of zephyr.exe and the functions each of them calls directly.
+pub type CallGraph = BTreeMap<String, BTreeSet<String>>;
+
+/// Build the call graph of zephyr.exe from its disassembly, see [`parse_call_graph`].
+pub fn call_graph(zephyr_exec_path: &Path) -> Result<CallGraph, Error> {
+    let output = Command::new("objdump")
+        .args(["-d", "--no-show-raw-insn"])
+        .arg(zephyr_exec_path)
+        .output()
+        .map_err(|e| Error::os_error(e, "Could not run objdump"))?;
+    if !output.status.success() {
+        return Err(Error::unknown(format!(
+            "objdump failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        )));
+    }
+    Ok(parse_call_graph(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Parse the direct calls and tail calls from the output of `objdump -d`. Calls through function pointers are not resolved.
+pub fn parse_call_graph(disassembly: &str) -> CallGraph {
+    // compiler generated clones like `tcp_in.constprop.0` or `tcp_in.cold` belong to the original function
+    let symbol = |name: &str| name.split(['.', '@']).next().unwrap_or(name).to_string();
+
+    let mut graph = CallGraph::new();
+    let mut current = None;
+    for line in disassembly.lines() {
+        if let Some(header) = line.strip_suffix(">:") {
+            current = header.split_once(" <").map(|(_, name)| symbol(name));
+            if let Some(function) = &current {
+                graph.entry(function.clone()).or_default();
+            }
+            continue;
+        }
+        let Some(function) = &current else {
+            continue;
+        };
+        // address, then the instruction with its operands
+        let Some(instruction) = line.split('\t').nth(1) else {
+            continue;
+        };
+        let is_call = instruction.starts_with("call");
+        // indirect calls only name the symbol holding the pointer, if any
+        if (!is_call && !instruction.starts_with("jmp")) || instruction.contains('*') {
+            continue;
+        }
+        let Some(target) = instruction
+            .split_once('<')
+            .and_then(|(_, t)| t.strip_suffix('>'))
+        else {
+            continue;
+        };
+        // jumps into the middle of a function are branches within the current one
+        if !is_call && target.contains('+') {
+            continue;
+        }
+        let callee = symbol(target.split('+').next().unwrap_or(target));
+        if callee != *function {
+            graph.get_mut(function).unwrap().insert(callee);
+        }
+    }
+    graph
+}
+
+/// Distance of each sancov guard to the nearest target, `None` if no target is reachable from it.
+///
+/// Targets are function names or `<file>:<line>`, both may contain the wildcards of the coverage filter. Guards of a target have distance 0, all others [`CALL_DISTANCE`] per call from their function to the function of a target plus one.
+#[derive(Debug, Clone, Default)]
+pub struct GuardDistances {
+    distances: Vec<Option<u32>>,
+}
+
+impl GuardDistances {
+    /// Resolve the targets with the source locations of the guards and the call graph of zephyr.exe.
+    pub fn compute(
+        locations: &[Option<Location>],
+        graph: &CallGraph,
+        targets: &[String],
+    ) -> Result<Self, Error> {
+        let is_target = |location: &Location| {
+            targets.iter().any(|target| {
+                match target
+                    .rsplit_once(':')
+                    .and_then(|(file, line)| Some((file, line.parse::<u32>().ok()?)))
+                {
+                    Some((file, line)) => glob_match(file, &location.file) && line == location.line,
+                    None => glob_match(target, &location.function),
+                }
+            })
+        };
+
+        let target_guards = locations
+            .iter()
+            .map(|l| l.as_ref().is_some_and(is_target))
+            .collect::<Vec<_>>();
+        let target_functions = locations
+            .iter()
+            .zip(&target_guards)
+            .filter(|(_, is_target)| **is_target)
+            .filter_map(|(l, _)| l.as_ref().map(|l| l.function.clone()))
+            .collect::<BTreeSet<_>>();
+        if target_functions.is_empty() {
+            return Err(Error::illegal_argument(format!(
+                "None of the directed targets {targets:?} matches a coverage guard"
+            )));
+        }
+
+        let calls = call_distances(graph, &target_functions);
+        let distances = locations
+            .iter()
+            .zip(target_guards)
+            .map(|(location, is_target)| {
+                if is_target {
+                    return Some(0);
+                }
+                let calls = calls.get(&location.as_ref()?.function)?;
+                Some(CALL_DISTANCE * calls + 1)
+            })
+            .collect();
+        Ok(Self { distances })
+    }
+
+    pub fn guards(&self) -> usize {
+        self.distances.len()
+    }
+
+    /// Number of guards from which a target is reachable.
+    pub fn reachable(&self) -> usize {
+        self.distances.iter().flatten().count()
+    }
+
+    /// AFLGo's seed distance: the mean distance of the covered guards a target is reachable from, `None` if there are none.
+    pub fn input_distance(&self, coverage_map: &[u8]) -> Option<f64> {
+        let covered = coverage_map
+            .iter()
+            .enumerate()
+            .filter(|(_, hits)| **hits != 0)
+            .map(|(entry, _)| guard_index(entry))
+            .collect::<BTreeSet<_>>();
+        let distances = covered
+            .into_iter()
+            .filter_map(|guard| self.distances.get(guard).copied().flatten())
+            .collect::<Vec<_>>();
+        (!distances.is_empty())
+            .then(|| distances.iter().map(|&d| d as f64).sum::<f64>() / distances.len() as f64)
+    }
+
+    /// Write the distance map, one `<guard> <distance>` line per guard a target is reachable from, after a header with the number of guards of the Zephyr build.
+    pub fn store<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let mut content = format!("# guards: {}\n", self.guards());
+        for (guard, distance) in self.distances.iter().enumerate() {
+            if let Some(distance) = distance {
+                writeln!(content, "{guard} {distance}").unwrap();
+            }
+        }
+        fs::write(path.as_ref(), content).map_err(|e| {
+            Error::os_error(
+                e,
+                format!("Could not write distance map {:?}", path.as_ref()),
+            )
+        })
+    }
+}
+
+/// Number of calls needed to get from each function to one of the targets, by a breadth-first search through the callers.
+fn call_distances(graph: &CallGraph, targets: &BTreeSet<String>) -> BTreeMap<String, u32> {
+    let mut callers = BTreeMap::<&str, Vec<&str>>::new();
+    for (caller, callees) in graph {
+        for callee in callees {
+            callers
+                .entry(callee.as_str())
+                .or_default()
+                .push(caller.as_str());
+        }
+    }
+
+    let mut distances = targets
+        .iter()
+        .map(|t| (t.clone(), 0))
+        .collect::<BTreeMap<_, _>>();
+    let mut queue = targets.iter().map(String::as_str).collect::<VecDeque<_>>();
+    while let Some(function) = queue.pop_front() {
+        let distance = distances[function];
+        for &caller in callers.get(function).into_iter().flatten() {
+            if !distances.contains_key(caller) {
+                distances.insert(caller.to_string(), distance + 1);
+                queue.push_back(caller);
+            }
+        }
+    }
+    distances
+}
+
+/// Distance of a corpus entry to the targets, see [`GuardDistances::input_distance`].
+#[derive(Debug, Clone, Copy, SerdeAny, Serialize, Deserialize)]
+pub struct DistanceMetadata {
+    pub distance: f64,
+}
+
+/// Adds the [`DistanceMetadata`] of new corpus entries, never interesting on its own.
+pub struct DistanceFeedback {
+    cov_observer: Handle<CoverageObserver>,
+    distances: Option<GuardDistances>,
+}
+
+impl DistanceFeedback {
+    /// Does nothing without distances, i.e. if the campaign is not directed.
+    pub fn new(cov_observer: Handle<CoverageObserver>, distances: Option<GuardDistances>) -> Self {
+        Self {
+            cov_observer,
+            distances,
+        }
+    }
+}
+
+impl<S> StateInitializer<S> for DistanceFeedback {}
+
+impl<EM, I, OT, S> Feedback<EM, I, OT, S> for DistanceFeedback
+where
+    OT: MatchNameRef,
+{
+    fn append_metadata(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        observers: &OT,
+        testcase: &mut Testcase<I>,
+    ) -> Result<(), Error> {
+        let Some(distances) = &self.distances else {
+            return Ok(());
+        };
+        let observer = observers
+            .get(&self.cov_observer)
+            .ok_or(Error::illegal_argument(
+            "Could not retrieve CoverageObserver, make sure you pass it to the executor in the OT.",
+        ))?;
+        if let Some(distance) = distances.input_distance(&observer.to_vec()) {
+            testcase.add_metadata(DistanceMetadata { distance });
+        }
+        Ok(())
+    }
+
+    fn is_interesting(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &I,
+        _observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error> {
+        Ok(false)
+    }
+}
+
+impl Named for DistanceFeedback {
+    fn name(&self) -> &Cow<'static, str> {
+        &Cow::Borrowed("DistanceFeedback")
+    }
+}
+
+#[derive(Debug, Default, SerdeAny, Serialize, Deserialize)]
+pub struct DirectedSchedulerMetadata {
+    distances: BTreeMap<CorpusId, f64>,
+}
+
+/// AFLGo's annealing-based power schedule, applied to picking the next corpus entry: entries are weighted by how close they get to the targets, more so the longer the campaign runs.
+///
+/// The temperature drops to 5% after `exploitation_time`, until then distant entries still get picked for exploration. Entries without a distance count as the most distant ones. Falls back to the inner scheduler if it is not active or no entry has a distance yet.
+pub struct DirectedScheduler<CS> {
+    inner: CS,
+    active: bool,
+    exploitation_time: Duration,
+    start: Instant,
+}
+
+impl<CS> DirectedScheduler<CS> {
+    pub fn new<S: HasMetadata>(
+        state: &mut S,
+        inner: CS,
+        active: bool,
+        exploitation_time: Duration,
+    ) -> Self {
+        if !state.has_metadata::<DirectedSchedulerMetadata>() {
+            state.add_metadata(DirectedSchedulerMetadata::default());
+        }
+        Self {
+            inner,
+            active,
+            exploitation_time,
+            start: Instant::now(),
+        }
+    }
+
+    fn record<I, S>(state: &mut S, id: CorpusId) -> Result<(), Error>
+    where
+        S: HasCorpus<I> + HasMetadata,
+    {
+        let distance = state
+            .corpus()
+            .get(id)?
+            .borrow()
+            .metadata::<DistanceMetadata>()
+            .ok()
+            .map(|m| m.distance);
+        let metadata = state.metadata_mut::<DirectedSchedulerMetadata>()?;
+        match distance {
+            Some(distance) => {
+                if metadata.distances.values().all(|&d| distance < d) {
+                    log::info!(
+                        "Corpus entry {id} is the closest to the targets so far: {distance:.2}"
+                    );
+                }
+                metadata.distances.insert(id, distance);
+            }
+            None => {
+                metadata.distances.remove(&id);
+            }
+        }
+        Ok(())
+    }
+
+    /// AFLGo's power factor between 1/32 and 32 for an entry with a distance normalized to `0..=1`.
+    fn weight(normalized_distance: f64, temperature: f64) -> f64 {
+        let p = (1. - normalized_distance) * (1. - temperature) + 0.5 * temperature;
+        2f64.powf(10. * (p - 0.5))
+    }
+
+    fn temperature(&self) -> f64 {
+        let progress = self.start.elapsed().as_secs_f64() / self.exploitation_time.as_secs_f64();
+        20f64.powf(-progress)
+    }
+}
+
+impl<CS, I, S> Scheduler<I, S> for DirectedScheduler<CS>
+where
+    CS: Scheduler<I, S>,
+    S: HasCorpus<I> + HasMetadata + HasRand,
+{
+    fn on_add(&mut self, state: &mut S, id: CorpusId) -> Result<(), Error> {
+        Self::record(state, id)?;
+        self.inner.on_add(state, id)
+    }
+
+    fn on_evaluation<OT>(&mut self, state: &mut S, input: &I, observers: &OT) -> Result<(), Error>
+    where
+        OT: MatchName,
+    {
+        self.inner.on_evaluation(state, input, observers)
+    }
+
+    fn next(&mut self, state: &mut S) -> Result<CorpusId, Error> {
+        let distances = &state.metadata::<DirectedSchedulerMetadata>()?.distances;
+        if !self.active || distances.is_empty() {
+            return self.inner.next(state);
+        }
+
+        let min = distances.values().copied().fold(f64::INFINITY, f64::min);
+        let max = distances
+            .values()
+            .copied()
+            .fold(f64::NEG_INFINITY, f64::max);
+        let temperature = self.temperature();
+        let candidates = state
+            .corpus()
+            .ids()
+            .map(|id| {
+                let normalized = match distances.get(&id) {
+                    Some(d) if max > min => (d - min) / (max - min),
+                    Some(_) => 0.,
+                    None => 1.,
+                };
+                (id, Self::weight(normalized, temperature))
+            })
+            .collect::<Vec<_>>();
+
+        let Some(id) = weighted_choice(state.rand_mut(), candidates) else {
+            return self.inner.next(state);
+        };
+        self.set_current_scheduled(state, Some(id))?;
+        Ok(id)
+    }
+
+    fn set_current_scheduled(
+        &mut self,
+        state: &mut S,
+        next_id: Option<CorpusId>,
+    ) -> Result<(), Error> {
+        self.inner.set_current_scheduled(state, next_id)
+    }
+}
+
+impl<CS, I, S> RemovableScheduler<I, S> for DirectedScheduler<CS>
+where
+    CS: RemovableScheduler<I, S>,
+    S: HasCorpus<I> + HasMetadata + HasRand,
+{
+    fn on_remove(
+        &mut self,
+        state: &mut S,
+        id: CorpusId,
+        testcase: &Option<Testcase<I>>,
+    ) -> Result<(), Error> {
+        state
+            .metadata_mut::<DirectedSchedulerMetadata>()?
+            .distances
+            .remove(&id);
+        self.inner.on_remove(state, id, testcase)
+    }
+
+    /// The replacing testcase may cover different guards, e.g. after minimizing it.
+    fn on_replace(&mut self, state: &mut S, id: CorpusId, prev: &Testcase<I>) -> Result<(), Error> {
+        Self::record(state, id)?;
+        self.inner.on_replace(state, id, prev)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::runner::coverage::parse_location;
+
+    use super::{parse_call_graph, DirectedScheduler, GuardDistances};
+
+    const DISASSEMBLY: &str = "
+0000000000401000 <tcp_in>:
+  401000:\tpush   %rbp
+  401004:\tcall   401100 <tcp_options_check.constprop.0>
+  401009:\tjmp    401020 <tcp_in+0x20>
+  40100e:\tcall   *0x2fe2(%rip)        # 404138 <k_sleep>
+
+0000000000401100 <tcp_options_check.constprop.0>:
+  401100:\tret
+
+0000000000401200 <net_tcp_input>:
+  401200:\tjmp    401000 <tcp_in>
+
+0000000000401300 <k_sleep>:
+  401300:\tret
+";
+
+    #[test]
+    fn distances_through_call_graph() {
+        let graph = parse_call_graph(DISASSEMBLY);
+        assert_eq!(
+            graph["tcp_in"].iter().collect::<Vec<_>>(),
+            ["tcp_options_check"]
+        );
+        assert_eq!(
+            graph["net_tcp_input"].iter().collect::<Vec<_>>(),
+            ["tcp_in"]
+        );
+        assert!(graph["k_sleep"].is_empty());
+
+        let locations = [
+            ("net_tcp_input", "/zephyr/subsys/net/ip/tcp.c:100"),
+            ("tcp_in", "/zephyr/subsys/net/ip/tcp.c:200"),
+            ("tcp_options_check", "/zephyr/subsys/net/ip/tcp.c:300"),
+            ("tcp_options_check", "/zephyr/subsys/net/ip/tcp.c:301"),
+            ("k_sleep", "/zephyr/kernel/sched.c:120"),
+            ("??", "??:0"),
+        ]
+        .map(|(f, l)| parse_location(f, l));
+        let distances =
+            GuardDistances::compute(&locations, &graph, &["*/tcp.c:301".to_string()]).unwrap();
+        assert_eq!(
+            distances.distances,
+            [Some(21), Some(11), Some(1), Some(0), None, None]
+        );
+        assert_eq!(distances.reachable(), 4);
+
+        // one byte per entry of the coverage map, four entries per guard
+        let mut map = vec![0; 4 * locations.len()];
+        map[4] = 1;
+        map[12] = 1;
+        map[16] = 1;
+        assert_eq!(distances.input_distance(&map), Some(5.5));
+        assert_eq!(distances.input_distance(&[0; 24]), None);
+
+        assert!(GuardDistances::compute(&locations, &graph, &["tcp_out".to_string()]).is_err());
+    }
+
+    #[test]
+    fn closer_entries_are_favoured_when_cold() {
+        let weight = DirectedScheduler::<()>::weight;
+        assert_eq!(weight(0., 1.), weight(1., 1.));
+        assert_eq!(weight(0., 0.), 32.);
+        assert_eq!(weight(1., 0.), 1. / 32.);
+    }
+}
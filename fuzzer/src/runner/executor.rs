@@ -5,16 +5,17 @@ use std::{
     marker::PhantomData,
     os::unix::process::ExitStatusExt as _,
     path::PathBuf,
-    process::{Command, Stdio},
+    process::{Child, Command, Stdio},
+    sync::{Arc, Mutex},
     thread::sleep,
-    time::Instant,
+    time::{Duration, Instant},
 };
 
 use libafl::{
     executors::{Executor, ExitKind, HasObservers},
     observers::ObserversTuple,
     state::HasExecutions,
-    Error,
+    Error, HasMetadata,
 };
 use libafl_bolts::{
     shmem::ShMemDescription,
@@ -24,24 +25,93 @@ use libafl_bolts::{
 use crate::{
     direction::Source,
     layers::data_link::parse_eth,
-    runner::{get_path, INTER_SEND_WAIT},
+    runner::{
+        client::exchange_message, get_path, network::OversizeDropsMetadata, NetworkProfile, Timing,
+        LIVENESS_PROBE_MESSAGE, LIVENESS_PROBE_TIMEOUT,
+    },
 };
 
-use crate::smoltcp::shmem_net_device::ShmemNetworkDevice;
+use crate::smoltcp::{
+    shmem_net_device::ShmemNetworkDevice, smoltcp_shmem_net_device::SmoltcpShmemNetworkDevice,
+};
 
 use super::{
     input::{ZephyrInput, ZephyrInputPart},
-    observer::packet::PacketObserver,
+    observer::{
+        cmplog::CmpLogObserver, liveness::LivenessObserver, net_stats::NetStatsObserver,
+        packet::PacketObserver,
+    },
+    pool::{PrewarmedZephyr, ZephyrPool},
 };
 
+/// How to start Zephyr, shared by the [`ZepyhrExecutor`] and its [`ZephyrPool`].
+#[derive(Debug, Clone)]
+pub struct ZephyrCommand {
+    exec_path: PathBuf,
+    out_path: Option<PathBuf>,
+    inherit_output: bool,
+    rt_ratio: f64,
+}
+
+impl ZephyrCommand {
+    pub fn spawn(&self, envs: &[(String, String)]) -> Result<Child, Error> {
+        let (stdout, stderr) = self
+            .out_path
+            .as_ref()
+            .map(|path| {
+                let mut file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .expect("Failed to open file");
+                writeln!(file, "----------------------------------------").unwrap();
+                (
+                    Stdio::from(file.try_clone().expect("Could not clone zephyr outfile")),
+                    Stdio::from(file),
+                )
+            })
+            .unwrap_or_else(|| {
+                if self.inherit_output {
+                    (Stdio::inherit(), Stdio::inherit())
+                } else {
+                    (Stdio::null(), Stdio::null())
+                }
+            });
+
+        Command::new(self.exec_path.clone())
+            .stdout(stdout)
+            .stderr(stderr)
+            .envs(envs.iter().cloned())
+            .arg(format!("--rt-ratio={}", self.rt_ratio))
+            .spawn()
+            .map_err(|e| Error::unknown(format!("Could not start command: {e:?}")))
+    }
+}
+
+/// A shmem passed to Zephyr in `<prefix>_SIZE` and `<prefix>_NAME`, apart from the one of the network device.
+#[derive(Debug, Clone)]
+pub struct ZephyrShMem {
+    pub prefix: String,
+    pub description: ShMemDescription,
+    /// Whether what Zephyr writes while booting is part of the execution, e.g. for the baseline of the net stats.
+    pub keep_boot: bool,
+}
+
 pub struct ZepyhrExecutor<'a, S, OT, II> {
     observers: &'a mut OT,
     packet_observer: Handle<PacketObserver>,
+    liveness_observer: Option<Handle<LivenessObserver>>,
+    net_stats_observer: Option<Handle<NetStatsObserver>>,
     device: ShmemNetworkDevice,
     envs: Vec<(String, String)>,
-    zephyr_exec_path: PathBuf,
-    zephyr_out_path: Option<PathBuf>,
-    zephyr_rt_ratio: f64,
+    shmems: Vec<ZephyrShMem>,
+    command: ZephyrCommand,
+    timing: Timing,
+    network: NetworkProfile,
+    network_buf_size: usize,
+    id: usize,
+    pool_size: usize,
+    pool: Option<ZephyrPool>,
     phantom: PhantomData<(S, II)>,
 }
 
@@ -63,45 +133,181 @@ impl<'a, S, OT, II> ZepyhrExecutor<'a, S, OT, II> {
         let envs = ([
             (&"SHMEM_ETH_INTERFACE_SIZE", &net_shmem_desc.size),
             (&"SHMEM_ETH_INTERFACE_NAME", &get_path(&net_shmem_desc)?),
-            (&"SHMEM_COVERAGE_SIZE", &cov_shmem_desc.size),
-            (&"SHMEM_COVERAGE_NAME", &get_path(cov_shmem_desc)?),
-        ] as [(&dyn ToString, &dyn ToString); 4])
+        ] as [(&dyn ToString, &dyn ToString); 2])
             .iter()
             .map(|(k, v)| (k.to_string(), v.to_string()))
             .collect();
 
-        Ok(Self {
+        let mut executor = Self {
             observers,
             packet_observer,
+            liveness_observer: None,
+            net_stats_observer: None,
             device,
             envs,
-            zephyr_exec_path,
-            zephyr_out_path,
-            zephyr_rt_ratio,
+            shmems: vec![],
+            command: ZephyrCommand {
+                exec_path: zephyr_exec_path,
+                out_path: zephyr_out_path,
+                inherit_output: false,
+                rt_ratio: zephyr_rt_ratio,
+            },
+            timing: Timing::default(),
+            network: NetworkProfile::default(),
+            network_buf_size,
+            id,
+            pool_size: 0,
+            pool: None,
             phantom: PhantomData,
-        })
+        };
+        executor.push_shmem_env("SHMEM_COVERAGE", cov_shmem_desc, false)?;
+        Ok(executor)
     }
-}
 
-impl<EM, Z, S, OT, I, II> Executor<EM, I, S, Z> for ZepyhrExecutor<'_, S, OT, II>
-where
-    S: HasExecutions,
-    OT: Debug + MatchName + MatchNameRef + ObserversTuple<I, S>,
-    I: ZephyrInput<II>,
-    II: ZephyrInputPart,
-    Vec<u8>: From<II>,
-{
-    fn run_target(
+    fn push_shmem_env(
         &mut self,
-        _fuzzer: &mut Z,
-        state: &mut S,
-        _mgr: &mut EM,
-        input: &I,
-    ) -> Result<ExitKind, Error> {
-        log::debug!("Starting input run #{} on target", state.executions());
-        *state.executions_mut() += 1;
+        prefix: &str,
+        shmem_desc: &ShMemDescription,
+        keep_boot: bool,
+    ) -> Result<(), Error> {
+        self.envs
+            .push((format!("{prefix}_SIZE"), shmem_desc.size.to_string()));
+        self.envs
+            .push((format!("{prefix}_NAME"), get_path(shmem_desc)?.to_string()));
+        self.shmems.push(ZephyrShMem {
+            prefix: prefix.to_string(),
+            description: *shmem_desc,
+            keep_boot,
+        });
+        Ok(())
+    }
 
-        self.observers.pre_exec_child_all(state, input)?;
+    /// Run a liveness probe after each input and store its result in the [`LivenessObserver`].
+    pub fn with_liveness_probe(mut self, liveness_observer: Handle<LivenessObserver>) -> Self {
+        self.liveness_observer = Some(liveness_observer);
+        self
+    }
+
+    /// Have Zephyr export its network stats into the shmem of the [`NetStatsObserver`].
+    ///
+    /// The observer's baseline is taken once Zephyr finished booting.
+    pub fn with_net_stats(
+        mut self,
+        net_stats_observer: Handle<NetStatsObserver>,
+    ) -> Result<Self, Error>
+    where
+        OT: MatchNameRef,
+    {
+        let stats_shmem_desc = self
+            .observers
+            .get(&net_stats_observer)
+            .ok_or(Error::illegal_argument(
+                "Could not retrieve NetStatsObserver, make sure you pass it to the executor in the OT.",
+            ))?
+            .shmem_description()?;
+        self.push_shmem_env("SHMEM_STATS", &stats_shmem_desc, true)?;
+        self.net_stats_observer = Some(net_stats_observer);
+        Ok(self)
+    }
+
+    /// Pass the state annotation map to Zephyr, which marks the values passed to `FTZ_STATE` in it.
+    pub fn with_state_annotations(
+        mut self,
+        state_annotation_shmem_desc: &ShMemDescription,
+    ) -> Result<Self, Error> {
+        self.push_shmem_env("SHMEM_STATE", state_annotation_shmem_desc, false)?;
+        Ok(self)
+    }
+
+    /// Have Zephyr log the operands of its comparisons into the shmem of the [`CmpLogObserver`].
+    pub fn with_cmplog(mut self, cmplog_observer: Handle<CmpLogObserver>) -> Result<Self, Error>
+    where
+        OT: MatchNameRef,
+    {
+        let cmplog_shmem_desc = self
+            .observers
+            .get(&cmplog_observer)
+            .ok_or(Error::illegal_argument(
+                "Could not retrieve CmpLogObserver, make sure you pass it to the executor in the OT.",
+            ))?
+            .shmem_description()?;
+        self.push_shmem_env("SHMEM_CMPLOG", &cmplog_shmem_desc, false)?;
+        Ok(self)
+    }
+
+    /// Stream Zephyr's output to the terminal instead of discarding it, unless it is redirected to a file.
+    pub fn with_inherited_zephyr_output(mut self) -> Self {
+        self.command.inherit_output = true;
+        self
+    }
+
+    /// Keep `size` Zephyr instances booting in the background and run each input on one that already finished, see [`ZephyrPool`].
+    ///
+    /// Needs to be called after the other builders, since the instances are started with the shmems configured at the first execution. Coverage of booting Zephyr is not part of the executions.
+    pub fn with_prewarmed_pool(mut self, size: usize) -> Self {
+        self.pool_size = size;
+        self
+    }
+
+    /// Wait for Zephyr as configured instead of using [`Timing::default`].
+    pub fn with_timing(mut self, timing: Timing) -> Self {
+        self.timing = timing;
+        self
+    }
+
+    /// Talk to Zephyr using the addresses and MTU of `network` instead of [`NetworkProfile::default`].
+    pub fn with_network_profile(mut self, network: NetworkProfile) -> Result<Self, Error> {
+        self.device.set_mtu(network.mtu())?;
+        self.network = network;
+        Ok(self)
+    }
+
+    /// Perform a clean handshake and echo exchange on a new connection.
+    ///
+    /// Returns whether the echo was received, along with the packets of the probe.
+    fn probe_liveness(
+        &self,
+        device: &ShmemNetworkDevice,
+        child: &mut Child,
+    ) -> Result<(bool, Vec<(Duration, Source<Vec<u8>>)>), Error> {
+        let start_time = Instant::now();
+        let packets = Arc::new(Mutex::new(Vec::new()));
+        let packets_clone = packets.clone();
+
+        let mut device = SmoltcpShmemNetworkDevice::new(device.clone(), move |packet| {
+            packets_clone
+                .lock()
+                .unwrap()
+                .push((start_time.elapsed(), Source::from(packet)));
+        });
+
+        let echoed = exchange_message(
+            &mut device,
+            child,
+            LIVENESS_PROBE_MESSAGE,
+            LIVENESS_PROBE_TIMEOUT,
+            &self.network,
+            self.network.probe_port(),
+        )?;
+        let alive = echoed.starts_with(LIVENESS_PROBE_MESSAGE);
+        log::debug!("Liveness probe finished, Zephyr alive: {}", alive);
+
+        let packets = packets.lock().unwrap().drain(..).collect();
+        Ok((alive, packets))
+    }
+
+    /// Send the packets to Zephyr, either a prewarmed instance or a new one started into `cold_child`, and return how the execution ended.
+    ///
+    /// It crashed if Zephyr was killed by a signal or wrote an invalid frame into the ring towards the fuzzer. Zephyr is left running, the caller needs to stop it on every path.
+    fn execute_packets(
+        &mut self,
+        prewarmed: Option<&mut PrewarmedZephyr>,
+        cold_child: &mut Option<Child>,
+        packets: Vec<Vec<u8>>,
+    ) -> Result<ExitKind, Error>
+    where
+        OT: MatchNameRef,
+    {
         let packet_observer = self
             .observers
             .get_mut(&self.packet_observer)
@@ -109,72 +315,166 @@ where
             "Could not retrieve PacketObserver, make sure you pass it to the executor in the OT.",
         ))?;
 
-        log::debug!("Preparing Zephyr");
-
-        self.device.reset();
-
-        let (stdout, stderr) = self
-            .zephyr_out_path
-            .as_ref()
-            .map(|path| {
-                let mut file = OpenOptions::new()
-                    .create(true)
-                    .append(true)
-                    .open(path)
-                    .expect("Failed to open file");
-                writeln!(file, "----------------------------------------").unwrap();
-                (
-                    Stdio::from(file.try_clone().expect("Could not clone zephyr outfile")),
-                    Stdio::from(file),
-                )
-            })
-            .unwrap_or((Stdio::null(), Stdio::null()));
-
-        let mut child = Command::new(self.zephyr_exec_path.clone())
-            .stdout(stdout)
-            .stderr(stderr)
-            .envs(self.envs.clone())
-            .arg(format!("--rt-ratio={}", self.zephyr_rt_ratio))
-            .spawn()
-            .map_err(|e| Error::unknown(format!("Could not start command: {e:?}")))?;
+        let (child, mut device) = match prewarmed {
+            Some(zephyr) => {
+                for p in zephyr.take_boot_packets() {
+                    packet_observer.add_packet(p);
+                }
+                let device = zephyr.device().clone();
+                (&mut zephyr.child, device)
+            }
+            None => {
+                self.device.reset();
+                let child = cold_child.insert(self.command.spawn(&self.envs)?);
+                self.device.init_zephyr(self.timing, &self.network, |p| {
+                    packet_observer.add_packet(p)
+                })?;
+                (child, self.device.clone())
+            }
+        };
 
-        self.device.init_zephyr(|p| packet_observer.add_packet(p))?;
+        if let Some(net_stats_observer) = &self.net_stats_observer {
+            self.observers
+                .get_mut(net_stats_observer)
+                .ok_or(Error::illegal_argument(
+                    "Could not retrieve NetStatsObserver, make sure you pass it to the executor in the OT.",
+                ))?
+                .take_baseline();
+        }
 
-        let packets = input.to_packets();
+        let packet_observer = self
+            .observers
+            .get_mut(&self.packet_observer)
+            .ok_or(Error::illegal_argument(
+            "Could not retrieve PacketObserver, make sure you pass it to the executor in the OT.",
+        ))?;
 
         log::debug!("Started Zephyr, now sending {} packets", packets.len());
 
         for e in packets {
-            self.device.send(&e);
-            packet_observer.add_packet(Source::Client(e));
+            device.send(&e)?;
+            packet_observer.add_input_packet(e);
             let mut last_packet_time = Instant::now();
-            while last_packet_time.elapsed() < INTER_SEND_WAIT {
-                if let Some(incoming) = self.device.try_recv() {
+            while last_packet_time.elapsed() < self.timing.inter_send_wait {
+                if let Some(pool) = self.pool.as_mut() {
+                    pool.pump()?;
+                }
+                if let Some(incoming) = device.try_recv() {
                     let parsed = parse_eth(&incoming)
                         .map_err(|e| Error::illegal_argument(format!("{e:?}")))?;
                     packet_observer.add_packet(Source::Server(incoming));
-                    if let Some(manual_response_res) = ShmemNetworkDevice::respond_manually(parsed)
+                    if let Some(manual_response_res) =
+                        ShmemNetworkDevice::respond_manually(parsed, &self.network)
                     {
                         let manual_response = manual_response_res?;
-                        self.device.send(&manual_response);
+                        device.send(&manual_response)?;
                         packet_observer.add_packet(Source::Client(manual_response));
                     }
 
                     last_packet_time = Instant::now();
                 }
-                sleep(INTER_SEND_WAIT / 5);
+                sleep(self.timing.inter_send_wait / 5);
             }
         }
 
-        let res = child.try_wait().unwrap();
-        child.kill().unwrap();
-        child.wait().unwrap();
+        if device.rx_corrupted() {
+            log::warn!("Zephyr corrupted the ring towards the fuzzer, reporting a crash");
+            return Ok(ExitKind::Crash);
+        }
+
+        let mut res = child.try_wait()?;
+
+        if let (None, Some(liveness_observer)) = (res, self.liveness_observer.clone()) {
+            let (alive, probe_packets) = self.probe_liveness(&device, child)?;
+            self.observers
+                .get_mut(&liveness_observer)
+                .ok_or(Error::illegal_argument(
+                    "Could not retrieve LivenessObserver, make sure you pass it to the executor in the OT.",
+                ))?
+                .set_probe_result(alive, probe_packets);
+            // Zephyr may have crashed while handling the probe
+            res = child.try_wait()?;
+        }
 
-        let res = match res.map(|status| status.signal()) {
+        if device.rx_corrupted() {
+            log::warn!("Zephyr corrupted the ring towards the fuzzer during the liveness probe, reporting a crash");
+            return Ok(ExitKind::Crash);
+        }
+        Ok(match res.map(|status| status.signal()) {
             Some(Some(_)) => ExitKind::Crash,
             Some(None) => ExitKind::Ok,
             None => ExitKind::Ok,
+        })
+    }
+}
+
+impl<EM, Z, S, OT, I, II> Executor<EM, I, S, Z> for ZepyhrExecutor<'_, S, OT, II>
+where
+    S: HasExecutions + HasMetadata,
+    OT: Debug + MatchName + MatchNameRef + ObserversTuple<I, S>,
+    I: ZephyrInput<II>,
+    II: ZephyrInputPart,
+    Vec<u8>: From<II>,
+{
+    fn run_target(
+        &mut self,
+        _fuzzer: &mut Z,
+        state: &mut S,
+        _mgr: &mut EM,
+        input: &I,
+    ) -> Result<ExitKind, Error> {
+        self.observers.pre_exec_child_all(state, input)?;
+
+        let Some(packets) = self.network.fit_to_link(input.to_packets()) else {
+            log::debug!("Input exceeds the MTU of the link, dropping it without running Zephyr");
+            // not an execution, the OversizeDropFeedback keeps the feedbacks from evaluating it
+            let metadata = state.metadata_or_insert_with(OversizeDropsMetadata::default);
+            metadata.dropped += 1;
+            metadata.last_dropped = true;
+            self.observers
+                .post_exec_child_all(state, input, &ExitKind::Ok)?;
+            return Ok(ExitKind::Ok);
         };
+        if let Ok(metadata) = state.metadata_mut::<OversizeDropsMetadata>() {
+            metadata.last_dropped = false;
+        }
+
+        log::debug!("Starting input run #{} on target", state.executions());
+        *state.executions_mut() += 1;
+
+        log::debug!("Preparing Zephyr");
+
+        if self.pool.is_none() && self.pool_size > 0 {
+            self.pool = Some(ZephyrPool::new(
+                self.pool_size,
+                self.command.clone(),
+                &self.envs,
+                &self.shmems,
+                self.network_buf_size,
+                self.id,
+                self.timing,
+                self.network.clone(),
+            )?);
+        }
+        let mut prewarmed = self.pool.as_mut().map(ZephyrPool::take).transpose()?;
+        let mut cold_child = None;
+        let res = self.execute_packets(prewarmed.as_mut(), &mut cold_child, packets);
+
+        // on every path, also if the execution failed, so neither instance outlives it and the pool keeps its size
+        for child in prewarmed
+            .as_mut()
+            .map(|zephyr| &mut zephyr.child)
+            .into_iter()
+            .chain(cold_child.as_mut())
+        {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+        if let (Some(pool), Some(zephyr)) = (self.pool.as_mut(), prewarmed) {
+            pool.give_back(zephyr)?;
+        }
+
+        let res = res?;
 
         self.observers.post_exec_child_all(state, input, &res)?;
 
@@ -15,6 +15,23 @@ use libafl_bolts::Named;
 
 static CORPUS_DIR_COUNT_NAME: Cow<'static, str> = Cow::Borrowed("corpus_dir_count");
 
+/// Count the non-hidden files in `dir` and its subdirectories, e.g. the per-client corpus directories.
+fn count_files(dir: &Path) -> usize {
+    std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flat_map(|e| e.ok())
+        .filter(|e| e.file_name().to_str().is_some_and(|s| !s.starts_with(".")))
+        .map(|e| {
+            if e.path().is_dir() {
+                count_files(&e.path())
+            } else {
+                1
+            }
+        })
+        .sum()
+}
+
 /// Feedback that counts the number of files in the corpus directory.
 pub struct CorpusDirCountFeedback {
     dir: PathBuf,
@@ -46,11 +63,7 @@ where
         _exit_kind: &libafl::executors::ExitKind,
     ) -> Result<bool, Error> {
         if self.last_timestamp.elapsed().unwrap() > self.interval && self.dir.exists() {
-            let corpus_dir_count = std::fs::read_dir(&self.dir)
-                .unwrap()
-                .flat_map(|e| e.ok())
-                .filter(|e| e.file_name().to_str().is_some_and(|s| !s.starts_with(".")))
-                .count();
+            let corpus_dir_count = count_files(&self.dir);
 
             manager.fire(
                 state,
@@ -1,5 +1,5 @@
-#[cfg(feature = "monitor_memory")]
 pub mod corpus_dir_count;
 pub mod input_len;
-#[cfg(feature = "monitor_memory")]
 pub mod memory;
+pub mod net_stats;
+pub mod oversize_drops;
@@ -0,0 +1,115 @@
+use std::borrow::Cow;
+
+use libafl::{
+    corpus::Testcase,
+    executors::ExitKind,
+    feedbacks::{Feedback, StateInitializer},
+    Error, HasMetadata as _, HasNamedMetadata, SerdeAny,
+};
+use libafl_bolts::{
+    tuples::{Handle, MatchNameRef},
+    Named,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::runner::observer::net_stats::{NetStats, NetStatsObserver, NetStatsValues};
+
+/// The most extreme pool usage seen across all executions so far.
+#[derive(Debug, Default, SerdeAny, Serialize, Deserialize)]
+pub struct NetStatsExtremesMetadata {
+    extreme: Option<NetStatsValues>,
+}
+
+/// Stats of the execution that produced a testcase.
+#[derive(Debug, SerdeAny, Serialize, Deserialize)]
+pub struct NetStatsMetadata {
+    stats: NetStats,
+}
+
+/// Feedback that marks inputs reaching new extremes of Zephyr's network buffer pool usage, connections or contexts as interesting.
+///
+/// The known extremes are updated in `append_metadata` from the stats of the execution, like [`libafl::feedbacks::MapFeedback`] updates its history, so they are also recorded if another feedback found the testcase interesting first.
+pub struct NetStatsFeedback {
+    net_stats_observer: Handle<NetStatsObserver>,
+    name: Cow<'static, str>,
+}
+
+impl NetStatsFeedback {
+    pub fn new(net_stats_observer: Handle<NetStatsObserver>) -> Self {
+        Self {
+            net_stats_observer,
+            name: Cow::Borrowed("net-stats"),
+        }
+    }
+}
+
+impl<S> StateInitializer<S> for NetStatsFeedback
+where
+    S: HasNamedMetadata,
+{
+    fn init_state(&mut self, state: &mut S) -> Result<(), Error> {
+        state.add_named_metadata(&self.name, NetStatsExtremesMetadata::default());
+        Ok(())
+    }
+}
+
+impl<EM, I, OT, S> Feedback<EM, I, OT, S> for NetStatsFeedback
+where
+    OT: MatchNameRef,
+    S: HasNamedMetadata,
+{
+    fn is_interesting(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        _input: &I,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error> {
+        let observer = observers
+            .get(&self.net_stats_observer)
+            .ok_or(Error::illegal_argument(
+            "Could not retrieve NetStatsObserver, make sure you pass it to the executor in the OT.",
+        ))?;
+
+        let Some(stats) = observer.stats() else {
+            return Ok(false);
+        };
+
+        let metadata = state.named_metadata::<NetStatsExtremesMetadata>(&self.name)?;
+        Ok(metadata
+            .extreme
+            .is_none_or(|known| stats.extreme.exceeds(&known)))
+    }
+
+    fn append_metadata(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        observers: &OT,
+        testcase: &mut Testcase<I>,
+    ) -> Result<(), Error> {
+        let observer = observers
+            .get(&self.net_stats_observer)
+            .ok_or(Error::illegal_argument(
+            "Could not retrieve NetStatsObserver, make sure you pass it to the executor in the OT.",
+        ))?;
+        let Some(stats) = observer.stats() else {
+            return Ok(());
+        };
+
+        let metadata = state.named_metadata_mut::<NetStatsExtremesMetadata>(&self.name)?;
+        metadata.extreme = Some(match metadata.extreme {
+            Some(known) => known.most_extreme(&stats.extreme),
+            None => stats.extreme,
+        });
+        testcase.add_metadata(NetStatsMetadata { stats: *stats });
+        Ok(())
+    }
+}
+
+impl Named for NetStatsFeedback {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
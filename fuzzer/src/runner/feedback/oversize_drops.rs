@@ -0,0 +1,82 @@
+use std::{borrow::Cow, marker::PhantomData};
+
+use libafl::{
+    events::{Event, EventFirer},
+    executors::ExitKind,
+    feedbacks::{Feedback, StateInitializer},
+    monitors::{AggregatorOps, UserStats, UserStatsValue},
+    Error, HasMetadata,
+};
+use libafl_bolts::Named;
+
+use crate::runner::network::OversizeDropsMetadata;
+
+static OVERSIZE_DROPS_NAME: Cow<'static, str> = Cow::Borrowed("oversize_drops");
+
+/// Feedback rejecting inputs the executor dropped because of [`crate::runner::network::OversizePolicy::Drop`], their observers are empty.
+///
+/// Needs to come first in a [`libafl::feedback_and_fast`], so the other feedbacks do not evaluate dropped inputs at all.
+pub struct OversizeDropFeedback {
+    user_stat: bool,
+}
+
+impl OversizeDropFeedback {
+    pub fn new() -> Self {
+        Self { user_stat: false }
+    }
+
+    /// Report the number of inputs dropped by this client as the `oversize_drops` user stat, summed over all clients.
+    pub fn with_user_stat(mut self) -> Self {
+        self.user_stat = true;
+        self
+    }
+}
+
+impl Default for OversizeDropFeedback {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<EM, I, OT, S> Feedback<EM, I, OT, S> for OversizeDropFeedback
+where
+    S: HasMetadata,
+    EM: EventFirer<I, S>,
+{
+    fn is_interesting(
+        &mut self,
+        state: &mut S,
+        manager: &mut EM,
+        _input: &I,
+        _observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error> {
+        let Ok(metadata) = state.metadata::<OversizeDropsMetadata>() else {
+            return Ok(true);
+        };
+        if !metadata.last_dropped {
+            return Ok(true);
+        }
+
+        if self.user_stat {
+            let dropped = metadata.dropped;
+            manager.fire(
+                state,
+                Event::UpdateUserStats {
+                    name: OVERSIZE_DROPS_NAME.clone(),
+                    value: UserStats::new(UserStatsValue::Number(dropped), AggregatorOps::Sum),
+                    phantom: PhantomData,
+                },
+            )?;
+        }
+        Ok(false)
+    }
+}
+
+impl Named for OversizeDropFeedback {
+    fn name(&self) -> &Cow<'static, str> {
+        &Cow::Borrowed("OversizeDropFeedback")
+    }
+}
+
+impl<S> StateInitializer<S> for OversizeDropFeedback {}
@@ -1,101 +1,272 @@
 use crate::{
     cli::Cli,
-    packets::outgoing_tcp_packets,
+    config::{CampaignConfig, MonitorKind},
     runner::{
+        calibration_log_stage::CalibrationLogStage,
+        cmplog_tracing_stage::CmpLogTracingStage,
+        corpus::JsonOnDiskCorpus,
+        coverage::{coverage_observer, CoverageLayout, CoverageMaskObserver},
+        directed::{DirectedScheduler, DirectedSchedulerMetadata, DistanceFeedback},
         feedback::{
             corpus_dir_count::CorpusDirCountFeedback, input_len::InputLenFeedback,
-            memory::MemoryPseudoFeedback,
+            memory::MemoryPseudoFeedback, net_stats::NetStatsFeedback,
+            oversize_drops::OversizeDropFeedback,
         },
-        generator::{
-            fixed::{FixedZephyrInputGenerator, FixedZephyrInputPartGenerator},
-            random::RandomTcpZephyrInputPartGenerator,
+        generator::fixed::FixedZephyrInputGenerator,
+        input::{
+            list::{ListInput, PrefixPreservingListMutator},
+            model::{ContainerModel, ModelPart, PartModel},
+            stateful::ReplayingStatefulInput,
+            EtherparseInput, ParsedZephyrInput, PartsInput, ZephyrInput,
         },
-        input::{appending::ToAppendingMutatorWrapper, list::ListInput},
-        objective::CrashLoggingFeedback,
-        PacketMetadataFeedback, PacketObserver, ZepyhrExecutor,
+        limits::CampaignLimits,
+        metadata_dump_stage::{restore_state_metadata, StateMetadataDumpStage},
+        minimizer::{MinimizableInput, MinimizationStage},
+        network::OversizeDropsMetadata,
+        objective::{CrashLoggingFeedback, LivenessFeedback, NetStatsLeakFeedback},
+        observer::{cmplog::CmpLogObserver, net_stats::NetStatsObserver},
+        scheduler::{EitherScheduler, StateAwareScheduler, StateSchedulerMetadata},
+        stability::{unstable_edges_handler, UnstableEdges, UnstableEdgesStage},
+        summary::{CampaignSummary, PreviousTestcases},
+        LivenessObserver, PacketMetadataFeedback, PacketObserver, ZepyhrExecutor,
     },
     shmem::get_shmem,
-    COV_SHMEM_SIZE, NETWORK_SHMEM_SIZE,
+    NETWORK_SHMEM_SIZE, STATE_ANNOTATION_SHMEM_SIZE,
 };
 
-#[allow(unused_imports)]
-use {
-    crate::runner::input::etherparse::EtherparseInput,
-    libafl::{inputs::BytesInput, mutators::havoc_mutations_no_crossover},
-};
-
-use clap::Parser as _;
 use libafl::{
-    corpus::{Corpus, InMemoryCorpus, OnDiskCorpus},
+    corpus::Corpus,
     events::{
-        CentralizedEventManager, CentralizedLauncher, ClientDescription, EventConfig,
-        SendExiting as _,
+        CentralizedEventManager, CentralizedLauncher, ClientDescription, Event, EventConfig,
+        EventFirer as _, HasCustomBufHandlers as _, SendExiting as _,
     },
     feedback_and_fast, feedback_or_fast,
-    feedbacks::{ConstFeedback, MaxMapFeedback, TimeFeedback},
-    fuzzer::{replaying::ReplayingFuzzer, Evaluator as _, Fuzzer as _},
+    feedbacks::{ConstFeedback, MapFeedbackMetadata, MaxMapFeedback, TimeFeedback},
+    fuzzer::replaying::HasLen,
+    fuzzer::{replaying::ReplayingFuzzer, Evaluator as _, Fuzzer as _, HasScheduler as _},
     generators::Generator as _,
-    monitors::OnDiskJsonAggregateMonitor,
-    mutators::StdMOptMutator,
-    observers::{ConstMapObserver, HitcountsMapObserver, StdMapObserver, TimeObserver},
-    stages::StdMutationalStage,
-    state::{HasCorpus as _, StdState},
-    Error,
+    inputs::{BytesInput, Input, MultipartInput},
+    monitors::{
+        tui::TuiMonitor, AggregatorOps, Monitor, MultiMonitor, NopMonitor,
+        OnDiskJsonAggregateMonitor, UserStats, UserStatsValue,
+    },
+    mutators::{MutatorsTuple, StdMOptMutator},
+    observers::{HitcountsMapObserver, StdMapObserver, TimeObserver},
+    schedulers::{powersched::PowerSchedule, Scheduler as _, StdScheduler, StdWeightedScheduler},
+    stages::{CalibrationStage, IfStage, StdMutationalStage},
+    state::{HasCorpus as _, HasExecutions as _, HasSolutions as _, StdState},
+    Error, HasMetadata as _, HasNamedMetadata as _,
 };
 use libafl_bolts::{
     core_affinity::Cores,
+    current_nanos,
     rands::StdRand,
     shmem::{ShMem, ShMemProvider as _, StdShMemProvider},
-    tuples::{tuple_list, Handle, Handled as _, Map as _, Merge as _},
+    tuples::{tuple_list, Handle, Handled as _, NamedTuple},
     Named as _,
 };
-use std::{path::PathBuf, ptr::NonNull, time::Duration};
-
-#[cfg(feature = "monitor_tui")]
-use libafl::monitors::tui::TuiMonitor;
-#[cfg(feature = "monitor_stdout")]
-use libafl::monitors::MultiMonitor;
-#[cfg(feature = "monitor_none")]
-use libafl::monitors::NopMonitor;
-#[cfg(not(feature = "coverage_stability"))]
-use libafl::schedulers::{powersched::PowerSchedule, StdWeightedScheduler};
-#[cfg(feature = "coverage_stability")]
-use {
-    crate::runner::calibration_log_stage::CalibrationLogStage,
-    libafl::{schedulers::StdScheduler, stages::CalibrationStage},
-};
+use std::{borrow::Cow, fs, hash::Hash, marker::PhantomData, path::PathBuf, time::Duration};
+
+type FuzzState<I> = StdState<JsonOnDiskCorpus<I>, I, StdRand, JsonOnDiskCorpus<I>>;
+
+/// Seed of the random number generator of a client, so campaigns started with the same `--seed` take the same decisions on a deterministic target.
+///
+/// [`StdRand`] mixes its seed, so consecutive ids still result in unrelated sequences.
+pub fn client_seed(seed: u64, id: usize) -> u64 {
+    seed.wrapping_add(id as u64)
+}
+
+pub fn fuzz(opt: Cli) {
+    let config = opt.campaign_config().unwrap();
+    opt.input_model()
+        .validate(opt.mutator_groups(), opt.minimize())
+        .unwrap();
+
+    let json_path = format!("{}.json", opt.monitor());
+    if std::path::Path::new(&json_path).exists() {
+        println!("Monitor file already exists: {}, exiting", json_path);
+        return;
+    }
+    // record the config next to the monitor JSON, so the results can be traced back to it
+    config.store(format!("{}.toml", opt.monitor())).unwrap();
+
+    // drawn once, so the clients still derive their seeds from a common one that can be passed to --seed later on
+    let seed = opt.seed().unwrap_or_else(current_nanos);
+    println!("Seed: {seed}");
+
+    let mut coverage = CoverageLayout::probe(
+        opt.zephyr_exec_dir().unwrap(),
+        config.coverage_filter(),
+        config.directed().targets(),
+    )
+    .unwrap();
+    if let Some(distances) = coverage.target_distances() {
+        let distance_map_path = config.directed().distance_map();
+        distances.store(distance_map_path).unwrap();
+        println!(
+            "Directed towards {:?}, reachable from {} of {} coverage guards, distances written to {distance_map_path:?}",
+            config.directed().targets(),
+            distances.reachable(),
+            distances.guards()
+        );
+    }
+    let unstable_edges_path = config.stability().unstable_edges();
+    if unstable_edges_path.exists() {
+        let loaded = coverage
+            .unstable_edges_mut()
+            .load(unstable_edges_path)
+            .unwrap();
+        println!("Loaded {loaded} unstable edges from {unstable_edges_path:?}");
+    }
 
-pub fn fuzz() {
+    let limits = CampaignLimits::new(
+        opt.max_time(),
+        opt.max_execs(),
+        opt.stop_after_solutions(),
+        coverage.map_size(),
+    )
+    .unwrap();
+    // the summary only covers the testcases found in this campaign
+    let previous = if limits.is_active() {
+        PreviousTestcases::collect(opt.corpus_dir(), opt.solutions_dir()).unwrap()
+    } else {
+        PreviousTestcases::default()
+    };
+    // the clients are forked from this process and return from the launcher as well
+    let launcher_pid = std::process::id();
+
+    match config.monitor() {
+        MonitorKind::Tui => launch_input_model(
+            &opt,
+            &config,
+            seed,
+            &limits,
+            &coverage,
+            json_path,
+            TuiMonitor::builder()
+                .title("Zephyr TCP/IP Stack Fuzzer")
+                .build(),
+        ),
+        MonitorKind::Stdout => launch_input_model(
+            &opt,
+            &config,
+            seed,
+            &limits,
+            &coverage,
+            json_path,
+            MultiMonitor::new(|m| println!("{m}")),
+        ),
+        MonitorKind::None => launch_input_model(
+            &opt,
+            &config,
+            seed,
+            &limits,
+            &coverage,
+            json_path,
+            NopMonitor::new(),
+        ),
+    }
+
+    if std::process::id() == launcher_pid {
+        if let Some(limit) = limits.reached() {
+            let summary_path = format!("{}-summary.json", opt.monitor());
+            CampaignSummary::collect(&limits, opt.corpus_dir(), opt.solutions_dir(), &previous)
+                .and_then(|summary| summary.store(&summary_path))
+                .unwrap();
+            println!("Reached {limit}, wrote summary to {summary_path}");
+        }
+    }
+}
+
+/// Dispatch `--input-model` to the corresponding monomorphised fuzzer.
+fn launch_input_model<MT: Monitor + Clone>(
+    opt: &Cli,
+    config: &CampaignConfig,
+    seed: u64,
+    limits: &CampaignLimits,
+    coverage: &CoverageLayout,
+    json_path: String,
+    monitor: MT,
+) {
+    use ContainerModel::*;
+    use PartModel::*;
+
+    let model = opt.input_model();
+    match (model.container(), model.part()) {
+        (List, Bytes) => launch::<_, ListInput<BytesInput>, _>(
+            opt, config, seed, limits, coverage, json_path, monitor,
+        ),
+        (List, Parsed) => launch::<_, ListInput<ParsedZephyrInput>, _>(
+            opt, config, seed, limits, coverage, json_path, monitor,
+        ),
+        (List, Etherparse) => launch::<_, ListInput<EtherparseInput>, _>(
+            opt, config, seed, limits, coverage, json_path, monitor,
+        ),
+        (Stateful, Bytes) => launch::<_, ReplayingStatefulInput<BytesInput>, _>(
+            opt, config, seed, limits, coverage, json_path, monitor,
+        ),
+        (Stateful, Parsed) => launch::<_, ReplayingStatefulInput<ParsedZephyrInput>, _>(
+            opt, config, seed, limits, coverage, json_path, monitor,
+        ),
+        (Stateful, Etherparse) => launch::<_, ReplayingStatefulInput<EtherparseInput>, _>(
+            opt, config, seed, limits, coverage, json_path, monitor,
+        ),
+        (Multipart, Bytes) => launch::<_, MultipartInput<BytesInput>, _>(
+            opt, config, seed, limits, coverage, json_path, monitor,
+        ),
+        (Multipart, Parsed) => launch::<_, MultipartInput<ParsedZephyrInput>, _>(
+            opt, config, seed, limits, coverage, json_path, monitor,
+        ),
+        (Multipart, Etherparse) => launch::<_, MultipartInput<EtherparseInput>, _>(
+            opt, config, seed, limits, coverage, json_path, monitor,
+        ),
+    }
+}
+
+fn launch<MT, I, II>(
+    opt: &Cli,
+    config: &CampaignConfig,
+    seed: u64,
+    limits: &CampaignLimits,
+    coverage: &CoverageLayout,
+    json_path: String,
+    monitor: MT,
+) where
+    MT: Monitor + Clone,
+    I: ZephyrInput<II> + PartsInput<Part = II> + MinimizableInput + HasLen + Input + Hash + Clone,
+    II: ModelPart + From<Vec<u8>> + Clone,
+    II::GroupedMutators: MutatorsTuple<I, FuzzState<I>> + NamedTuple,
+    Vec<u8>: From<II>,
+{
     log::info!("Initializing fuzzer");
-    let opt = Cli::parse();
 
-    let zephyr_exec_path = opt.zephyr_exec_dir();
+    let zephyr_exec_path = opt.zephyr_exec_dir().unwrap();
+
+    let (cores, overcommit) = if opt.fuzz_one() || opt.load_only() {
+        (Cores::from_cmdline("1").unwrap(), 1)
+    } else {
+        (opt.cores().clone(), opt.overcommit())
+    };
+
+    // each core runs `overcommit` clients, one of them the main client
+    let clients = cores.ids.len() * overcommit;
 
     let run_client = |primary: bool| {
-        let opt = &opt;
         move |state: Option<_>,
               mut manager: CentralizedEventManager<_, _, _, _, _, _>,
               client_description: ClientDescription| {
             log::info!("Initializing fuzzing client");
 
-            let mut cov_shmem = get_shmem(COV_SHMEM_SIZE, client_description.id(), "cov")?;
+            let mut cov_shmem = get_shmem(coverage.map_size(), client_description.id(), "cov")?;
             let cov_shmem_description = cov_shmem.description();
-
-            let cov_raw_observer = unsafe {
-                ConstMapObserver::from_mut_ptr(
-                    "coverage-observer",
-                    NonNull::new(cov_shmem.as_mut_ptr())
-                        .expect("map ptr is null")
-                        .cast::<[u8; COV_SHMEM_SIZE]>(),
-                )
-            };
-
-            let cov_observer = HitcountsMapObserver::new(cov_raw_observer);
+            let cov_observer = coverage_observer(&mut cov_shmem);
+            let cov_mask_observer = CoverageMaskObserver::new(&cov_shmem_description, coverage)?;
             let time_observer = TimeObserver::new("time-observer");
 
-            let mut packet_observer = PacketObserver::new(opt.state_diff());
+            let mut packet_observer = PacketObserver::new(opt.state_diff(), opt.state_hitcounts());
             let state_map = packet_observer.get_state_map();
-            let state_map_observer = unsafe {
+            // classifying binary maps is a no-op, so the observer can stay the same if hitcounts are disabled
+            let state_map_observer = HitcountsMapObserver::new(unsafe {
                 let state_map_len = state_map.len();
                 StdMapObserver::from_mut_ptr(
                     if opt.state_diff() {
@@ -106,13 +277,46 @@ pub fn fuzz() {
                     state_map.as_mut_ptr(),
                     state_map_len,
                 )
+            });
+
+            let mut state_annotation_shmem = get_shmem(
+                STATE_ANNOTATION_SHMEM_SIZE,
+                client_description.id(),
+                "state",
+            )?;
+            let state_annotation_shmem_description = state_annotation_shmem.description();
+            let state_annotation_observer = unsafe {
+                StdMapObserver::from_mut_ptr(
+                    "state-annotation-map-observer",
+                    state_annotation_shmem.as_mut_ptr(),
+                    STATE_ANNOTATION_SHMEM_SIZE,
+                )
             };
+
             let cov_feedback = MaxMapFeedback::new(&cov_observer);
+            let cov_feedback_name = cov_feedback.name().clone();
             let state_feedback = MaxMapFeedback::new(&state_map_observer);
+            let state_annotation_feedback = MaxMapFeedback::new(&state_annotation_observer);
             let packet_observer_handle = packet_observer.handle();
-
-            #[cfg(feature = "coverage_stability")]
-            let stability = CalibrationStage::new(&cov_feedback);
+            let liveness_observer = LivenessObserver::new();
+            let liveness_observer_handle = liveness_observer.handle();
+            let net_stats_observer = NetStatsObserver::new(client_description.id())?;
+            let net_stats_observer_handle = net_stats_observer.handle();
+            let cmplog_observer = if opt.cmplog() {
+                CmpLogObserver::new(client_description.id())?
+            } else {
+                CmpLogObserver::disabled()
+            };
+            let cmplog_observer_handle = cmplog_observer.handle();
+
+            let coverage_stability = config.coverage_stability();
+            let stability = IfStage::new(
+                move |_, _, _, _| Ok(coverage_stability),
+                tuple_list!(
+                    CalibrationStage::new(&cov_feedback),
+                    CalibrationLogStage::new("unstable-coverage.txt")
+                ),
+            );
 
             let should_have_gated_feedbacks =
                 opt.cores().ids.len() * opt.overcommit() == client_description.id();
@@ -122,38 +326,83 @@ pub fn fuzz() {
             }
 
             let gated_feedbacks = feedback_and_fast!(
-                ConstFeedback::new(should_have_gated_feedbacks),
+                ConstFeedback::new(
+                    should_have_gated_feedbacks && config.feedbacks().memory_stats()
+                ),
                 feedback_or_fast!(
                     MemoryPseudoFeedback::new(Duration::from_secs(10)),
                     CorpusDirCountFeedback::new(opt.corpus_dir(), Duration::from_secs(10))
                 )
             );
 
-            let mut feedback = feedback_or_fast!(
-                gated_feedbacks,
-                TimeFeedback::new(&time_observer),
-                PacketMetadataFeedback::new(packet_observer_handle.clone()),
-                InputLenFeedback,
-                // only log coverage
-                // feedback_and_fast!(state_feedback, ConstFeedback::new(false)),
-                feedback_and_fast!(cov_feedback, ConstFeedback::new(false)),
-                // cov_feedback,
-                state_feedback,
+            // dropped inputs were not executed, so none of the feedbacks get to evaluate them
+            let mut feedback = feedback_and_fast!(
+                OversizeDropFeedback::new().with_user_stat(),
+                feedback_or_fast!(
+                    gated_feedbacks,
+                    TimeFeedback::new(&time_observer),
+                    PacketMetadataFeedback::new(packet_observer_handle.clone()),
+                    DistanceFeedback::new(
+                        cov_observer.handle(),
+                        coverage.target_distances().cloned()
+                    ),
+                    InputLenFeedback,
+                    // both maps are always observed, the config decides whether they guide the fuzzer or are only logged
+                    feedback_and_fast!(
+                        cov_feedback,
+                        ConstFeedback::new(config.feedbacks().coverage())
+                    ),
+                    feedback_and_fast!(
+                        state_feedback,
+                        ConstFeedback::new(config.feedbacks().state())
+                    ),
+                    feedback_and_fast!(
+                        ConstFeedback::new(opt.state_annotations()),
+                        state_annotation_feedback
+                    ),
+                    NetStatsFeedback::new(net_stats_observer_handle.clone()),
+                )
             );
 
-            let mut objective = feedback_or_fast!(
-                TimeFeedback::new(&time_observer),
-                CrashLoggingFeedback::new(),
+            let mut objective = feedback_and_fast!(
+                OversizeDropFeedback::new(),
+                feedback_or_fast!(
+                    TimeFeedback::new(&time_observer),
+                    CrashLoggingFeedback::new(),
+                    LivenessFeedback::new(liveness_observer_handle.clone()),
+                    NetStatsLeakFeedback::new(net_stats_observer_handle.clone()),
+                )
             );
 
-            let solutions = OnDiskCorpus::<ListInput<EtherparseInput>>::new(opt.solutions_dir())?;
-            // let corpus = OnDiskCorpus::new(opt.corpus_dir())?;
+            // each client stores its testcases in its own subdirectory, since the file names contain the corpus ids
+            let client_dir = client_description.id().to_string();
+            let corpus_dir = opt.corpus_dir().join(&client_dir);
+            let solutions_dir = opt.solutions_dir().join(&client_dir);
+            let state_metadata_path = corpus_dir.join(".state-metadata.json");
+
+            // a restarted client gets its corpus with the state
+            let fresh_state = state.is_none();
+            let (stored_corpus, stored_solutions) = if fresh_state {
+                let stored_corpus = JsonOnDiskCorpus::load_dir(&corpus_dir)?;
+                let stored_solutions = JsonOnDiskCorpus::load_dir(&solutions_dir)?;
+                if !opt.resume() && !(stored_corpus.is_empty() && stored_solutions.is_empty()) {
+                    return Err(Error::illegal_argument(format!(
+                        "{corpus_dir:?} or {solutions_dir:?} already contain testcases, pass --resume to continue that campaign"
+                    )));
+                }
+                (stored_corpus, stored_solutions)
+            } else {
+                (vec![], vec![])
+            };
 
-            let corpus = InMemoryCorpus::new();
+            let solutions = JsonOnDiskCorpus::<I>::new(&solutions_dir)?;
+            let corpus = JsonOnDiskCorpus::new(&corpus_dir)?;
 
-            let mut state: StdState<_, _, _, _> = state.unwrap_or_else(|| {
+            // a restarted client continues with the generator stored in its state
+            let client_seed = client_seed(seed, client_description.id());
+            let mut state: FuzzState<I> = state.unwrap_or_else(|| {
                 StdState::new(
-                    StdRand::new(),
+                    StdRand::with_seed(client_seed),
                     corpus,
                     solutions,
                     &mut feedback,
@@ -162,43 +411,112 @@ pub fn fuzz() {
                 .expect("Could not create state")
             });
 
-            let appending_muators = tuple_list!(
-                FixedZephyrInputPartGenerator::new(outgoing_tcp_packets(), true),
-                RandomTcpZephyrInputPartGenerator
+            // before creating the scheduler and MOpt, so they keep the restored metadata
+            if fresh_state
+                && opt.resume()
+                && restore_state_metadata(&mut state, &state_metadata_path)?
+            {
+                log::info!("Restored state metadata from {:?}", state_metadata_path);
+                // the stored testcases get new corpus ids when they are added again below, the schedulers record them again in on_add
+                if let Ok(metadata) = state.metadata_mut::<StateSchedulerMetadata>() {
+                    metadata.forget_testcases();
+                }
+                state
+                    .metadata_map_mut()
+                    .remove::<DirectedSchedulerMetadata>();
+            }
+
+            // the unstable edges found by other clients are merged as they broadcast them
+            if !state.has_metadata::<UnstableEdges>() {
+                state.add_metadata(coverage.unstable_edges().clone());
+            }
+            manager.add_custom_buf_handler(unstable_edges_handler(
+                config.stability().unstable_edges().to_path_buf(),
+            ));
+
+            log::info!("Client {} uses seed {client_seed}", client_description.id());
+            manager.fire(
+                &mut state,
+                Event::UpdateUserStats {
+                    name: Cow::Borrowed("seed"),
+                    value: UserStats::new(UserStatsValue::Number(client_seed), AggregatorOps::None),
+                    phantom: PhantomData,
+                },
+            )?;
+
+            let mutators = II::grouped_mutators(opt.mutator_groups(), config.network());
+
+            println!(
+                "Input/Mutator config: {} with {:?}",
+                opt.input_model(),
+                opt.mutator_groups()
+            );
+
+            let mutator =
+                StdMutationalStage::new(PrefixPreservingListMutator::new(StdMOptMutator::new(
+                    &mut state,
+                    mutators,
+                    config.mopt().max_stack_pow(),
+                    config.mopt().swarm_num(),
+                )?));
+
+            let minimization_stage = MinimizationStage::new(
+                packet_observer_handle.clone(),
+                config.network(),
+                opt.minimize(),
             )
-            .map(ToAppendingMutatorWrapper);
+            .with_distances(cov_observer.handle(), coverage.target_distances().cloned());
 
-            let mutators =
-                ListInput::<EtherparseInput>::map_to_mutate_on_last(EtherparseInput::mutators())
-                    .merge(appending_muators);
+            let cmplog_tracing_stage =
+                CmpLogTracingStage::new(cmplog_observer_handle.clone(), opt.cmplog());
 
-            println!("Input/Mutator config: {}", mutators.0.name());
+            let unstable_edges_stage = UnstableEdgesStage::new(
+                cov_observer.handle(),
+                config.stability().calibration_runs(),
+                config.stability().unstable_edges().to_path_buf(),
+            );
 
-            let mutator = StdMutationalStage::new(StdMOptMutator::new(&mut state, mutators, 7, 5)?);
+            let state_metadata_dump_stage =
+                StateMetadataDumpStage::new(&state_metadata_path, Duration::from_secs(60));
 
-            #[cfg(feature = "coverage_stability")]
-            let unstable_coverage_log_stage = CalibrationLogStage::new("unstable-coverage.txt");
-            #[cfg(feature = "coverage_stability")]
-            let mut stages = tuple_list!(stability, unstable_coverage_log_stage, mutator);
+            let mut stages = tuple_list!(
+                stability,
+                unstable_edges_stage,
+                minimization_stage,
+                cmplog_tracing_stage,
+                mutator,
+                state_metadata_dump_stage
+            );
 
-            #[cfg(not(feature = "coverage_stability"))]
-            let mut stages = tuple_list!(mutator);
+            // StdWeightedScheduler is not compatible with CalibrationStage
+            let scheduler = if config.coverage_stability() {
+                EitherScheduler::Left(StdScheduler::new())
+            } else {
+                EitherScheduler::Right(StdWeightedScheduler::with_schedule(
+                    &mut state,
+                    &state_map_observer,
+                    Some(PowerSchedule::fast()),
+                ))
+            };
 
-            #[cfg(not(feature = "coverage_stability"))]
-            let scheduler = StdWeightedScheduler::with_schedule(
+            let scheduler = DirectedScheduler::new(
                 &mut state,
-                &state_map_observer,
-                Some(PowerSchedule::fast()),
+                scheduler,
+                config.directed().is_active(),
+                config.directed().exploitation_time(),
             );
 
-            // StdWeightedScheduler is not compatible with CalibrationStage
-            #[cfg(feature = "coverage_stability")]
-            let scheduler = StdScheduler::new();
+            let scheduler = StateAwareScheduler::new(
+                &mut state,
+                scheduler,
+                packet_observer_handle.clone(),
+                opt.state_scheduler(),
+            );
 
             let mut fuzzer = ReplayingFuzzer::new(
-                3,
-                2.0,
-                10,
+                config.replaying().min_count_diff(),
+                config.replaying().min_count_ratio(),
+                config.replaying().max_trials(),
                 true,
                 None::<Handle<u8>>,
                 Some(state_map_observer.handle()),
@@ -209,12 +527,17 @@ pub fn fuzz() {
 
             let mut observers = tuple_list!(
                 cov_observer,
+                cov_mask_observer,
                 time_observer,
                 packet_observer,
-                state_map_observer
+                state_map_observer,
+                state_annotation_observer,
+                liveness_observer,
+                net_stats_observer,
+                cmplog_observer
             );
 
-            let mut executor = ZepyhrExecutor::new(
+            let executor = ZepyhrExecutor::new(
                 &mut observers,
                 packet_observer_handle,
                 &cov_shmem_description,
@@ -223,12 +546,78 @@ pub fn fuzz() {
                 NETWORK_SHMEM_SIZE,
                 client_description.id(),
                 opt.zephyr_rt_ratio(),
-            )?;
+            )?
+            .with_timing(config.timing())
+            .with_network_profile(config.network().clone())?;
+
+            let executor = if opt.liveness_probe() {
+                executor.with_liveness_probe(liveness_observer_handle)
+            } else {
+                executor
+            };
+
+            let executor = if opt.net_stats() {
+                executor.with_net_stats(net_stats_observer_handle)?
+            } else {
+                executor
+            };
+
+            let executor = if opt.state_annotations() {
+                executor.with_state_annotations(&state_annotation_shmem_description)?
+            } else {
+                executor
+            };
+
+            let mut executor = if opt.cmplog() {
+                executor.with_cmplog(cmplog_observer_handle)?
+            } else {
+                executor
+            }
+            .with_prewarmed_pool(opt.prewarm());
+
+            if !stored_corpus.is_empty() || !stored_solutions.is_empty() {
+                log::info!(
+                    "Resuming with {} stored inputs and {} stored solutions",
+                    stored_corpus.len(),
+                    stored_solutions.len()
+                );
+            }
+            for stored in stored_corpus {
+                let id = if stored.disabled {
+                    state.corpus_mut().add_disabled(stored.testcase)?
+                } else {
+                    let id = state.corpus_mut().add(stored.testcase)?;
+                    fuzzer.scheduler_mut().on_add(&mut state, id)?;
+                    id
+                };
+                // the testcase was stored again under its new id
+                if state.corpus().path_of(id)? != stored.path {
+                    fs::remove_file(stored.path)?;
+                }
+            }
+            for stored in stored_solutions {
+                let id = state.solutions_mut().add(stored.testcase)?;
+                if state.solutions().path_of(id)? != stored.path {
+                    fs::remove_file(stored.path)?;
+                }
+            }
+
+            let oversize_drops = |state: &FuzzState<I>| {
+                state
+                    .metadata::<OversizeDropsMetadata>()
+                    .map_or(0, |m| m.dropped)
+            };
+            // restarted clients already reported their executions, stored solutions and drops restored with the state metadata are not part of this campaign
+            let mut client_limits = limits.client(
+                if fresh_state { 0 } else { *state.executions() },
+                state.solutions().count(),
+                oversize_drops(&state),
+            );
 
             if state.must_load_initial_inputs() {
-                let outgoing_packets = outgoing_tcp_packets();
+                let outgoing_packets = config.network().outgoing_tcp_packets();
                 let outgoing_packets_len = outgoing_packets.len();
-                let mut generator = FixedZephyrInputGenerator::new(outgoing_packets, true);
+                let mut generator = FixedZephyrInputGenerator::<II>::new(outgoing_packets, true);
 
                 log::debug!(
                     "Generating inputs from fixed trace, expecting {} packets",
@@ -272,6 +661,47 @@ pub fn fuzz() {
                 return Err(Error::shutting_down());
             // } else if manager.is_main() {
             //     fuzzer.fuzz_loop(&mut tuple_list!(), &mut executor, &mut state, &mut manager)?;
+            } else if limits.is_active() {
+                let limit = loop {
+                    if primary {
+                        fuzzer.fuzz_loop_for(
+                            &mut (),
+                            &mut executor,
+                            &mut state,
+                            &mut manager,
+                            1,
+                        )?;
+                    } else {
+                        fuzzer.fuzz_loop_for(
+                            &mut stages,
+                            &mut executor,
+                            &mut state,
+                            &mut manager,
+                            1,
+                        )?;
+                    }
+                    if let Some(limit) = client_limits.update(
+                        *state.executions(),
+                        state.solutions().count(),
+                        oversize_drops(&state),
+                    ) {
+                        break limit;
+                    }
+                };
+                log::info!(
+                    "Client {} stopping, {limit} reached",
+                    client_description.id()
+                );
+                if let Ok(history) =
+                    state.named_metadata::<MapFeedbackMetadata<u8>>(&cov_feedback_name)
+                {
+                    limits.merge_coverage(&history.history_map);
+                }
+                manager.send_exiting()?;
+                if limits.client_exited(clients)? {
+                    log::info!("All {clients} clients stopped, stopping broker");
+                }
+                return Err(Error::shutting_down());
             } else if primary {
                 fuzzer.fuzz_loop(&mut (), &mut executor, &mut state, &mut manager)?;
             } else {
@@ -281,33 +711,9 @@ pub fn fuzz() {
         }
     };
 
-    #[cfg(feature = "monitor_tui")]
-    let monitor = {
-        TuiMonitor::builder()
-            .title("Zephyr TCP/IP Stack Fuzzer")
-            .build()
-    };
-
-    #[cfg(feature = "monitor_stdout")]
-    let monitor = MultiMonitor::new(|m| println!("{m}"));
-
-    #[cfg(feature = "monitor_none")]
-    let monitor = NopMonitor::new();
-
-    let json_path = format!("{}.json", opt.monitor());
-    if std::path::Path::new(&json_path).exists() {
-        println!("Monitor file already exists: {}, exiting", json_path);
-        return;
-    }
     let monitor =
         OnDiskJsonAggregateMonitor::with_interval(json_path, monitor, Duration::from_secs(1));
 
-    let (cores, overcommit) = if opt.fuzz_one() || opt.load_only() {
-        (Cores::from_cmdline("1").unwrap(), 1)
-    } else {
-        (opt.cores().clone(), opt.overcommit())
-    };
-
     match CentralizedLauncher::builder()
         .shmem_provider(StdShMemProvider::new().expect("Failed to init shared memory"))
         .configuration(EventConfig::from_name("default"))
@@ -324,6 +730,9 @@ pub fn fuzz() {
     {
         Ok(()) => (),
         Err(e) => match e {
+            Error::ShuttingDown if limits.reached().is_some() => {
+                log::info!("Fuzzing stopped by {}. Good bye.", limits.reached().unwrap())
+            }
             Error::ShuttingDown => log::info!("Fuzzing stopped by user. Good bye."),
             _ => log::warn!("--------------------------------\nFailed to run launcher:\n{}\n--------------------------------", e),
         },
@@ -1,10 +1,19 @@
-use etherparse::{PacketBuilder, PacketHeaders, TcpOptionElement, TcpOptions};
+use etherparse::{PacketBuilder, TcpOptionElement, TcpOptions};
 use libafl::{generators::Generator, nonzero, state::HasRand, Error};
 use libafl_bolts::rands::Rand;
 
-use crate::{packets::outgoing_tcp_packets, runner::input::ZephyrInputPart};
+use crate::runner::{input::ZephyrInputPart, network::NetworkProfile};
 
-pub struct RandomTcpZephyrInputPartGenerator;
+/// Generates TCP packets with random headers and payload, sent from the client to Zephyr of the [`NetworkProfile`].
+pub struct RandomTcpZephyrInputPartGenerator {
+    network: NetworkProfile,
+}
+
+impl RandomTcpZephyrInputPartGenerator {
+    pub fn new(network: NetworkProfile) -> Self {
+        Self { network }
+    }
+}
 
 impl<I, S> Generator<I, S> for RandomTcpZephyrInputPartGenerator
 where
@@ -20,14 +29,9 @@ where
             .map(|_| rand.next() as u8)
             .collect::<Vec<u8>>();
 
-        let outgoing_packets = outgoing_tcp_packets();
-        let blueprint = PacketHeaders::from_ethernet_slice(&outgoing_packets[0]).unwrap();
-        let eth = blueprint.link.unwrap().ethernet2().unwrap();
-        let net = blueprint.net.unwrap();
-        let (ipv4, _ipv4_extensions) = net.ipv4_ref().unwrap();
-
-        let builder = PacketBuilder::ethernet2(eth.source, eth.destination)
-            .ipv4(ipv4.source, ipv4.destination, rand.next() as u8)
+        let network = &self.network;
+        let builder = PacketBuilder::ethernet2(network.client_mac(), network.zephyr_mac())
+            .ipv4(network.client_ipv4(), network.zephyr_ipv4(), rand.next() as u8)
             .tcp(
                 rand.next() as u16,
                 rand.next() as u16,
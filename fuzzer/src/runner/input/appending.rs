@@ -4,11 +4,11 @@ use libafl::{
     generators::Generator,
     inputs::MultipartInput,
     mutators::{MutationResult, Mutator},
-    Error,
+    Error, HasMetadata,
 };
 use libafl_bolts::{tuples::MappingFunctor, Named};
 
-use super::{list::ListInput, stateful::ReplayingStatefulInput};
+use super::{list::ListInput, mutation_log::MutationLogMetadata, stateful::ReplayingStatefulInput};
 
 pub struct AppendingMutator<G> {
     generator: G,
@@ -38,10 +38,12 @@ where
 impl<G, I, S> Mutator<ListInput<I>, S> for AppendingMutator<G>
 where
     G: Generator<I, S>,
+    S: HasMetadata,
 {
     fn mutate(&mut self, state: &mut S, input: &mut ListInput<I>) -> Result<MutationResult, Error> {
         let new_part = self.generator.generate(state).unwrap();
         input.parts_mut().push(new_part);
+        MutationLogMetadata::log(state, input.parts().len() - 1, "AppendingMutator");
         Ok(MutationResult::Mutated)
     }
 }
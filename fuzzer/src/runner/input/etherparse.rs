@@ -1,6 +1,6 @@
 use etherparse::{
     ip_number::AUTH, Ethernet2Header, Ipv4Extensions, Ipv4Header, Packet, PacketHeaders, Payload,
-    TcpHeader,
+    TcpHeader, TcpOptionElement,
 };
 
 use libafl::{
@@ -17,14 +17,21 @@ use libafl_bolts::{
 };
 use serde::{Deserialize, Serialize};
 use std::{
+    collections::BTreeSet,
     hash::{Hash, Hasher},
     io::Write as _,
+    mem::size_of,
     vec::Vec,
 };
 
-use crate::layers::PacketParseError;
+use crate::{
+    layers::PacketParseError,
+    runner::{network::NetworkProfile, observer::cmplog::CmpOperands},
+};
+
+use super::{bool::BoolMutator, i2s::I2SFieldMutator};
 
-use super::bool::BoolMutator;
+const TCP_MIN_HEADER_LEN: usize = 20;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EtherparseInput {
@@ -136,6 +143,16 @@ impl EtherparseInput {
         }
     }
 
+    /// Send the packet from the client to Zephyr of `profile`, keeping everything else.
+    pub fn retarget(&mut self, profile: &NetworkProfile) {
+        self.eth.source = profile.client_mac();
+        self.eth.destination = profile.zephyr_mac();
+        self.ip.source = profile.client_ipv4();
+        self.ip.destination = profile.zephyr_ipv4();
+        self.tcp.source_port = profile.client_port();
+        self.tcp.destination_port = profile.zephyr_port();
+    }
+
     pub fn tcp_source_port(&mut self) -> &mut u16 {
         &mut self.tcp.source_port
     }
@@ -182,6 +199,178 @@ impl EtherparseInput {
         &mut self.tcp.urgent_pointer
     }
 
+    /// Smaller or more seed-like variants of this packet, used to minimize inputs.
+    ///
+    /// Drops the TCP options, shortens the payload and resets each field the mutators change to its value in the most similar seed.
+    pub fn simplifications(&self, seeds: &[Self]) -> Vec<Self> {
+        let mut simplifications = vec![];
+        if self.tcp_options_len() > 0 {
+            simplifications.extend(self.trimmed(true, self.payload.slice().len()));
+        }
+        let payload_len = self.payload.slice().len();
+        if payload_len > 0 {
+            simplifications.extend(self.trimmed(false, 0));
+        }
+        if payload_len > 1 {
+            simplifications.extend(self.trimmed(false, payload_len / 2));
+        }
+        if let Some(seed) = seeds.iter().min_by_key(|seed| self.seed_resets(seed).len()) {
+            simplifications.extend(self.seed_resets(seed));
+        }
+        simplifications
+    }
+
+    /// Copies of this packet with one of the mutated fields reset to the value in `seed`.
+    fn seed_resets(&self, seed: &Self) -> Vec<Self> {
+        let mut resets = Self::field_resets(self, seed, &Self::U16_FIELDS);
+        resets.extend(Self::field_resets(self, seed, &Self::U32_FIELDS));
+        resets.extend(Self::field_resets(self, seed, &Self::BOOL_FIELDS));
+        resets
+    }
+
+    fn field_resets<T: PartialEq + Copy>(
+        &self,
+        seed: &Self,
+        fields: &[fn(&mut Self) -> &mut T],
+    ) -> Vec<Self> {
+        fields
+            .iter()
+            .filter_map(|field| {
+                let seed_value = *field(&mut seed.clone());
+                let mut reset = self.clone();
+                if *field(&mut reset) == seed_value {
+                    return None;
+                }
+                *field(&mut reset) = seed_value;
+                Some(reset)
+            })
+            .collect()
+    }
+
+    /// Copies of this packet with a header field or TCP option value replaced by the operand Zephyr compared it against, used by the [`super::i2s::I2SFieldMutator`].
+    pub fn i2s_replacements(&self, operands: &[CmpOperands]) -> Vec<Self> {
+        let mut replacements = Self::field_replacements(self, operands, &Self::U16_FIELDS);
+        replacements.extend(Self::field_replacements(self, operands, &Self::U32_FIELDS));
+        replacements.extend(self.option_replacements(operands));
+        replacements
+    }
+
+    fn field_replacements<T: Copy + Into<u64> + TryFrom<u64>>(
+        &self,
+        operands: &[CmpOperands],
+        fields: &[fn(&mut Self) -> &mut T],
+    ) -> Vec<Self> {
+        let mut replacements = vec![];
+        for field in fields {
+            let value = *field(&mut self.clone());
+            for replacement in i2s_values(value.into(), size_of::<T>(), operands) {
+                let Ok(replacement) = T::try_from(replacement) else {
+                    continue;
+                };
+                let mut replaced = self.clone();
+                *field(&mut replaced) = replacement;
+                replacements.push(replaced);
+            }
+        }
+        replacements
+    }
+
+    fn option_replacements(&self, operands: &[CmpOperands]) -> Vec<Self> {
+        let Ok(options) = self.tcp.options_iterator().collect::<Result<Vec<_>, _>>() else {
+            return vec![];
+        };
+
+        let mut replacements = vec![];
+        for (i, option) in options.iter().enumerate() {
+            let values: Vec<TcpOptionElement> = match *option {
+                TcpOptionElement::MaximumSegmentSize(mss) => i2s_values(mss.into(), 2, operands)
+                    .into_iter()
+                    .map(|v| TcpOptionElement::MaximumSegmentSize(v as u16))
+                    .collect(),
+                TcpOptionElement::WindowScale(shift) => i2s_values(shift.into(), 1, operands)
+                    .into_iter()
+                    .map(|v| TcpOptionElement::WindowScale(v as u8))
+                    .collect(),
+                TcpOptionElement::Timestamp(value, echo) => i2s_values(value.into(), 4, operands)
+                    .into_iter()
+                    .map(|v| TcpOptionElement::Timestamp(v as u32, echo))
+                    .chain(
+                        i2s_values(echo.into(), 4, operands)
+                            .into_iter()
+                            .map(|v| TcpOptionElement::Timestamp(value, v as u32)),
+                    )
+                    .collect(),
+                _ => vec![],
+            };
+            for value in values {
+                let mut replaced_options = options.clone();
+                replaced_options[i] = value;
+                let mut replaced = self.clone();
+                // the replaced option has the same length, so the IPv4 total length stays valid
+                if replaced.tcp.set_options(&replaced_options).is_ok() {
+                    replacements.push(replaced);
+                }
+            }
+        }
+        replacements
+    }
+
+    const U16_FIELDS: [fn(&mut Self) -> &mut u16; 4] = [
+        Self::tcp_source_port,
+        Self::tcp_destination_port,
+        Self::tcp_window_size,
+        Self::tcp_urgent_pointer,
+    ];
+    const U32_FIELDS: [fn(&mut Self) -> &mut u32; 2] =
+        [Self::tcp_sequence_number, Self::tcp_acknowledgment_number];
+    const BOOL_FIELDS: [fn(&mut Self) -> &mut bool; 9] = [
+        Self::tcp_ns,
+        Self::tcp_fin,
+        Self::tcp_syn,
+        Self::tcp_rst,
+        Self::tcp_psh,
+        Self::tcp_ack,
+        Self::tcp_urg,
+        Self::tcp_ece,
+        Self::tcp_cwr,
+    ];
+
+    fn tcp_options_len(&self) -> usize {
+        let mut tcp = Vec::new();
+        self.tcp.write(&mut tcp).unwrap();
+        tcp.len() - TCP_MIN_HEADER_LEN
+    }
+
+    /// Rebuild the packet, optionally without TCP options, keeping only the first `payload_len` bytes of the payload.
+    ///
+    /// Works on the serialized packet, since the IPv4 total length and the TCP data offset need to match the new contents.
+    fn trimmed(&self, drop_options: bool, payload_len: usize) -> Option<Self> {
+        let mut bytes: Vec<u8> = self.into();
+        let current_payload_len = self.payload.slice().len();
+        let options_len = self.tcp_options_len();
+        let tcp_start = bytes
+            .len()
+            .checked_sub(current_payload_len + TCP_MIN_HEADER_LEN + options_len)?;
+
+        let removed_payload = current_payload_len.saturating_sub(payload_len);
+        bytes.truncate(bytes.len() - removed_payload);
+        let removed_options = if drop_options { options_len } else { 0 };
+        let options_start = tcp_start + TCP_MIN_HEADER_LEN;
+        bytes.drain(options_start..options_start + removed_options);
+        if drop_options {
+            // data offset in 32 bit words in the upper nibble, keep the reserved bits and NS
+            bytes[tcp_start + 12] =
+                ((TCP_MIN_HEADER_LEN as u8 / 4) << 4) | (bytes[tcp_start + 12] & 0x0f);
+        }
+
+        let total_len_offset = Ethernet2Header::LEN + 2;
+        let total_len = u16::from_be_bytes([bytes[total_len_offset], bytes[total_len_offset + 1]])
+            .checked_sub((removed_payload + removed_options).try_into().ok()?)?;
+        bytes[total_len_offset..total_len_offset + 2].copy_from_slice(&total_len.to_be_bytes());
+
+        Self::try_from(bytes.as_slice()).ok()
+    }
+
     pub fn mutators() -> TcpMutators {
         int_mutators_no_crossover()
             .map(ToMappingMutator::new(
@@ -229,7 +418,33 @@ impl EtherparseInput {
             .merge(tuple_list!(BoolMutator).map(ToMappingMutator::new(
                 Self::tcp_cwr as fn(&mut EtherparseInput) -> &mut bool,
             )))
+            .merge(tuple_list!(I2SFieldMutator))
+    }
+}
+
+/// Values a field of `bytes` bytes is compared against, if it is equal to one operand the other one is returned.
+///
+/// Zephyr usually compares fields after converting them to host byte order, so the byte-swapped value is matched as well and its replacement swapped back.
+fn i2s_values(value: u64, bytes: usize, operands: &[CmpOperands]) -> BTreeSet<u64> {
+    let bits = 8 * bytes as u32;
+    let max = u64::MAX >> (64 - bits);
+    let swap = |v: u64| v.swap_bytes() >> (64 - bits);
+
+    let mut values = BTreeSet::new();
+    for operands in operands {
+        for (compared, other) in [(operands.op1, operands.op2), (operands.op2, operands.op1)] {
+            if other > max {
+                continue;
+            }
+            if compared == value {
+                values.insert(other);
+            } else if compared == swap(value) {
+                values.insert(swap(other));
+            }
+        }
     }
+    values.remove(&value);
+    values
 }
 
 pub type TcpMutators = merge_tuple_list_type!(
@@ -292,5 +507,6 @@ pub type TcpMutators = merge_tuple_list_type!(
     map_tuple_list_type!(
         tuple_list_type!(BoolMutator),
         ToMappingMutator<fn(&mut EtherparseInput) -> &mut bool>
-    )
+    ),
+    tuple_list_type!(I2SFieldMutator)
 );
@@ -0,0 +1,92 @@
+use std::borrow::Cow;
+
+use libafl::{
+    mutators::{MutationResult, Mutator},
+    state::HasRand,
+    Error, HasMetadata,
+};
+use libafl_bolts::{rands::Rand as _, Named};
+
+use crate::runner::observer::cmplog::CmpLogMetadata;
+
+use super::EtherparseInput;
+
+/// Redqueen-style input-to-state mutator: replaces a header field or TCP option value equal to an operand of a comparison Zephyr executed with the other operand, see [`EtherparseInput::i2s_replacements`].
+///
+/// Skips unless the [`crate::runner::cmplog_tracing_stage::CmpLogTracingStage`] provided the [`CmpLogMetadata`] of the current corpus entry.
+pub struct I2SFieldMutator;
+
+impl<S> Mutator<EtherparseInput, S> for I2SFieldMutator
+where
+    S: HasMetadata + HasRand,
+{
+    fn mutate(
+        &mut self,
+        state: &mut S,
+        input: &mut EtherparseInput,
+    ) -> Result<MutationResult, Error> {
+        let replacements = match state.metadata::<CmpLogMetadata>() {
+            Ok(metadata) => input.i2s_replacements(metadata.operands()),
+            Err(_) => return Ok(MutationResult::Skipped),
+        };
+        match state.rand_mut().choose(replacements) {
+            Some(replacement) => {
+                *input = replacement;
+                Ok(MutationResult::Mutated)
+            }
+            None => Ok(MutationResult::Skipped),
+        }
+    }
+}
+
+impl Named for I2SFieldMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        &Cow::Borrowed("I2SFieldMutator")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{packets::outgoing_tcp_packets, runner::observer::cmplog::CmpOperands};
+
+    use super::EtherparseInput;
+
+    #[test]
+    fn i2s_replaces_compared_fields() {
+        let mut packet = EtherparseInput::from(outgoing_tcp_packets()[0].clone());
+        let seq = *packet.tcp_sequence_number();
+        let port = *packet.tcp_destination_port();
+        let operands = [
+            // compared in host byte order
+            CmpOperands {
+                size: 4,
+                op1: 0x1234_5678,
+                op2: seq.into(),
+            },
+            // compared in network byte order
+            CmpOperands {
+                size: 2,
+                op1: port.swap_bytes().into(),
+                op2: 0x5000,
+            },
+            // too wide for any field
+            CmpOperands {
+                size: 8,
+                op1: seq.into(),
+                op2: u64::MAX,
+            },
+        ];
+
+        let mut replacements = packet.i2s_replacements(&operands);
+        assert!(replacements
+            .iter_mut()
+            .any(|r| *r.tcp_sequence_number() == 0x1234_5678));
+        assert!(replacements
+            .iter_mut()
+            .any(|r| *r.tcp_destination_port() == 0x0050));
+        assert!(replacements
+            .iter_mut()
+            .all(|r| *r.tcp_sequence_number() != u32::MAX));
+        assert!(packet.i2s_replacements(&[]).is_empty());
+    }
+}
@@ -6,7 +6,7 @@ use libafl::{
     inputs::Input,
     mutators::{MutationResult, Mutator},
     state::HasRand,
-    Error,
+    Error, HasMetadata,
 };
 use libafl_bolts::{
     generic_hash_std,
@@ -16,9 +16,11 @@ use libafl_bolts::{
 };
 use serde::{Deserialize, Serialize};
 
-use crate::runner::feedback::input_len::HasLen;
+use crate::runner::{feedback::input_len::HasLen, scheduler::StateSchedulerMetadata};
 
-use super::{EtherparseInput, ZephyrInput, ZephyrInputPart};
+use super::{
+    mutation_log::MutationLogMetadata, EtherparseInput, PartsInput, ZephyrInput, ZephyrInputPart,
+};
 
 pub type ListZephyrInputType = ListInput<EtherparseInput>;
 
@@ -106,15 +108,22 @@ impl<M: Named> LastEntryListMutator<M> {
     }
 }
 
-impl<I, S, M> Mutator<ListInput<I>, S> for LastEntryListMutator<M>
+impl<C, S, M> Mutator<C, S> for LastEntryListMutator<M>
 where
-    M: Mutator<I, S>,
+    C: PartsInput,
+    M: Mutator<C::Part, S>,
+    S: HasMetadata,
 {
-    fn mutate(&mut self, state: &mut S, input: &mut ListInput<I>) -> Result<MutationResult, Error> {
-        match input.parts.last_mut() {
-            Some(inner_input) => self.inner.mutate(state, inner_input),
-            None => Ok(MutationResult::Skipped),
+    fn mutate(&mut self, state: &mut S, input: &mut C) -> Result<MutationResult, Error> {
+        let index = input.parts().len().checked_sub(1);
+        let res = match input.parts_mut().last_mut() {
+            Some(inner_input) => self.inner.mutate(state, inner_input)?,
+            None => MutationResult::Skipped,
+        };
+        if let (MutationResult::Mutated, Some(index)) = (res, index) {
+            MutationLogMetadata::log(state, index, &self.name);
         }
+        Ok(res)
     }
 }
 
@@ -146,18 +155,23 @@ impl<M: Named> RandomEntryListMutator<M> {
     }
 }
 
-impl<I, S, M> Mutator<ListInput<I>, S> for RandomEntryListMutator<M>
+impl<C, S, M> Mutator<C, S> for RandomEntryListMutator<M>
 where
-    M: Mutator<I, S>,
-    S: HasRand,
+    C: PartsInput,
+    M: Mutator<C::Part, S>,
+    S: HasRand + HasMetadata,
 {
-    fn mutate(&mut self, state: &mut S, input: &mut ListInput<I>) -> Result<MutationResult, Error> {
+    fn mutate(&mut self, state: &mut S, input: &mut C) -> Result<MutationResult, Error> {
         let rand = state.rand_mut();
-        match input.parts.len() {
+        match input.parts().len() {
             0 => Ok(MutationResult::Skipped),
             len => {
                 let index = rand.below(unsafe { NonZero::new_unchecked(len) });
-                self.inner.mutate(state, &mut input.parts[index])
+                let res = self.inner.mutate(state, &mut input.parts_mut()[index])?;
+                if res == MutationResult::Mutated {
+                    MutationLogMetadata::log(state, index, &self.name);
+                }
+                Ok(res)
             }
         }
     }
@@ -179,6 +193,52 @@ impl<M> Named for RandomEntryListMutator<M> {
     }
 }
 
+/// Mutator only passing the parts after the prefix that leads to the state targeted by the [`crate::runner::scheduler::StateAwareScheduler`] to the inner mutator.
+pub struct PrefixPreservingListMutator<M> {
+    inner: M,
+    name: Cow<'static, str>,
+}
+
+impl<M: Named> PrefixPreservingListMutator<M> {
+    pub fn new(inner: M) -> Self {
+        let name = Cow::Owned(format!("PrefixPreservingListMutator<{}>", inner.name()));
+        Self { inner, name }
+    }
+}
+
+impl<C, S, M> Mutator<C, S> for PrefixPreservingListMutator<M>
+where
+    C: PartsInput,
+    M: Mutator<C, S>,
+    S: HasMetadata,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut C) -> Result<MutationResult, Error> {
+        // this wraps the whole mutator scheduler, so each call mutates a new input
+        MutationLogMetadata::clear(state);
+        let prefix_len = state
+            .metadata::<StateSchedulerMetadata>()
+            .map_or(0, StateSchedulerMetadata::prefix_len)
+            .min(input.parts().len());
+        let mut suffix = input.split_off(prefix_len);
+        let res = self.inner.mutate(state, &mut suffix);
+        input.append(suffix);
+        MutationLogMetadata::shift(state, prefix_len);
+        res
+    }
+
+    fn post_exec(&mut self, state: &mut S, new_corpus_id: Option<CorpusId>) -> Result<(), Error> {
+        // the mutated input was evaluated, later executions of other inputs were not mutated by these
+        MutationLogMetadata::clear(state);
+        self.inner.post_exec(state, new_corpus_id)
+    }
+}
+
+impl<M> Named for PrefixPreservingListMutator<M> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
 impl<I> ZephyrInput<I> for ListInput<I>
 where
     I: ZephyrInputPart + for<'a> TryFrom<&'a [u8]> + Clone,
@@ -2,6 +2,7 @@ use crate::runner::{
     generator::fixed::FixedZephyrInputGenerator,
     input::{
         appending::ToAppendingMutatorWrapper,
+        list::ListInput,
         stateful::{ReplayingStatefulInput, ToReplayingStatefulMutator},
     },
 };
@@ -16,30 +17,22 @@ use libafl::{
 
 use libafl_bolts::{
     map_tuple_list_type,
+    serdeany::SerdeAnyMap,
     tuples::{tuple_list, tuple_list_type, Map, Merge},
 };
-use serde::Serialize;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::time::Duration;
 
 pub mod appending;
 pub mod bool;
 pub mod etherparse;
+pub mod i2s;
 pub mod list;
+pub mod model;
+pub mod mutation_log;
 pub mod parsed;
 pub mod stateful;
 
-#[allow(dead_code)]
-type HavocStatefulInput = ReplayingStatefulInput<BytesInput>;
-#[allow(dead_code)]
-type HavocMultipartInput = MultipartInput<BytesInput>;
-#[allow(dead_code)]
-type ParsedStatefulInput = ReplayingStatefulInput<ParsedZephyrInput>;
-#[allow(dead_code)]
-type ParsedMultipartInput = MultipartInput<ParsedZephyrInput>;
-#[allow(dead_code)]
-type EtherparseStatefulInput = ReplayingStatefulInput<EtherparseInput>;
-
-pub type ZephyrInputType = EtherparseStatefulInput;
-
 use super::feedback::input_len::HasLen;
 
 pub use {etherparse::EtherparseInput, parsed::ParsedZephyrInput};
@@ -159,21 +152,156 @@ where
     }
 }
 
+/// Inputs made of a sequence of parts, one per packet. Lets the list mutators work on all containers.
+pub trait PartsInput: Sized {
+    type Part;
+
+    fn parts(&self) -> &[Self::Part];
+    fn parts_mut(&mut self) -> &mut [Self::Part];
+    /// Like [`Vec::split_off`], the returned input contains the parts from `at` on.
+    fn split_off(&mut self, at: usize) -> Self;
+    fn append(&mut self, other: Self);
+}
+
+impl<I> PartsInput for ListInput<I> {
+    type Part = I;
+
+    fn parts(&self) -> &[I] {
+        ListInput::parts(self)
+    }
+
+    fn parts_mut(&mut self) -> &mut [I] {
+        ListInput::parts_mut(self)
+    }
+
+    fn split_off(&mut self, at: usize) -> Self {
+        ListInput::parts_mut(self).split_off(at).into()
+    }
+
+    fn append(&mut self, other: Self) {
+        ListInput::parts_mut(self).append(&mut other.parts_owned());
+    }
+}
+
+impl<I> PartsInput for ReplayingStatefulInput<I> {
+    type Part = I;
+
+    fn parts(&self) -> &[I] {
+        ReplayingStatefulInput::parts(self)
+    }
+
+    fn parts_mut(&mut self) -> &mut [I] {
+        ReplayingStatefulInput::parts_mut(self)
+    }
+
+    fn split_off(&mut self, at: usize) -> Self {
+        ReplayingStatefulInput::parts_mut(self).split_off(at).into()
+    }
+
+    fn append(&mut self, other: Self) {
+        ReplayingStatefulInput::parts_mut(self).append(&mut other.parts_owned());
+    }
+}
+
+/// The parts are named by their index, like in [`ZephyrInput::parse`], so they are renamed when splitting.
+impl<I: Clone> PartsInput for MultipartInput<I> {
+    type Part = I;
+
+    fn parts(&self) -> &[I] {
+        MultipartInput::parts(self)
+    }
+
+    fn parts_mut(&mut self) -> &mut [I] {
+        MultipartInput::parts_mut(self)
+    }
+
+    fn split_off(&mut self, at: usize) -> Self {
+        let renamed = |parts: &[I]| -> Self {
+            parts
+                .iter()
+                .cloned()
+                .enumerate()
+                .map(|(i, p)| (i.to_string(), p))
+                .into()
+        };
+        let parts = MultipartInput::parts(self);
+        let at = at.min(parts.len());
+        let suffix = renamed(&parts[at..]);
+        *self = renamed(&parts[..at]);
+        suffix
+    }
+
+    fn append(&mut self, other: Self) {
+        for part in MultipartInput::parts(&other) {
+            let len = self.names().len();
+            self.add_part(len.to_string(), part.clone());
+        }
+    }
+}
+
 #[derive(Serialize)]
 struct DumpFormat<'a, I, M> {
     input: &'a I,
     metadata: &'a M,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exec_time: Option<Duration>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    disabled: bool,
 }
 
 pub fn serialize_input<S, I: Serialize>(testcase: &Testcase<I>, _state: &S) -> Vec<u8> {
-    // let testcase = state.current_testcase().unwrap();
+    serialize_corpus_entry(testcase, false)
+}
+
+/// Like [`serialize_input`], additionally keeping whether the testcase is disabled in its corpus.
+pub fn serialize_corpus_entry<I: Serialize>(testcase: &Testcase<I>, disabled: bool) -> Vec<u8> {
     let metadata = testcase.metadata_map();
     let input = testcase.input().as_ref().unwrap();
 
-    serde_json::to_string_pretty(&DumpFormat { input, metadata })
-        .unwrap()
-        .as_bytes()
-        .to_vec()
+    serde_json::to_string_pretty(&DumpFormat {
+        input,
+        metadata,
+        exec_time: *testcase.exec_time(),
+        disabled,
+    })
+    .unwrap()
+    .as_bytes()
+    .to_vec()
+}
+
+#[derive(Deserialize)]
+struct LoadFormat<I> {
+    input: I,
+    metadata: SerdeAnyMap,
+    #[serde(default)]
+    exec_time: Option<Duration>,
+    #[serde(default)]
+    disabled: bool,
+}
+
+/// Inverse of [`serialize_input`], restores the input along with its metadata.
+pub fn deserialize_input<I: DeserializeOwned>(
+    bytes: &[u8],
+) -> Result<Testcase<I>, serde_json::Error> {
+    deserialize_corpus_entry(bytes).map(|(testcase, _)| testcase)
+}
+
+/// Inverse of [`serialize_corpus_entry`], also returns whether the testcase was disabled.
+pub fn deserialize_corpus_entry<I: DeserializeOwned>(
+    bytes: &[u8],
+) -> Result<(Testcase<I>, bool), serde_json::Error> {
+    let LoadFormat {
+        input,
+        metadata,
+        exec_time,
+        disabled,
+    } = serde_json::from_slice(bytes)?;
+    let mut testcase = Testcase::new(input);
+    *testcase.metadata_map_mut() = metadata;
+    if let Some(exec_time) = exec_time {
+        testcase.set_exec_time(exec_time);
+    }
+    Ok((testcase, disabled))
 }
 
 pub fn generate_filename<I: Input>(testcase: &Testcase<I>, id: &CorpusId) -> String {
@@ -194,7 +322,7 @@ mod tests {
         runner::{
             generator::fixed::FixedZephyrInputPartGenerator,
             input::{
-                appending::AppendingMutator, etherparse::EtherparseInput, EtherparseStatefulInput,
+                appending::AppendingMutator, etherparse::EtherparseInput,
                 FixedZephyrInputGenerator, ReplayingStatefulInput, ZephyrInput, ZephyrInputPart,
             },
         },
@@ -222,9 +350,9 @@ mod tests {
         }
         let _input = take_zephyr_input::<_, EtherparseInput>(input);
         let mut generator = FixedZephyrInputGenerator::new(outgoing_tcp_packets(), true);
-        let mut state: NopState<EtherparseStatefulInput> = NopState::new();
+        let mut state: NopState<ReplayingStatefulInput<EtherparseInput>> = NopState::new();
         let input = generator.generate(&mut state).unwrap();
-        let mut input: EtherparseStatefulInput = take_zephyr_input(input);
+        let mut input: ReplayingStatefulInput<EtherparseInput> = take_zephyr_input(input);
 
         let mut inner_generator = FixedZephyrInputPartGenerator::new(outgoing_tcp_packets(), true);
         let _input_inner: EtherparseInput = inner_generator.generate(&mut state).unwrap();
@@ -0,0 +1,331 @@
+//! Input representations and mutator groups selectable at runtime, see [`InputModel`] and [`MutatorGroup`].
+
+use std::{borrow::Cow, fmt, str::FromStr};
+
+use clap::ValueEnum;
+use libafl::{
+    corpus::CorpusId,
+    inputs::BytesInput,
+    mutators::{MutationResult, Mutator},
+    Error,
+};
+use libafl_bolts::{
+    map_tuple_list_type, merge_tuple_list_type,
+    tuples::{tuple_list, tuple_list_type, Map as _, MappingFunctor, Merge as _},
+    Named,
+};
+
+use crate::runner::{
+    generator::{fixed::FixedZephyrInputPartGenerator, random::RandomTcpZephyrInputPartGenerator},
+    network::NetworkProfile,
+};
+
+use super::{
+    appending::ToAppendingMutatorWrapper,
+    list::{ToLastEntryListMutator, ToRandomEntryListMutator},
+    EtherparseInput, ParsedZephyrInput, ZephyrInputPart,
+};
+
+/// Representation of a single packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartModel {
+    /// Raw bytes, mutated with the havoc mutators
+    Bytes,
+    /// Parsed by pnet, without mutators of its own
+    Parsed,
+    /// Parsed by etherparse, with mutators for each header field
+    Etherparse,
+}
+
+/// Container holding the packets of an input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContainerModel {
+    /// [`super::list::ListInput`], supports prefix preservation and minimization
+    List,
+    /// [`super::stateful::ReplayingStatefulInput`]
+    Stateful,
+    /// LibAFL's [`libafl::inputs::MultipartInput`]
+    Multipart,
+}
+
+/// Input representation passed with `--input-model` as `<container>:<part>`, e.g. `list:etherparse`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputModel {
+    container: ContainerModel,
+    part: PartModel,
+}
+
+impl InputModel {
+    pub fn new(container: ContainerModel, part: PartModel) -> Self {
+        Self { container, part }
+    }
+
+    pub fn container(&self) -> ContainerModel {
+        self.container
+    }
+
+    pub fn part(&self) -> PartModel {
+        self.part
+    }
+
+    /// Check whether the model can be fuzzed with these mutator groups and options.
+    pub fn validate(&self, groups: &[MutatorGroup], minimize: bool) -> Result<(), Error> {
+        if self.part == PartModel::Parsed && !groups.contains(&MutatorGroup::Appending) {
+            return Err(Error::illegal_argument(
+                "Parsed parts have no mutators of their own, enable the appending mutator group",
+            ));
+        }
+        if groups.is_empty() {
+            return Err(Error::illegal_argument("Enable at least one mutator group"));
+        }
+        if minimize && *self != Self::default() {
+            return Err(Error::illegal_argument(format!(
+                "--minimize is only supported for {}",
+                Self::default()
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Default for InputModel {
+    fn default() -> Self {
+        Self::new(ContainerModel::List, PartModel::Etherparse)
+    }
+}
+
+impl fmt::Display for InputModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let container = match self.container {
+            ContainerModel::List => "list",
+            ContainerModel::Stateful => "stateful",
+            ContainerModel::Multipart => "multipart",
+        };
+        let part = match self.part {
+            PartModel::Bytes => "bytes",
+            PartModel::Parsed => "parsed",
+            PartModel::Etherparse => "etherparse",
+        };
+        write!(f, "{container}:{part}")
+    }
+}
+
+impl FromStr for InputModel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (container, part) = s
+            .split_once(':')
+            .ok_or(format!("Expected <container>:<part>, got {s}"))?;
+        let container = match container {
+            "list" => ContainerModel::List,
+            "stateful" => ContainerModel::Stateful,
+            "multipart" => ContainerModel::Multipart,
+            other => {
+                return Err(format!(
+                    "Unknown container {other}, expected list, stateful or multipart"
+                ))
+            }
+        };
+        let part = match part {
+            "bytes" => PartModel::Bytes,
+            "parsed" => PartModel::Parsed,
+            "etherparse" => PartModel::Etherparse,
+            other => {
+                return Err(format!(
+                    "Unknown part {other}, expected bytes, parsed or etherparse"
+                ))
+            }
+        };
+        Ok(Self::new(container, part))
+    }
+}
+
+/// Groups of mutators passed to MOpt, selected with `--mutator-groups`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum MutatorGroup {
+    /// The mutators of the part, applied to the last part
+    LastEntry,
+    /// The mutators of the part, applied to a random part
+    RandomEntry,
+    /// Append a packet of the initial trace or a random TCP packet
+    Appending,
+}
+
+/// Mutator that skips unless its [`MutatorGroup`] was selected.
+///
+/// Skipped mutations are not executed, so disabled groups only cost a selection in MOpt.
+pub struct GatedMutator<M> {
+    inner: M,
+    active: bool,
+}
+
+impl<I, S, M> Mutator<I, S> for GatedMutator<M>
+where
+    M: Mutator<I, S>,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error> {
+        if self.active {
+            self.inner.mutate(state, input)
+        } else {
+            Ok(MutationResult::Skipped)
+        }
+    }
+
+    fn post_exec(&mut self, state: &mut S, new_corpus_id: Option<CorpusId>) -> Result<(), Error> {
+        self.inner.post_exec(state, new_corpus_id)
+    }
+}
+
+impl<M: Named> Named for GatedMutator<M> {
+    fn name(&self) -> &Cow<'static, str> {
+        self.inner.name()
+    }
+}
+
+pub struct ToGatedMutator {
+    active: bool,
+}
+
+impl ToGatedMutator {
+    pub fn new(group: MutatorGroup, groups: &[MutatorGroup]) -> Self {
+        Self {
+            active: groups.contains(&group),
+        }
+    }
+}
+
+impl<M> MappingFunctor<M> for ToGatedMutator {
+    type Output = GatedMutator<M>;
+
+    fn apply(&mut self, from: M) -> Self::Output {
+        GatedMutator {
+            inner: from,
+            active: self.active,
+        }
+    }
+}
+
+pub type AppendingGenerators<II> = tuple_list_type!(
+    FixedZephyrInputPartGenerator<II>,
+    RandomTcpZephyrInputPartGenerator
+);
+
+/// Generators of the packets appended by the [`MutatorGroup::Appending`] group.
+pub fn appending_generators<II>(network: &NetworkProfile) -> AppendingGenerators<II> {
+    tuple_list!(
+        FixedZephyrInputPartGenerator::new(network.outgoing_tcp_packets(), true),
+        RandomTcpZephyrInputPartGenerator::new(network.clone())
+    )
+}
+
+/// All mutator groups for parts of type `II`, only the selected ones are active.
+pub type GroupedMutators<II> = merge_tuple_list_type!(
+    map_tuple_list_type!(
+        map_tuple_list_type!(<II as ZephyrInputPart>::Mutators, ToLastEntryListMutator),
+        ToGatedMutator
+    ),
+    map_tuple_list_type!(
+        map_tuple_list_type!(<II as ZephyrInputPart>::Mutators, ToRandomEntryListMutator),
+        ToGatedMutator
+    ),
+    map_tuple_list_type!(
+        map_tuple_list_type!(AppendingGenerators<II>, ToAppendingMutatorWrapper),
+        ToGatedMutator
+    )
+);
+
+/// Parts selectable with `--input-model`.
+pub trait ModelPart: ZephyrInputPart
+where
+    Vec<u8>: From<Self>,
+{
+    type GroupedMutators;
+
+    /// See [`GroupedMutators`], appended packets are addressed according to `network`.
+    fn grouped_mutators(groups: &[MutatorGroup], network: &NetworkProfile)
+        -> Self::GroupedMutators;
+}
+
+macro_rules! impl_model_part {
+    ($part:ty) => {
+        impl ModelPart for $part {
+            type GroupedMutators = GroupedMutators<$part>;
+
+            fn grouped_mutators(
+                groups: &[MutatorGroup],
+                network: &NetworkProfile,
+            ) -> Self::GroupedMutators {
+                <$part as ZephyrInputPart>::mutators()
+                    .map(ToLastEntryListMutator)
+                    .map(ToGatedMutator::new(MutatorGroup::LastEntry, groups))
+                    .merge(
+                        <$part as ZephyrInputPart>::mutators()
+                            .map(ToRandomEntryListMutator)
+                            .map(ToGatedMutator::new(MutatorGroup::RandomEntry, groups)),
+                    )
+                    .merge(
+                        appending_generators::<$part>(network)
+                            .map(ToAppendingMutatorWrapper)
+                            .map(ToGatedMutator::new(MutatorGroup::Appending, groups)),
+                    )
+            }
+        }
+    };
+}
+
+impl_model_part!(BytesInput);
+impl_model_part!(ParsedZephyrInput);
+impl_model_part!(EtherparseInput);
+
+#[cfg(test)]
+mod tests {
+    use libafl::{
+        inputs::ValueInput,
+        mutators::{numeric::IncMutator, MutationResult, Mutator},
+        state::NopState,
+    };
+    use libafl_bolts::tuples::MappingFunctor as _;
+
+    use super::{ContainerModel, InputModel, MutatorGroup, PartModel, ToGatedMutator};
+
+    #[test]
+    fn parse_input_model() {
+        for container in [
+            ContainerModel::List,
+            ContainerModel::Stateful,
+            ContainerModel::Multipart,
+        ] {
+            for part in [PartModel::Bytes, PartModel::Parsed, PartModel::Etherparse] {
+                let model = InputModel::new(container, part);
+                assert_eq!(model.to_string().parse::<InputModel>(), Ok(model));
+            }
+        }
+        assert_eq!(
+            "list:etherparse".parse::<InputModel>(),
+            Ok(InputModel::default())
+        );
+        assert!("list".parse::<InputModel>().is_err());
+        assert!("list:pnet".parse::<InputModel>().is_err());
+    }
+
+    #[test]
+    fn gated_mutator_skips_disabled_groups() {
+        let mut state: NopState<ValueInput<i32>> = NopState::new();
+        let mut input = ValueInput::new(0);
+
+        let mut disabled = ToGatedMutator::new(MutatorGroup::LastEntry, &[MutatorGroup::Appending])
+            .apply(IncMutator);
+        assert_eq!(
+            disabled.mutate(&mut state, &mut input).unwrap(),
+            MutationResult::Skipped
+        );
+
+        let mut enabled = ToGatedMutator::new(MutatorGroup::Appending, &[MutatorGroup::Appending])
+            .apply(IncMutator);
+        assert_eq!(
+            enabled.mutate(&mut state, &mut input).unwrap(),
+            MutationResult::Mutated
+        );
+    }
+}
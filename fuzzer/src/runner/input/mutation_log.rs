@@ -0,0 +1,35 @@
+use libafl::{HasMetadata, SerdeAny};
+use serde::{Deserialize, Serialize};
+
+/// Mutators applied to the input currently being executed, along with the index of the input part they changed.
+#[derive(Debug, Default, Clone, SerdeAny, Serialize, Deserialize)]
+pub struct MutationLogMetadata {
+    entries: Vec<(usize, String)>,
+}
+
+impl MutationLogMetadata {
+    pub fn entries(&self) -> &[(usize, String)] {
+        &self.entries
+    }
+
+    pub fn log<S: HasMetadata>(state: &mut S, index: usize, mutator: &str) {
+        state
+            .metadata_or_insert_with(Self::default)
+            .entries
+            .push((index, mutator.to_string()));
+    }
+
+    /// Start a new log, e.g. before mutating the next input.
+    pub fn clear<S: HasMetadata>(state: &mut S) {
+        if let Ok(log) = state.metadata_mut::<Self>() {
+            log.entries.clear();
+        }
+    }
+
+    /// Shift the indices of all entries, e.g. if the mutators only saw a suffix of the input.
+    pub fn shift<S: HasMetadata>(state: &mut S, offset: usize) {
+        if let Ok(log) = state.metadata_mut::<Self>() {
+            log.entries.iter_mut().for_each(|(i, _)| *i += offset);
+        }
+    }
+}
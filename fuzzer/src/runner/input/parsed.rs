@@ -1,5 +1,7 @@
 use crate::layers::{data_link::parse_eth, PacketParseError};
 
+use libafl::{corpus::CorpusId, inputs::Input};
+use libafl_bolts::generic_hash_std;
 use pnet::packet::{
     ethernet::{Ethernet, MutableEthernetPacket},
     ipv4::{Ipv4, MutableIpv4Packet},
@@ -199,6 +201,13 @@ impl ParsedZephyrInput {
     }
 }
 
+impl Input for ParsedZephyrInput {
+    fn generate_name(&self, _id: Option<CorpusId>) -> String {
+        let buf: Vec<u8> = self.clone().into();
+        format!("{:16x}", generic_hash_std(&buf))
+    }
+}
+
 impl Hash for ParsedZephyrInput {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         serde_json::to_string(self).unwrap().hash(state)
@@ -286,6 +295,12 @@ impl TryFrom<&[u8]> for ParsedZephyrInput {
     }
 }
 
+impl From<Vec<u8>> for ParsedZephyrInput {
+    fn from(value: Vec<u8>) -> Self {
+        ParsedZephyrInput::try_from(&value as &[u8]).unwrap()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{layers::data_link::parse_eth, packets::outgoing_tcp_packets};
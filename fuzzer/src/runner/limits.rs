@@ -0,0 +1,295 @@
+//! Limits bounding a campaign across all clients, see [`CampaignLimits`].
+
+use std::{
+    fmt, process,
+    sync::atomic::{AtomicU64, AtomicU8, Ordering},
+    time::{Duration, Instant},
+};
+
+use libafl::Error;
+use libafl_bolts::shmem::{MmapShMem, MmapShMemProvider, ShMem as _, ShMemProvider as _};
+
+/// The limit that ended a campaign.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    Time,
+    Executions,
+    Solutions,
+}
+
+impl Limit {
+    fn to_u8(self) -> u8 {
+        match self {
+            Self::Time => 1,
+            Self::Executions => 2,
+            Self::Solutions => 3,
+        }
+    }
+
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            1 => Some(Self::Time),
+            2 => Some(Self::Executions),
+            3 => Some(Self::Solutions),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Time => write!(f, "max-time"),
+            Self::Executions => write!(f, "max-execs"),
+            Self::Solutions => write!(f, "stop-after-solutions"),
+        }
+    }
+}
+
+/// Counters in shared memory, updated by all clients.
+///
+/// Followed by the union of the coverage found by the clients that stopped, as large as the coverage map.
+#[repr(C)]
+struct SharedCounters {
+    executions: AtomicU64,
+    solutions: AtomicU64,
+    /// [`Limit::to_u8`] of the first limit reached, 0 while running
+    reached: AtomicU8,
+    /// Clients that stopped after a limit was reached
+    exited: AtomicU64,
+    /// Inputs the clients did not execute because they exceed the MTU
+    oversize_drops: AtomicU64,
+}
+
+/// Limits checked by all clients after each fuzzing iteration, the first one reached stops all of them.
+///
+/// Needs to be created before launching the clients, which share its memory after forking.
+pub struct CampaignLimits {
+    max_time: Option<Duration>,
+    max_executions: Option<u64>,
+    max_solutions: Option<u64>,
+    start: Instant,
+    /// Process running the broker, which created the limits
+    launcher_pid: u32,
+    shmem: MmapShMem,
+}
+
+impl CampaignLimits {
+    pub fn new(
+        max_time: Option<Duration>,
+        max_executions: Option<u64>,
+        max_solutions: Option<u64>,
+        coverage_map_size: usize,
+    ) -> Result<Self, Error> {
+        // freshly created shared memory is zeroed, which is a valid state for all counters
+        let shmem = MmapShMemProvider::default()
+            .new_shmem(size_of::<SharedCounters>() + coverage_map_size)?;
+        Ok(Self {
+            max_time,
+            max_executions,
+            max_solutions,
+            start: Instant::now(),
+            launcher_pid: process::id(),
+            shmem,
+        })
+    }
+
+    fn counters(&self) -> &SharedCounters {
+        // the shmem is at least as large as SharedCounters, which consists of atomics only
+        unsafe { &*self.shmem.as_ptr().cast::<SharedCounters>() }
+    }
+
+    /// Size of the coverage map of Zephyr, as passed to [`CampaignLimits::new`].
+    fn coverage_map_size(&self) -> usize {
+        self.shmem.len() - size_of::<SharedCounters>()
+    }
+
+    fn coverage(&self) -> &[AtomicU8] {
+        let len = self.coverage_map_size();
+        // the coverage map follows the counters, AtomicU8 has the same layout as u8
+        unsafe {
+            std::slice::from_raw_parts(
+                self.shmem
+                    .as_ptr()
+                    .add(size_of::<SharedCounters>())
+                    .cast::<AtomicU8>(),
+                len,
+            )
+        }
+    }
+
+    /// Whether any limit is set, otherwise the campaign runs until it is stopped externally.
+    pub fn is_active(&self) -> bool {
+        self.max_time.is_some() || self.max_executions.is_some() || self.max_solutions.is_some()
+    }
+
+    /// Time since the limits were created, i.e. since the campaign was started.
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /// Executions of all clients, as reported by [`ClientLimits::update`].
+    pub fn executions(&self) -> u64 {
+        self.counters().executions.load(Ordering::Relaxed)
+    }
+
+    /// Inputs of all clients dropped because they exceed the MTU, as reported by [`ClientLimits::update`].
+    pub fn oversize_drops(&self) -> u64 {
+        self.counters().oversize_drops.load(Ordering::Relaxed)
+    }
+
+    /// The first limit reached by any client.
+    pub fn reached(&self) -> Option<Limit> {
+        Limit::from_u8(self.counters().reached.load(Ordering::Relaxed))
+    }
+
+    /// Track the progress of a client, starting with the executions, solutions and oversize drops it already reported or that are not part of this campaign.
+    pub fn client(
+        &self,
+        executions: u64,
+        solutions: usize,
+        oversize_drops: u64,
+    ) -> ClientLimits<'_> {
+        ClientLimits {
+            limits: self,
+            executions,
+            solutions,
+            oversize_drops,
+        }
+    }
+
+    /// Record that a client stopped after reaching a limit. Once all `clients` stopped, the broker is stopped as well, which does not exit on its own once its clients exited.
+    ///
+    /// Returns whether this was the last client.
+    pub fn client_exited(&self, clients: usize) -> Result<bool, Error> {
+        let exited = self.counters().exited.fetch_add(1, Ordering::AcqRel) + 1;
+        if exited < clients as u64 {
+            return Ok(false);
+        }
+        // handled by the broker like a Ctrl-C, it then stops the remaining restarter processes
+        let pid = self.launcher_pid as libc::pid_t;
+        if unsafe { libc::kill(pid, libc::SIGTERM) } != 0 {
+            return Err(Error::os_error(
+                std::io::Error::last_os_error(),
+                format!("Could not stop the broker with pid {pid}"),
+            ));
+        }
+        Ok(true)
+    }
+
+    /// Add the coverage history of a client to the union of all clients.
+    pub fn merge_coverage(&self, history: &[u8]) {
+        for (shared, &value) in self.coverage().iter().zip(history) {
+            shared.fetch_max(value, Ordering::Relaxed);
+        }
+    }
+
+    /// Number of edges covered by any client that merged its coverage.
+    pub fn coverage_edges(&self) -> usize {
+        self.coverage()
+            .iter()
+            .filter(|e| e.load(Ordering::Relaxed) != 0)
+            .count()
+    }
+
+    fn check(&self) -> Option<Limit> {
+        let counters = self.counters();
+        let limit = if self.max_time.is_some_and(|max| self.elapsed() >= max) {
+            Some(Limit::Time)
+        } else if self
+            .max_executions
+            .is_some_and(|max| counters.executions.load(Ordering::Relaxed) >= max)
+        {
+            Some(Limit::Executions)
+        } else if self
+            .max_solutions
+            .is_some_and(|max| counters.solutions.load(Ordering::Relaxed) >= max)
+        {
+            Some(Limit::Solutions)
+        } else {
+            None
+        };
+
+        if let Some(limit) = limit {
+            // only the first limit is recorded
+            let _ = counters.reached.compare_exchange(
+                0,
+                limit.to_u8(),
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            );
+        }
+        self.reached()
+    }
+}
+
+/// Progress of a single client towards the [`CampaignLimits`].
+pub struct ClientLimits<'a> {
+    limits: &'a CampaignLimits,
+    executions: u64,
+    solutions: usize,
+    oversize_drops: u64,
+}
+
+impl ClientLimits<'_> {
+    /// Report the current executions, solutions and oversize drops of the client. Returns the limit if one was reached, by this or any other client.
+    pub fn update(
+        &mut self,
+        executions: u64,
+        solutions: usize,
+        oversize_drops: u64,
+    ) -> Option<Limit> {
+        let counters = self.limits.counters();
+        counters.executions.fetch_add(
+            executions.saturating_sub(self.executions),
+            Ordering::Relaxed,
+        );
+        counters.solutions.fetch_add(
+            solutions.saturating_sub(self.solutions) as u64,
+            Ordering::Relaxed,
+        );
+        counters.oversize_drops.fetch_add(
+            oversize_drops.saturating_sub(self.oversize_drops),
+            Ordering::Relaxed,
+        );
+        self.executions = executions;
+        self.solutions = solutions;
+        self.oversize_drops = oversize_drops;
+        self.limits.check()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CampaignLimits, Limit};
+
+    #[test]
+    fn limits_are_shared_between_clients() {
+        let limits = CampaignLimits::new(None, Some(100), Some(2), 0).unwrap();
+        assert!(limits.is_active());
+
+        let mut first = limits.client(0, 0, 0);
+        let mut second = limits.client(0, 0, 2);
+        assert_eq!(first.update(4, 0, 1), None);
+        assert_eq!(second.update(5, 1, 3), None);
+        assert_eq!(first.update(5, 1, 1), Some(Limit::Solutions));
+        // the first limit reached is kept
+        assert_eq!(second.update(20, 1, 3), Some(Limit::Solutions));
+        assert_eq!(limits.executions(), 25);
+        // drops reported before the campaign started are not counted
+        assert_eq!(limits.oversize_drops(), 2);
+        // the broker is only stopped by the last client
+        assert!(!limits.client_exited(3).unwrap());
+        assert!(!limits.client_exited(3).unwrap());
+    }
+
+    #[test]
+    fn merge_coverage() {
+        let limits = CampaignLimits::new(None, None, None, 4).unwrap();
+        assert!(!limits.is_active());
+
+        limits.merge_coverage(&[0, 1, 0, 4]);
+        limits.merge_coverage(&[2, 0, 0, 1]);
+        assert_eq!(limits.coverage_edges(), 3);
+    }
+}
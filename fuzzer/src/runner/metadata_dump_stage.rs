@@ -0,0 +1,109 @@
+use libafl::{
+    stages::{RetryCountRestartHelper, Stage},
+    Error, HasMetadata, HasNamedMetadata,
+};
+use libafl_bolts::serdeany::{NamedSerdeAnyMap, SerdeAnyMap};
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+#[derive(Serialize)]
+struct DumpFormat<'a> {
+    metadata: &'a SerdeAnyMap,
+    named_metadata: &'a NamedSerdeAnyMap,
+}
+
+#[derive(Deserialize)]
+struct LoadFormat {
+    metadata: SerdeAnyMap,
+    named_metadata: NamedSerdeAnyMap,
+}
+
+/// Stage periodically writing the metadata of the state (scheduler, MOpt, feedback history, ...) to a JSON file, so it can be restored with [`restore_state_metadata`] when resuming a campaign.
+pub struct StateMetadataDumpStage {
+    path: PathBuf,
+    tmp_path: PathBuf,
+    interval: Duration,
+    last_timestamp: Instant,
+    name: Cow<'static, str>,
+}
+
+impl StateMetadataDumpStage {
+    pub fn new<P: AsRef<Path>>(path: P, interval: Duration) -> Self {
+        let path = path.as_ref().to_path_buf();
+        Self {
+            tmp_path: path.with_extension("json.tmp"),
+            path,
+            interval,
+            last_timestamp: Instant::now(),
+            name: Cow::Borrowed("StateMetadataDumpStage"),
+        }
+    }
+}
+
+impl<E, EM, S, Z> Stage<E, EM, S, Z> for StateMetadataDumpStage
+where
+    S: HasMetadata + HasNamedMetadata,
+{
+    fn should_restart(&mut self, state: &mut S) -> Result<bool, Error> {
+        RetryCountRestartHelper::no_retry(state, &self.name)
+    }
+
+    fn clear_progress(&mut self, state: &mut S) -> Result<(), Error> {
+        RetryCountRestartHelper::clear_progress(state, &self.name)
+    }
+
+    fn perform(
+        &mut self,
+        _fuzzer: &mut Z,
+        _executor: &mut E,
+        state: &mut S,
+        _manager: &mut EM,
+    ) -> Result<(), Error> {
+        if self.last_timestamp.elapsed() < self.interval {
+            return Ok(());
+        }
+
+        let dump = serde_json::to_vec(&DumpFormat {
+            metadata: state.metadata_map(),
+            named_metadata: state.named_metadata_map(),
+        })
+        .map_err(|e| Error::serialize(format!("Could not serialize state metadata: {e:?}")))?;
+
+        // write to a temporary file first, so a crash never leaves a partial dump behind
+        fs::write(&self.tmp_path, dump)?;
+        fs::rename(&self.tmp_path, &self.path)?;
+        self.last_timestamp = Instant::now();
+        Ok(())
+    }
+}
+
+/// Replace the metadata of the state with the one dumped by a [`StateMetadataDumpStage`].
+///
+/// Needs to be called before creating the components that initialize their metadata only if it is missing, like the scheduler or MOpt. The configuration of the feedbacks needs to match the dumped one.
+pub fn restore_state_metadata<S, P>(state: &mut S, path: P) -> Result<bool, Error>
+where
+    S: HasMetadata + HasNamedMetadata,
+    P: AsRef<Path>,
+{
+    if !path.as_ref().exists() {
+        return Ok(false);
+    }
+
+    let LoadFormat {
+        metadata,
+        named_metadata,
+    } = serde_json::from_slice(&fs::read(path.as_ref())?).map_err(|e| {
+        Error::serialize(format!(
+            "Could not deserialize state metadata from {:?}: {e:?}",
+            path.as_ref()
+        ))
+    })?;
+    *state.metadata_map_mut() = metadata;
+    *state.named_metadata_map_mut() = named_metadata;
+    Ok(true)
+}
@@ -0,0 +1,371 @@
+use std::borrow::Cow;
+
+use libafl::{
+    corpus::{Corpus as _, HasCurrentCorpusId},
+    executors::{Executor, ExitKind, HasObservers},
+    fuzzer::HasScheduler,
+    inputs::{BytesInput, MultipartInput},
+    observers::{MapObserver as _, ObserversTuple},
+    schedulers::RemovableScheduler,
+    stages::{RetryCountRestartHelper, Stage},
+    state::{HasCorpus, HasExecutions},
+    Error, HasMetadata, HasNamedMetadata, SerdeAny,
+};
+use libafl_bolts::tuples::{Handle, MatchName};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    corpus::StoresMetadata,
+    coverage::CoverageObserver,
+    directed::{DistanceMetadata, GuardDistances},
+    input::{
+        list::{ListInput, ListZephyrInputType},
+        mutation_log::MutationLogMetadata,
+        stateful::ReplayingStatefulInput,
+        EtherparseInput, ParsedZephyrInput, PartsInput, ZephyrInput as _,
+    },
+    network::NetworkProfile,
+    observer::{packet::PacketMetadata, state::StateKey},
+    PacketObserver,
+};
+
+/// What a minimized input needs to preserve from the original one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MinimizationGoal {
+    /// Zephyr exits the same way, e.g. by crashing, after last sending a packet in the same state, like the crash buckets of [`super::summary::CampaignSummary`]
+    ExitKind {
+        exit_kind: ExitKind,
+        last_state: Option<StateKey>,
+    },
+    /// All these entries of the state map are still set
+    StateMap(Vec<usize>),
+}
+
+impl MinimizationGoal {
+    /// Preserve the crash if the original input crashed, otherwise the states it reached.
+    pub fn new(exit_kind: ExitKind, metadata: &PacketMetadata) -> Self {
+        match exit_kind {
+            ExitKind::Ok => Self::StateMap(metadata.state_map_entries()),
+            exit_kind => Self::ExitKind {
+                exit_kind,
+                last_state: Self::last_state(metadata),
+            },
+        }
+    }
+
+    pub fn is_met(&self, exit_kind: ExitKind, metadata: &PacketMetadata) -> bool {
+        match self {
+            Self::ExitKind {
+                exit_kind: expected,
+                last_state,
+            } => exit_kind == *expected && Self::last_state(metadata) == *last_state,
+            Self::StateMap(entries) => {
+                exit_kind == ExitKind::Ok && {
+                    let reached = metadata.state_map_entries();
+                    entries.iter().all(|e| reached.contains(e))
+                }
+            }
+        }
+    }
+
+    fn last_state(metadata: &PacketMetadata) -> Option<StateKey> {
+        metadata.last_server_state().map(StateKey::from)
+    }
+}
+
+/// Marks corpus entries already handled by the [`MinimizationStage`].
+#[derive(Debug, Clone, SerdeAny, Serialize, Deserialize)]
+pub struct MinimizedMetadata {
+    original_len: usize,
+}
+
+impl MinimizedMetadata {
+    /// Number of packets before minimizing.
+    pub fn original_len(&self) -> usize {
+        self.original_len
+    }
+}
+
+/// The packets the initial inputs are generated from, the fields of the packets are reset to these when minimizing.
+pub fn minimization_seeds(network: &NetworkProfile) -> Vec<EtherparseInput> {
+    network
+        .outgoing_tcp_packets()
+        .into_iter()
+        .map(EtherparseInput::from)
+        .collect()
+}
+
+/// Delta debugging on the complements: Remove as many entries of `items` as possible while `test` still holds for the remaining ones.
+pub fn ddmin<T: Clone>(
+    items: &[T],
+    mut test: impl FnMut(&[T]) -> Result<bool, Error>,
+) -> Result<Vec<T>, Error> {
+    let mut current = items.to_vec();
+    let mut granularity = 2;
+    while current.len() >= 2 {
+        let chunk_len = current.len().div_ceil(granularity);
+        let mut reduced = false;
+        for start in (0..current.len()).step_by(chunk_len) {
+            let end = (start + chunk_len).min(current.len());
+            let complement = current[..start]
+                .iter()
+                .chain(&current[end..])
+                .cloned()
+                .collect::<Vec<_>>();
+            if test(&complement)? {
+                current = complement;
+                granularity = (granularity - 1).max(2);
+                reduced = true;
+                break;
+            }
+        }
+
+        if !reduced {
+            if granularity >= current.len() {
+                break;
+            }
+            granularity = (granularity * 2).min(current.len());
+        }
+    }
+    Ok(current)
+}
+
+/// Shrink `input` while `test` holds: First drop packets using [`ddmin`], then simplify each remaining packet using [`EtherparseInput::simplifications`].
+pub fn minimize_input(
+    input: &ListZephyrInputType,
+    seeds: &[EtherparseInput],
+    mut test: impl FnMut(&ListZephyrInputType) -> Result<bool, Error>,
+) -> Result<ListZephyrInputType, Error> {
+    let parts = ddmin(input.parts(), |parts| test(&ListInput::new(parts.to_vec())))?;
+    let mut minimized = ListInput::new(parts);
+
+    for i in 0..minimized.parts().len() {
+        // each accepted simplification makes the packet smaller or closer to a seed, so this terminates
+        'simplify: loop {
+            for simplification in minimized.parts()[i].simplifications(seeds) {
+                let mut candidate = minimized.clone();
+                candidate.parts_mut()[i] = simplification;
+                if test(&candidate)? {
+                    minimized = candidate;
+                    continue 'simplify;
+                }
+            }
+            break;
+        }
+    }
+    Ok(minimized)
+}
+
+/// Inputs the [`MinimizationStage`] can shrink, only [`ListZephyrInputType`] supports this so far.
+pub trait MinimizableInput: Sized {
+    /// Shrink the input while `test` holds, see [`minimize_input`]. Returns `None` if nothing could be removed or the representation does not support minimizing.
+    fn minimize(
+        &self,
+        _seeds: &[EtherparseInput],
+        _test: impl FnMut(&Self) -> Result<bool, Error>,
+    ) -> Result<Option<Self>, Error> {
+        Ok(None)
+    }
+}
+
+impl MinimizableInput for ListZephyrInputType {
+    fn minimize(
+        &self,
+        seeds: &[EtherparseInput],
+        test: impl FnMut(&Self) -> Result<bool, Error>,
+    ) -> Result<Option<Self>, Error> {
+        let minimized = minimize_input(self, seeds, test)?;
+        Ok((minimized.to_packets() != self.to_packets()).then_some(minimized))
+    }
+}
+
+impl MinimizableInput for ListInput<BytesInput> {}
+impl MinimizableInput for ListInput<ParsedZephyrInput> {}
+impl<I> MinimizableInput for ReplayingStatefulInput<I> {}
+impl<I> MinimizableInput for MultipartInput<I> {}
+
+/// Execute an input like the fuzzer does, including the pre and post execution hooks of all observers.
+pub fn run_observed<E, EM, I, S, Z>(
+    fuzzer: &mut Z,
+    executor: &mut E,
+    state: &mut S,
+    manager: &mut EM,
+    input: &I,
+) -> Result<ExitKind, Error>
+where
+    E: Executor<EM, I, S, Z> + HasObservers,
+    E::Observers: ObserversTuple<I, S>,
+{
+    executor.observers_mut().pre_exec_all(state, input)?;
+    let exit_kind = executor.run_target(fuzzer, state, manager, input)?;
+    executor
+        .observers_mut()
+        .post_exec_all(state, input, &exit_kind)?;
+    Ok(exit_kind)
+}
+
+/// Stage minimizing each corpus entry the first time it is scheduled, see [`minimize_input`].
+///
+/// The minimized input needs to set the same entries of the state map. It replaces the original one in the corpus, along with the packets captured while executing it and its distance to the targets of a directed campaign.
+pub struct MinimizationStage {
+    packet_observer: Handle<PacketObserver>,
+    seeds: Vec<EtherparseInput>,
+    active: bool,
+    distances: Option<(Handle<CoverageObserver>, GuardDistances)>,
+    name: Cow<'static, str>,
+}
+
+impl MinimizationStage {
+    pub fn new(
+        packet_observer: Handle<PacketObserver>,
+        network: &NetworkProfile,
+        active: bool,
+    ) -> Self {
+        Self {
+            packet_observer,
+            seeds: minimization_seeds(network),
+            active,
+            distances: None,
+            name: Cow::Borrowed("MinimizationStage"),
+        }
+    }
+
+    /// Recompute the [`DistanceMetadata`] of minimized entries from their coverage, like the [`super::directed::DistanceFeedback`] does for new ones.
+    pub fn with_distances(
+        mut self,
+        cov_observer: Handle<CoverageObserver>,
+        distances: Option<GuardDistances>,
+    ) -> Self {
+        self.distances = distances.map(|d| (cov_observer, d));
+        self
+    }
+
+    fn execute<E, EM, I, S, Z>(
+        &self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut S,
+        manager: &mut EM,
+        input: &I,
+    ) -> Result<(ExitKind, PacketMetadata), Error>
+    where
+        E: Executor<EM, I, S, Z> + HasObservers,
+        E::Observers: ObserversTuple<I, S> + MatchName,
+        S: HasMetadata,
+    {
+        // otherwise the mutators of the last input end up in the captured metadata
+        MutationLogMetadata::clear(state);
+        let exit_kind = run_observed(fuzzer, executor, state, manager, input)?;
+        let metadata = executor.observers()[&self.packet_observer].get_metadata()?;
+        Ok((exit_kind, metadata))
+    }
+}
+
+impl<E, EM, I, S, Z> Stage<E, EM, S, Z> for MinimizationStage
+where
+    E: Executor<EM, I, S, Z> + HasObservers,
+    E::Observers: ObserversTuple<I, S> + MatchName,
+    I: MinimizableInput + PartsInput + Clone,
+    S: HasCorpus<I> + HasCurrentCorpusId + HasExecutions + HasMetadata + HasNamedMetadata,
+    S::Corpus: StoresMetadata,
+    Z: HasScheduler<I, S>,
+    Z::Scheduler: RemovableScheduler<I, S>,
+{
+    fn should_restart(&mut self, state: &mut S) -> Result<bool, Error> {
+        RetryCountRestartHelper::no_retry(state, &self.name)
+    }
+
+    fn clear_progress(&mut self, state: &mut S) -> Result<(), Error> {
+        RetryCountRestartHelper::clear_progress(state, &self.name)
+    }
+
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut S,
+        manager: &mut EM,
+    ) -> Result<(), Error> {
+        if !self.active {
+            return Ok(());
+        }
+        let Some(id) = state.current_corpus_id()? else {
+            return Ok(());
+        };
+
+        let input = {
+            let testcase = state.corpus().get(id)?.borrow();
+            if testcase.has_metadata::<MinimizedMetadata>() {
+                return Ok(());
+            }
+            testcase
+                .input()
+                .clone()
+                .ok_or(Error::empty_optional("Corpus entry has no input"))?
+        };
+
+        let (exit_kind, metadata) = self.execute(fuzzer, executor, state, manager, &input)?;
+        let goal = MinimizationGoal::new(exit_kind, &metadata);
+        let minimized = input.minimize(&self.seeds, |candidate| {
+            let (exit_kind, metadata) =
+                self.execute(fuzzer, executor, state, manager, candidate)?;
+            Ok(goal.is_met(exit_kind, &metadata))
+        })?;
+
+        let marker = MinimizedMetadata {
+            original_len: input.parts().len(),
+        };
+        let Some(minimized) = minimized else {
+            state.corpus().get(id)?.borrow_mut().add_metadata(marker);
+            return state.corpus().store_metadata(id);
+        };
+
+        log::info!(
+            "Minimized corpus entry {id} from {} to {} packets",
+            input.parts().len(),
+            minimized.parts().len()
+        );
+        let (_, metadata) = self.execute(fuzzer, executor, state, manager, &minimized)?;
+        let distance = self.distances.as_ref().map(|(cov_observer, distances)| {
+            distances.input_distance(&executor.observers()[cov_observer].to_vec())
+        });
+        let mut testcase = state.corpus().get(id)?.borrow().clone();
+        testcase.set_input(minimized);
+        testcase.add_metadata(metadata);
+        testcase.add_metadata(marker);
+        match distance {
+            Some(Some(distance)) => testcase.add_metadata(DistanceMetadata { distance }),
+            // the minimized input no longer reaches a guard with a distance
+            Some(None) => {
+                testcase.metadata_map_mut().remove::<DistanceMetadata>();
+            }
+            None => {}
+        }
+        let prev = state.corpus_mut().replace(id, testcase)?;
+        fuzzer.scheduler_mut().on_replace(state, id, &prev)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ddmin, minimization_seeds, EtherparseInput, NetworkProfile};
+
+    #[test]
+    fn ddmin_finds_relevant_entries() {
+        let items = (0..10).collect::<Vec<_>>();
+        let minimized =
+            ddmin(&items, |items| Ok(items.contains(&3) && items.contains(&7))).unwrap();
+        assert_eq!(minimized, vec![3, 7]);
+    }
+
+    #[test]
+    fn simplifications_stay_valid() {
+        let seeds = minimization_seeds(&NetworkProfile::default());
+        for seed in &seeds {
+            for simplification in seed.simplifications(&seeds) {
+                let bytes: Vec<u8> = (&simplification).into();
+                assert!(EtherparseInput::try_from(bytes.as_slice()).is_ok());
+            }
+        }
+    }
+}
@@ -1,47 +1,66 @@
-use std::{ffi::CStr, sync::LazyLock, time::Duration};
+use std::{ffi::CStr, time::Duration};
 
 use libafl::Error;
 use libafl_bolts::shmem::ShMemDescription;
-use smoltcp::wire::IpAddress;
 
-#[cfg(feature = "coverage_stability")]
 pub mod calibration_log_stage;
-
 pub mod client;
+pub mod cmplog_tracing_stage;
+pub mod corpus;
+pub mod coverage;
+pub mod directed;
 pub mod executor;
 pub mod feedback;
 pub mod fuzzer;
 pub mod generator;
 pub mod input;
+pub mod limits;
+pub mod metadata_dump_stage;
+pub mod minimizer;
+pub mod network;
 pub mod objective;
 pub mod observer;
+pub mod pool;
+pub mod scheduler;
+pub mod stability;
+pub mod summary;
 
 pub use {
     client::connect_to_zephyr,
     executor::ZepyhrExecutor,
     fuzzer::fuzz,
-    observer::packet::{PacketMetadataFeedback, PacketObserver},
+    network::NetworkProfile,
+    observer::{
+        liveness::LivenessObserver,
+        packet::{PacketMetadataFeedback, PacketObserver},
+    },
 };
 
-pub const ZEPHYR_IP: IpAddress = IpAddress::v4(192, 0, 2, 1);
-pub const ZEPHYR_PORT: u16 = 4242;
-pub const CLIENT_PORT: u16 = 13377;
-#[cfg(feature = "coverage_stability")]
-pub const SETUP_TIMEOUT: Duration = Duration::from_millis(300 + 200); // time waited until client attempts to send data
-#[cfg(feature = "coverage_stability")]
-pub const INTER_SEND_WAIT: Duration = Duration::from_millis(200);
-
-#[cfg(not(feature = "coverage_stability"))]
 pub const SETUP_TIMEOUT: Duration = Duration::from_millis(300); // time waited until client attempts to send data
-#[cfg(not(feature = "coverage_stability"))]
 pub const INTER_SEND_WAIT: Duration = Duration::from_millis(100);
 
-pub static IPV6_LINK_LOCAL_ADDR: LazyLock<IpAddress> = LazyLock::new(|| {
-    IpAddress::v6(
-        0xfe80, 0x0000, 0x0000, 0x0000, 0x0200, 0x5eff, 0xfe00, 0x53ff,
-    )
-});
-pub const CLIENT_MAC_ADDR: [u8; 6] = [0x00, 0x00, 0x5e, 0x00, 0x53, 0xff];
+/// How long to wait for Zephyr while exchanging packets, defaults to [`SETUP_TIMEOUT`] and [`INTER_SEND_WAIT`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timing {
+    /// Time waited until the client attempts to send data
+    pub setup_timeout: Duration,
+    /// Time waited for responses after each packet
+    pub inter_send_wait: Duration,
+}
+
+impl Default for Timing {
+    fn default() -> Self {
+        Self {
+            setup_timeout: SETUP_TIMEOUT,
+            inter_send_wait: INTER_SEND_WAIT,
+        }
+    }
+}
+
+pub const LIVENESS_PROBE_MESSAGE: &[u8] = b"FTZ liveness probe\n";
+pub const LIVENESS_PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+/// Start of the ephemeral port range, see [`NetworkProfile::probe_port`].
+pub const LIVENESS_PROBE_PORT: u16 = 49152;
 
 pub(crate) fn get_path(shmem_desc: &ShMemDescription) -> Result<&str, Error> {
     CStr::from_bytes_until_nul(&shmem_desc.id)
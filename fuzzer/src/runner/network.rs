@@ -0,0 +1,309 @@
+//! Addresses of Zephyr and the client, see [`NetworkProfile`].
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use libafl::SerdeAny;
+use pnet::util::MacAddr;
+use serde::{Deserialize, Serialize};
+use smoltcp::wire::{IpAddress, IpCidr, Ipv4Address, Ipv6Address};
+
+use crate::packets::outgoing_tcp_packets;
+
+use super::{input::EtherparseInput, LIVENESS_PROBE_PORT};
+
+/// Largest MTU the shmem driver of Zephyr can receive, for jumbo frames.
+pub const JUMBO_MTU: usize = 9000;
+pub const DEFAULT_MTU: usize = 1500;
+/// Smallest MTU IPv6 works with, see RFC 8200.
+pub const MIN_MTU: usize = 1280;
+/// Destination and source MAC and EtherType, not counted in the MTU.
+pub const ETHERNET_HEADER_LEN: usize = 14;
+/// Size of the receive buffer of Zephyr's shmem driver, longer frames can not be delivered.
+pub const MAX_FRAME_LEN: usize = JUMBO_MTU + ETHERNET_HEADER_LEN;
+
+/// What to do with frames of an input that exceed the MTU of the link.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OversizePolicy {
+    /// Cut the frame to the largest size the link carries
+    Truncate,
+    /// Do not execute the input, so it produces no coverage and is never kept
+    #[default]
+    Drop,
+    /// Send the frame anyway as a giant frame, to test how Zephyr handles them. Still cut to [`MAX_FRAME_LEN`]
+    Deliver,
+}
+
+/// Inputs not executed because of [`OversizePolicy::Drop`], kept in the metadata of the state.
+#[derive(Debug, Clone, Copy, Default, SerdeAny, Serialize, Deserialize)]
+pub struct OversizeDropsMetadata {
+    pub dropped: u64,
+    /// Whether the input passed to the executor last was dropped, so the feedbacks can skip it
+    #[serde(default)]
+    pub last_dropped: bool,
+}
+
+/// Addresses and ports used to talk to Zephyr, these need to match the network configuration in its `prj.conf`.
+///
+/// Read by the auto-responders, the smoltcp client of the liveness probe and the generators. Defaults to the setup of the echo server sample the packet trace in [`crate::packets`] was recorded with.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkProfile {
+    zephyr_mac: MacAddr,
+    zephyr_ipv4: Ipv4Addr,
+    zephyr_port: u16,
+    client_mac: MacAddr,
+    client_ipv4: Ipv4Addr,
+    ipv4_prefix_len: u8,
+    /// Global address of the client, its link local address is derived from `client_mac`
+    client_ipv6: Ipv6Addr,
+    ipv6_prefix_len: u8,
+    ipv6_gateway: Ipv6Addr,
+    client_port: u16,
+    /// Hardware address announced in the ARP replies sent to Zephyr
+    router_mac: MacAddr,
+    /// MTU of the link, Zephyr's interface uses the one the fuzzer writes into the shmem. Up to [`JUMBO_MTU`]
+    mtu: usize,
+    oversize_frames: OversizePolicy,
+}
+
+impl Default for NetworkProfile {
+    fn default() -> Self {
+        Self {
+            zephyr_mac: MacAddr::new(0x02, 0x00, 0x5e, 0x00, 0x53, 0x31),
+            zephyr_ipv4: Ipv4Addr::new(192, 0, 2, 1),
+            zephyr_port: 4242,
+            client_mac: MacAddr::new(0x00, 0x00, 0x5e, 0x00, 0x53, 0xff),
+            client_ipv4: Ipv4Addr::new(192, 0, 2, 2),
+            ipv4_prefix_len: 24,
+            client_ipv6: Ipv6Addr::new(0xfdaa, 0, 0, 0, 0, 0, 0, 1),
+            ipv6_prefix_len: 64,
+            ipv6_gateway: Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 0x100),
+            client_port: 13377,
+            router_mac: MacAddr::new(0x02, 0x00, 0x5e, 0x00, 0x53, 0x31),
+            mtu: DEFAULT_MTU,
+            oversize_frames: OversizePolicy::default(),
+        }
+    }
+}
+
+impl NetworkProfile {
+    pub fn zephyr_mac(&self) -> [u8; 6] {
+        self.zephyr_mac.octets()
+    }
+
+    pub fn zephyr_ipv4(&self) -> [u8; 4] {
+        self.zephyr_ipv4.octets()
+    }
+
+    pub fn zephyr_port(&self) -> u16 {
+        self.zephyr_port
+    }
+
+    /// Address and port the client connects to.
+    pub fn zephyr_endpoint(&self) -> (IpAddress, u16) {
+        (Ipv4Address::from(self.zephyr_ipv4).into(), self.zephyr_port)
+    }
+
+    pub fn client_mac(&self) -> [u8; 6] {
+        self.client_mac.octets()
+    }
+
+    pub fn client_ipv4(&self) -> [u8; 4] {
+        self.client_ipv4.octets()
+    }
+
+    pub fn client_port(&self) -> u16 {
+        self.client_port
+    }
+
+    /// Ephemeral source port of the liveness probe, never [`Self::client_port`], so its SYN cannot land on a connection an input left open.
+    pub fn probe_port(&self) -> u16 {
+        if self.client_port == LIVENESS_PROBE_PORT {
+            LIVENESS_PROBE_PORT + 1
+        } else {
+            LIVENESS_PROBE_PORT
+        }
+    }
+
+    pub fn router_mac(&self) -> [u8; 6] {
+        self.router_mac.octets()
+    }
+
+    pub fn mtu(&self) -> usize {
+        self.mtu
+    }
+
+    pub fn oversize_frames(&self) -> OversizePolicy {
+        self.oversize_frames
+    }
+
+    /// Apply the [`OversizePolicy`] to the frames of an input, `None` if the input is dropped.
+    ///
+    /// Frames shorter than the Ethernet minimum are sent as they are.
+    pub fn fit_to_link(&self, frames: Vec<Vec<u8>>) -> Option<Vec<Vec<u8>>> {
+        let max_frame_len = self.mtu + ETHERNET_HEADER_LEN;
+        frames
+            .into_iter()
+            .map(|mut frame| {
+                if frame.len() <= max_frame_len {
+                    return Some(frame);
+                }
+                match self.oversize_frames {
+                    OversizePolicy::Truncate => {
+                        frame.truncate(max_frame_len);
+                        Some(frame)
+                    }
+                    OversizePolicy::Drop => None,
+                    OversizePolicy::Deliver => {
+                        frame.truncate(MAX_FRAME_LEN);
+                        Some(frame)
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// Link local address of the client, derived from its MAC address using EUI-64 like Zephyr does.
+    pub fn client_link_local(&self) -> IpAddress {
+        let [a, b, c, d, e, f] = self.client_mac();
+        IpAddress::v6(
+            0xfe80,
+            0,
+            0,
+            0,
+            u16::from_be_bytes([a ^ 0x02, b]),
+            u16::from_be_bytes([c, 0xff]),
+            u16::from_be_bytes([0xfe, d]),
+            u16::from_be_bytes([e, f]),
+        )
+    }
+
+    /// Addresses assigned to the interface of the smoltcp client.
+    pub fn client_cidrs(&self) -> [IpCidr; 3] {
+        [
+            IpCidr::new(
+                Ipv4Address::from(self.client_ipv4).into(),
+                self.ipv4_prefix_len,
+            ),
+            IpCidr::new(self.client_link_local(), 64),
+            IpCidr::new(
+                Ipv6Address::from(self.client_ipv6).into(),
+                self.ipv6_prefix_len,
+            ),
+        ]
+    }
+
+    /// Zephyr is the only other host, so all IPv4 traffic is routed to it.
+    pub fn ipv4_gateway(&self) -> Ipv4Address {
+        self.zephyr_ipv4.into()
+    }
+
+    pub fn ipv6_gateway(&self) -> Ipv6Address {
+        self.ipv6_gateway.into()
+    }
+
+    /// The TCP packets of the recorded trace, sent from this profile's client to its Zephyr instead.
+    pub fn outgoing_tcp_packets(&self) -> Vec<Vec<u8>> {
+        outgoing_tcp_packets()
+            .into_iter()
+            .map(|p| {
+                let mut packet = EtherparseInput::from(p);
+                packet.retarget(self);
+                packet.into()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use smoltcp::wire::IpAddress;
+
+    use crate::packets::outgoing_tcp_packets;
+
+    use crate::runner::LIVENESS_PROBE_PORT;
+
+    use super::{NetworkProfile, OversizePolicy, MAX_FRAME_LEN};
+
+    #[test]
+    fn default_profile_matches_recorded_trace() {
+        let profile = NetworkProfile::default();
+        assert_eq!(
+            profile.client_link_local(),
+            IpAddress::v6(0xfe80, 0, 0, 0, 0x0200, 0x5eff, 0xfe00, 0x53ff)
+        );
+        assert_eq!(profile.outgoing_tcp_packets(), outgoing_tcp_packets());
+    }
+
+    #[test]
+    fn probe_and_trace_use_different_ports() {
+        for client_port in [13377, LIVENESS_PROBE_PORT] {
+            let profile: NetworkProfile =
+                toml::from_str(&format!("client_port = {client_port}")).unwrap();
+            assert_ne!(profile.probe_port(), profile.client_port());
+            for packet in profile.outgoing_tcp_packets() {
+                // source port of the TCP header behind the Ethernet and IPv4 headers
+                let source_port = u16::from_be_bytes([packet[34], packet[35]]);
+                assert_eq!(source_port, client_port);
+                assert_ne!(source_port, profile.probe_port());
+            }
+        }
+    }
+
+    #[test]
+    fn partial_profile() {
+        let profile: NetworkProfile = toml::from_str(
+            r#"
+            zephyr_ipv4 = "10.0.0.1"
+            client_mac = "00:00:5e:00:53:01"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(profile.zephyr_ipv4(), [10, 0, 0, 1]);
+        assert_eq!(profile.client_mac(), [0x00, 0x00, 0x5e, 0x00, 0x53, 0x01]);
+        assert_eq!(profile.zephyr_port(), 4242);
+
+        let retargeted = profile.outgoing_tcp_packets();
+        assert_eq!(retargeted.len(), outgoing_tcp_packets().len());
+        for packet in retargeted {
+            assert_eq!(packet[6..12], profile.client_mac());
+            assert_eq!(packet[30..34], profile.zephyr_ipv4());
+        }
+    }
+
+    #[test]
+    fn oversize_frames_follow_policy() {
+        let frames = vec![vec![0; 60], vec![1; 1600]];
+        let mut profile = NetworkProfile::default();
+        assert_eq!(profile.oversize_frames(), OversizePolicy::Drop);
+        assert_eq!(profile.fit_to_link(frames.clone()), None);
+        assert_eq!(
+            profile.fit_to_link(vec![vec![0; 60], vec![1; 1514]]),
+            Some(vec![vec![0; 60], vec![1; 1514]])
+        );
+
+        profile.oversize_frames = OversizePolicy::Truncate;
+        assert_eq!(
+            profile.fit_to_link(frames.clone()),
+            Some(vec![vec![0; 60], vec![1; 1514]])
+        );
+
+        profile.oversize_frames = OversizePolicy::Deliver;
+        assert_eq!(profile.fit_to_link(frames.clone()), Some(frames.clone()));
+        // larger than Zephyr's receive buffer
+        assert_eq!(
+            profile.fit_to_link(vec![vec![1; 10_000]]),
+            Some(vec![vec![1; MAX_FRAME_LEN]])
+        );
+
+        let jumbo: NetworkProfile = toml::from_str(
+            r#"
+            mtu = 9000
+            oversize_frames = "drop"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(jumbo.fit_to_link(frames.clone()), Some(frames));
+    }
+}
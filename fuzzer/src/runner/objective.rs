@@ -1,19 +1,43 @@
 use std::borrow::Cow;
 
+use base64::prelude::*;
 use libafl::{
     corpus::Testcase,
     executors::ExitKind,
     feedbacks::{Feedback, StateInitializer},
     Error, HasMetadata as _, SerdeAny,
 };
-use libafl_bolts::Named;
+use libafl_bolts::{
+    tuples::{Handle, MatchNameRef},
+    Named,
+};
 use serde::{Deserialize, Serialize};
 
+use crate::{
+    pcap::write_pcap,
+    runner::observer::{
+        liveness::LivenessObserver,
+        net_stats::{NetStats, NetStatsObserver},
+    },
+};
+
 #[derive(Debug, Clone, SerdeAny, Serialize, Deserialize)]
 struct ExitKindMetadata {
     exit_kind: ExitKind,
 }
 
+/// Short description of why a solution is a solution, based on the metadata added by the objective feedbacks in this module.
+pub fn objective_kind<I>(testcase: &Testcase<I>) -> String {
+    match testcase.metadata::<ExitKindMetadata>() {
+        Ok(ExitKindMetadata { exit_kind }) if *exit_kind != ExitKind::Ok => {
+            format!("{exit_kind:?}")
+        }
+        _ if testcase.has_metadata::<LivenessMetadata>() => "LivenessProbeFailed".to_string(),
+        _ if testcase.has_metadata::<NetStatsLeakMetadata>() => "NetStatsLeak".to_string(),
+        _ => "Unknown".to_string(),
+    }
+}
+
 /// Feedback that marks inputs that result in an [`ExitKind::Crash`] as interesting. Additionally adds the [`ExitKind`] to a metadata field.
 pub struct CrashLoggingFeedback {
     exit_kind: Option<ExitKind>,
@@ -70,3 +94,174 @@ impl<EM, I, OT, S> Feedback<EM, I, OT, S> for CrashLoggingFeedback {
         Ok(())
     }
 }
+
+#[derive(Debug, Clone, SerdeAny, Serialize, Deserialize)]
+struct LivenessMetadata {
+    probe_pcap: String,
+    probe_packets: usize,
+}
+
+/// Feedback that marks inputs after which Zephyr was still running but failed the liveness probe of a [`LivenessObserver`] as interesting.
+///
+/// Adds the trace of the failed probe to a metadata field.
+pub struct LivenessFeedback {
+    liveness_observer: Handle<LivenessObserver>,
+    failed: bool,
+}
+
+impl LivenessFeedback {
+    pub fn new(liveness_observer: Handle<LivenessObserver>) -> Self {
+        Self {
+            liveness_observer,
+            failed: false,
+        }
+    }
+}
+
+impl<S> StateInitializer<S> for LivenessFeedback {}
+
+impl Named for LivenessFeedback {
+    fn name(&self) -> &Cow<'static, str> {
+        &Cow::Borrowed("LivenessFeedback")
+    }
+}
+
+impl<EM, I, OT, S> Feedback<EM, I, OT, S> for LivenessFeedback
+where
+    OT: MatchNameRef,
+{
+    fn is_interesting(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &I,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error> {
+        let observer = observers
+            .get(&self.liveness_observer)
+            .ok_or(Error::illegal_argument(
+            "Could not retrieve LivenessObserver, make sure you pass it to the executor in the OT.",
+        ))?;
+        self.failed = observer.alive() == Some(false);
+        if self.failed {
+            log::info!("Liveness probe failed");
+        }
+        Ok(self.failed)
+    }
+
+    fn append_metadata(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        observers: &OT,
+        testcase: &mut Testcase<I>,
+    ) -> Result<(), Error> {
+        // is_interesting is skipped if an earlier objective, e.g. a crash, already fired, so the flag may be left over from another execution
+        self.failed = false;
+        let observer = observers
+            .get(&self.liveness_observer)
+            .ok_or(Error::illegal_argument(
+            "Could not retrieve LivenessObserver, make sure you pass it to the executor in the OT.",
+        ))?;
+        if observer.alive() != Some(false) {
+            return Ok(());
+        }
+
+        let mut writer = Vec::new();
+        write_pcap(
+            &observer
+                .get_packets()
+                .iter()
+                .map(|(d, p)| (d, &**p))
+                .collect::<Vec<_>>(),
+            &mut writer,
+        )?;
+
+        testcase.add_metadata(LivenessMetadata {
+            probe_pcap: BASE64_STANDARD.encode(writer),
+            probe_packets: observer.get_packets().len(),
+        });
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, SerdeAny, Serialize, Deserialize)]
+struct NetStatsLeakMetadata {
+    baseline: NetStats,
+    end: NetStats,
+}
+
+/// Feedback that marks inputs as interesting if Zephyr still holds network buffers or contexts at the end of an execution, even though no TCP connection is left.
+///
+/// Compares against the baseline the [`NetStatsObserver`] took after booting Zephyr.
+pub struct NetStatsLeakFeedback {
+    net_stats_observer: Handle<NetStatsObserver>,
+    leak: Option<NetStatsLeakMetadata>,
+}
+
+impl NetStatsLeakFeedback {
+    pub fn new(net_stats_observer: Handle<NetStatsObserver>) -> Self {
+        Self {
+            net_stats_observer,
+            leak: None,
+        }
+    }
+}
+
+impl<S> StateInitializer<S> for NetStatsLeakFeedback {}
+
+impl Named for NetStatsLeakFeedback {
+    fn name(&self) -> &Cow<'static, str> {
+        &Cow::Borrowed("NetStatsLeakFeedback")
+    }
+}
+
+impl<EM, I, OT, S> Feedback<EM, I, OT, S> for NetStatsLeakFeedback
+where
+    OT: MatchNameRef,
+{
+    fn is_interesting(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &I,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error> {
+        let observer = observers
+            .get(&self.net_stats_observer)
+            .ok_or(Error::illegal_argument(
+            "Could not retrieve NetStatsObserver, make sure you pass it to the executor in the OT.",
+        ))?;
+
+        self.leak = match (observer.baseline(), observer.stats()) {
+            (Some(baseline), Some(end)) if end.current.leaks_compared_to(&baseline.current) => {
+                log::info!(
+                    "Network resource leak detected: {:?} -> {:?}",
+                    baseline,
+                    end
+                );
+                Some(NetStatsLeakMetadata {
+                    baseline: *baseline,
+                    end: *end,
+                })
+            }
+            _ => None,
+        };
+        Ok(self.leak.is_some())
+    }
+
+    fn append_metadata(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _observers: &OT,
+        testcase: &mut Testcase<I>,
+    ) -> Result<(), Error> {
+        if let Some(leak) = self.leak.take() {
+            testcase.add_metadata(leak);
+        }
+        Ok(())
+    }
+}
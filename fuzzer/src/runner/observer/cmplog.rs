@@ -0,0 +1,123 @@
+use std::{borrow::Cow, collections::BTreeSet, mem::size_of, ptr};
+
+use libafl::{observers::Observer, Error, SerdeAny};
+use libafl_bolts::{
+    shmem::{MmapShMem, ShMem, ShMemDescription},
+    Named,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{shmem::get_shmem, CMPLOG_SHMEM_SIZE};
+
+/// Operand pairs kept per comparison site, needs to match `CMPLOG_SLOT_ENTRIES` in `coverage.c`.
+const SLOT_ENTRIES: usize = 8;
+
+/// Needs to match `struct cmplog_operands` in `coverage.c`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct RawOperands {
+    v0: u64,
+    v1: u64,
+}
+
+/// Needs to match `struct cmplog_slot` in `coverage.c`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct RawSlot {
+    hits: u32,
+    size: u8,
+    _is_const: u8,
+    _pad: [u8; 2],
+    operands: [RawOperands; SLOT_ENTRIES],
+}
+
+/// Operands of a comparison Zephyr executed, `size` is the width of the compared integers in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct CmpOperands {
+    pub size: u8,
+    pub op1: u64,
+    pub op2: u64,
+}
+
+/// Operands of the comparisons executed by the corpus entry currently being mutated, written by the [`crate::runner::cmplog_tracing_stage::CmpLogTracingStage`].
+#[derive(Debug, Clone, Default, SerdeAny, Serialize, Deserialize)]
+pub struct CmpLogMetadata {
+    operands: Vec<CmpOperands>,
+}
+
+impl CmpLogMetadata {
+    pub fn new(operands: Vec<CmpOperands>) -> Self {
+        Self { operands }
+    }
+
+    pub fn operands(&self) -> &[CmpOperands] {
+        &self.operands
+    }
+}
+
+/// Observer owning the shmem Zephyr logs the operands of its comparisons into when built with `-fsanitize-coverage=trace-cmp`.
+///
+/// Reading the operands is comparatively expensive, so it is left to [`CmpLogObserver::operands`] instead of every execution.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CmpLogObserver {
+    #[serde(skip)]
+    shmem: Option<MmapShMem>,
+}
+
+impl CmpLogObserver {
+    pub fn new(id: usize) -> Result<Self, Error> {
+        Ok(Self {
+            shmem: Some(get_shmem(CMPLOG_SHMEM_SIZE, id, "cmplog")?),
+        })
+    }
+
+    /// Observer without a shmem, so it can stay in the observers without `--cmplog`.
+    pub fn disabled() -> Self {
+        Self { shmem: None }
+    }
+
+    pub fn shmem_description(&self) -> Result<ShMemDescription, Error> {
+        self.shmem
+            .as_ref()
+            .map(ShMem::description)
+            .ok_or(Error::illegal_state("CmpLogObserver has no shmem"))
+    }
+
+    /// Distinct operands logged during the last execution.
+    pub fn operands(&self) -> Vec<CmpOperands> {
+        let Some(shmem) = self.shmem.as_ref() else {
+            return vec![];
+        };
+        let slots = shmem.len() / size_of::<RawSlot>();
+        let mut operands = BTreeSet::new();
+        for i in 0..slots {
+            let slot = unsafe { ptr::read_volatile(shmem.as_ptr().cast::<RawSlot>().add(i)) };
+            let logged = (slot.hits as usize).min(SLOT_ENTRIES);
+            operands.extend(slot.operands[..logged].iter().map(|o| CmpOperands {
+                size: slot.size,
+                op1: o.v0,
+                op2: o.v1,
+            }));
+        }
+        operands.into_iter().collect()
+    }
+}
+
+impl<I, S> Observer<I, S> for CmpLogObserver {
+    fn pre_exec(&mut self, _state: &mut S, _input: &I) -> Result<(), Error> {
+        if let Some(shmem) = self.shmem.as_mut() {
+            shmem.fill(0);
+        }
+        Ok(())
+    }
+
+    fn pre_exec_child(&mut self, state: &mut S, input: &I) -> Result<(), Error> {
+        self.pre_exec(state, input)
+    }
+}
+
+impl Named for CmpLogObserver {
+    fn name(&self) -> &Cow<'static, str> {
+        &Cow::Borrowed("cmplog-observer")
+    }
+}
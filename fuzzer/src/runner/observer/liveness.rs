@@ -0,0 +1,72 @@
+use std::{borrow::Cow, time::Duration};
+
+use libafl::{executors::ExitKind, observers::Observer, Error};
+use libafl_bolts::Named;
+use serde::{Deserialize, Serialize};
+
+use crate::direction::Source;
+
+/// Observer storing the outcome of the liveness probe run after the fuzzed packets.
+///
+/// The probe is a fresh handshake and echo exchange on a new connection. If Zephyr is still running but cannot serve it, the input likely leaked some resource.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LivenessObserver {
+    alive: Option<bool>,
+    packets: Vec<(Duration, Source<Vec<u8>>)>,
+}
+
+impl LivenessObserver {
+    pub fn new() -> Self {
+        Self {
+            alive: None,
+            packets: vec![],
+        }
+    }
+
+    /// `None` if no probe was run, e.g. because Zephyr already exited.
+    pub fn alive(&self) -> Option<bool> {
+        self.alive
+    }
+
+    pub fn get_packets(&self) -> &Vec<(Duration, Source<Vec<u8>>)> {
+        &self.packets
+    }
+
+    pub fn set_probe_result(&mut self, alive: bool, packets: Vec<(Duration, Source<Vec<u8>>)>) {
+        self.alive = Some(alive);
+        self.packets = packets;
+    }
+}
+
+impl Default for LivenessObserver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<I, S> Observer<I, S> for LivenessObserver {
+    fn pre_exec(&mut self, _state: &mut S, _input: &I) -> Result<(), Error> {
+        self.alive = None;
+        self.packets.clear();
+        Ok(())
+    }
+
+    fn pre_exec_child(&mut self, state: &mut S, input: &I) -> Result<(), Error> {
+        self.pre_exec(state, input)
+    }
+
+    fn post_exec_child(
+        &mut self,
+        _state: &mut S,
+        _input: &I,
+        _exit_kind: &ExitKind,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Named for LivenessObserver {
+    fn name(&self) -> &Cow<'static, str> {
+        &Cow::Borrowed("liveness-observer")
+    }
+}
@@ -1,2 +1,5 @@
+pub mod cmplog;
+pub mod liveness;
+pub mod net_stats;
 pub mod packet;
 pub mod state;
@@ -0,0 +1,234 @@
+use std::{
+    borrow::Cow,
+    hint, ptr,
+    sync::atomic::{fence, AtomicU32, Ordering},
+};
+
+use libafl::{executors::ExitKind, observers::Observer, Error};
+use libafl_bolts::{
+    shmem::{MmapShMem, ShMem, ShMemDescription},
+    Named,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{shmem::get_shmem, NET_STATS_SHMEM_SIZE};
+
+/// Written by Zephyr once the stats page is valid, see `SHMEM_NET_STATS_MAGIC` in `eth_shmem_priv.h`.
+const NET_STATS_MAGIC: u32 = 0x53545a46;
+/// Zephyr updates the page whenever a packet or buffer is allocated or freed, a read racing it is retried this often.
+const READ_ATTEMPTS: usize = 1000;
+
+/// Counters of Zephyr's network buffers and connections, needs to match `struct shmem_net_stats_values` in Zephyr.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetStatsValues {
+    pub rx_pkt_free: u32,
+    pub tx_pkt_free: u32,
+    pub rx_buf_free: u32,
+    pub tx_buf_free: u32,
+    pub tcp_connections: u32,
+    pub tcp_unacked_len: u32,
+    pub net_contexts: u32,
+}
+
+impl NetStatsValues {
+    /// Whether any counter is more extreme than in `other`, i.e. fewer free buffers or more connections, contexts or unacked data.
+    pub fn exceeds(&self, other: &Self) -> bool {
+        self.rx_pkt_free < other.rx_pkt_free
+            || self.tx_pkt_free < other.tx_pkt_free
+            || self.rx_buf_free < other.rx_buf_free
+            || self.tx_buf_free < other.tx_buf_free
+            || self.tcp_connections > other.tcp_connections
+            || self.tcp_unacked_len > other.tcp_unacked_len
+            || self.net_contexts > other.net_contexts
+    }
+
+    /// Combine the most extreme values of both.
+    pub fn most_extreme(&self, other: &Self) -> Self {
+        Self {
+            rx_pkt_free: self.rx_pkt_free.min(other.rx_pkt_free),
+            tx_pkt_free: self.tx_pkt_free.min(other.tx_pkt_free),
+            rx_buf_free: self.rx_buf_free.min(other.rx_buf_free),
+            tx_buf_free: self.tx_buf_free.min(other.tx_buf_free),
+            tcp_connections: self.tcp_connections.max(other.tcp_connections),
+            tcp_unacked_len: self.tcp_unacked_len.max(other.tcp_unacked_len),
+            net_contexts: self.net_contexts.max(other.net_contexts),
+        }
+    }
+
+    /// Whether resources are still held without any TCP connection left to hold them.
+    pub fn leaks_compared_to(&self, baseline: &Self) -> bool {
+        self.tcp_connections == 0
+            && (self.rx_pkt_free < baseline.rx_pkt_free
+                || self.tx_pkt_free < baseline.tx_pkt_free
+                || self.rx_buf_free < baseline.rx_buf_free
+                || self.tx_buf_free < baseline.tx_buf_free
+                || self.net_contexts > baseline.net_contexts)
+    }
+}
+
+/// Layout of the stats page, needs to match `struct shmem_net_stats` in Zephyr.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct RawNetStats {
+    magic: u32,
+    updates: u32,
+    /// Odd while Zephyr writes the page
+    sequence: u32,
+    current: NetStatsValues,
+    extreme: NetStatsValues,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct NetStats {
+    pub updates: u32,
+    /// Values at the time of reading
+    pub current: NetStatsValues,
+    /// Most extreme values since Zephyr booted
+    pub extreme: NetStatsValues,
+}
+
+/// Observer reading the network stats page the patched Zephyr shmem driver exports next to the coverage map.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NetStatsObserver {
+    #[serde(skip)]
+    shmem: Option<MmapShMem>,
+    baseline: Option<NetStats>,
+    stats: Option<NetStats>,
+}
+
+impl NetStatsObserver {
+    pub fn new(id: usize) -> Result<Self, Error> {
+        Ok(Self {
+            shmem: Some(get_shmem(NET_STATS_SHMEM_SIZE, id, "stats")?),
+            baseline: None,
+            stats: None,
+        })
+    }
+
+    pub fn shmem_description(&self) -> Result<ShMemDescription, Error> {
+        self.shmem
+            .as_ref()
+            .map(ShMem::description)
+            .ok_or(Error::illegal_state("NetStatsObserver has no shmem"))
+    }
+
+    /// Read the stats page, `None` if Zephyr did not write it (yet) or was killed while writing it.
+    pub fn read(&self) -> Option<NetStats> {
+        let raw_ptr = self.shmem.as_ref()?.as_ptr().cast::<RawNetStats>();
+        let sequence =
+            unsafe { AtomicU32::from_ptr(ptr::addr_of!((*raw_ptr).sequence).cast_mut()) };
+        for _ in 0..READ_ATTEMPTS {
+            let before = sequence.load(Ordering::Acquire);
+            if before % 2 == 1 {
+                hint::spin_loop();
+                continue;
+            }
+            let raw = unsafe { ptr::read_volatile(raw_ptr) };
+            fence(Ordering::Acquire);
+            if sequence.load(Ordering::Relaxed) != before {
+                continue;
+            }
+            return (raw.magic == NET_STATS_MAGIC).then_some(NetStats {
+                updates: raw.updates,
+                current: raw.current,
+                extreme: raw.extreme,
+            });
+        }
+        log::warn!("Could not read a consistent net stats page");
+        None
+    }
+
+    /// Remember the current stats as the reference for a clean state, e.g. after booting Zephyr.
+    pub fn take_baseline(&mut self) {
+        self.baseline = self.read();
+    }
+
+    pub fn baseline(&self) -> Option<&NetStats> {
+        self.baseline.as_ref()
+    }
+
+    /// Stats at the end of the last execution
+    pub fn stats(&self) -> Option<&NetStats> {
+        self.stats.as_ref()
+    }
+}
+
+impl<I, S> Observer<I, S> for NetStatsObserver {
+    fn pre_exec(&mut self, _state: &mut S, _input: &I) -> Result<(), Error> {
+        if let Some(shmem) = self.shmem.as_mut() {
+            shmem.fill(0);
+        }
+        self.baseline = None;
+        self.stats = None;
+        Ok(())
+    }
+
+    fn pre_exec_child(&mut self, state: &mut S, input: &I) -> Result<(), Error> {
+        self.pre_exec(state, input)
+    }
+
+    fn post_exec(
+        &mut self,
+        _state: &mut S,
+        _input: &I,
+        _exit_kind: &ExitKind,
+    ) -> Result<(), Error> {
+        self.stats = self.read();
+        Ok(())
+    }
+
+    fn post_exec_child(
+        &mut self,
+        state: &mut S,
+        input: &I,
+        exit_kind: &ExitKind,
+    ) -> Result<(), Error> {
+        self.post_exec(state, input, exit_kind)
+    }
+}
+
+impl Named for NetStatsObserver {
+    fn name(&self) -> &Cow<'static, str> {
+        &Cow::Borrowed("net-stats-observer")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NetStatsValues;
+
+    #[test]
+    fn leaks_and_extremes() {
+        let baseline = NetStatsValues {
+            rx_pkt_free: 10,
+            tx_pkt_free: 10,
+            rx_buf_free: 20,
+            tx_buf_free: 20,
+            tcp_connections: 0,
+            tcp_unacked_len: 0,
+            net_contexts: 1,
+        };
+        assert!(!baseline.leaks_compared_to(&baseline));
+        assert!(!baseline.exceeds(&baseline));
+
+        let open_connection = NetStatsValues {
+            rx_pkt_free: 9,
+            tcp_connections: 1,
+            net_contexts: 2,
+            ..baseline
+        };
+        assert!(!open_connection.leaks_compared_to(&baseline));
+        assert!(open_connection.exceeds(&baseline));
+
+        let leaked = NetStatsValues {
+            tcp_connections: 0,
+            ..open_connection
+        };
+        assert!(leaked.leaks_compared_to(&baseline));
+
+        let combined = leaked.most_extreme(&open_connection);
+        assert_eq!(combined, open_connection);
+        assert!(!baseline.exceeds(&combined));
+    }
+}
@@ -1,4 +1,8 @@
-use crate::{direction::Source, pcap::write_pcap};
+use crate::{
+    direction::Source,
+    pcap::{read_pcap, write_pcap, write_pcapng},
+    runner::input::mutation_log::MutationLogMetadata,
+};
 use base64::prelude::*;
 use libafl::{
     corpus::Testcase,
@@ -6,6 +10,7 @@ use libafl::{
     feedbacks::{Feedback, StateInitializer},
     observers::Observer,
     // replaying::ObserverWithMetadata,
+    state::HasExecutions,
     Error,
     HasMetadata,
     SerdeAny,
@@ -22,15 +27,21 @@ use std::{
     time::{Duration, SystemTime},
 };
 
-use super::state::PacketState;
+use super::state::{PacketState, StateKey};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PacketObserver {
     packets: Vec<(Duration, Vec<u8>)>,
     states: Vec<Source<PacketState>>,
+    /// Number of input parts sent before each entry in `states`
+    prefix_lens: Vec<usize>,
+    input_parts_sent: usize,
     state_map: Vec<u8>,
     start_time: SystemTime,
     use_state_diffs: bool,
+    use_hitcounts: bool,
+    execution: u64,
+    mutations: Vec<(usize, String)>,
 }
 
 // impl ObserverWithMetadata for PacketObserver {
@@ -40,7 +51,8 @@ pub struct PacketObserver {
 // }
 
 impl PacketObserver {
-    pub fn new(use_state_diffs: bool) -> Self {
+    /// With `use_hitcounts`, the state map counts how often each state (or transition) was seen instead of only marking it. Bucketing these counts is left to a [`libafl::observers::HitcountsMapObserver`] wrapping the map.
+    pub fn new(use_state_diffs: bool, use_hitcounts: bool) -> Self {
         let state_map_size = if use_state_diffs {
             PacketState::array_size() * PacketState::array_size()
         } else {
@@ -50,9 +62,14 @@ impl PacketObserver {
         Self {
             packets: vec![],
             states: vec![],
+            prefix_lens: vec![],
+            input_parts_sent: 0,
             state_map: vec![0; state_map_size],
             start_time: SystemTime::now(),
             use_state_diffs,
+            use_hitcounts,
+            execution: 0,
+            mutations: vec![],
         }
     }
 
@@ -64,6 +81,12 @@ impl PacketObserver {
         &self.packets
     }
 
+    /// Add a packet that is part of the input, as opposed to packets sent during setup or auto-responses.
+    pub fn add_input_packet(&mut self, packet: Vec<u8>) {
+        self.input_parts_sent += 1;
+        self.add_packet(Source::Client(packet));
+    }
+
     pub fn add_packet(&mut self, packet: Source<Vec<u8>>) {
         let current_state = packet.map(|p| PacketState::from(p.as_slice()));
 
@@ -95,10 +118,15 @@ impl PacketObserver {
         };
 
         if let Some(offset) = offset {
-            self.state_map[offset] = 1;
+            self.state_map[offset] = if self.use_hitcounts {
+                self.state_map[offset].saturating_add(1)
+            } else {
+                1
+            };
         }
 
         self.states.push(current_state);
+        self.prefix_lens.push(self.input_parts_sent);
         self.packets
             .push((self.start_time.elapsed().unwrap(), packet.inner()));
     }
@@ -107,6 +135,13 @@ impl PacketObserver {
         prev_idx * PacketState::array_size() + current_idx
     }
 
+    /// Distinct states sent by Zephyr, along with the number of input parts needed to first reach them.
+    pub fn reached_states(&self) -> Vec<(StateKey, usize)> {
+        PacketMetadata::first_server_states(
+            self.states.iter().zip(self.prefix_lens.iter().copied()),
+        )
+    }
+
     pub fn get_metadata(&self) -> Result<PacketMetadata, Error> {
         let hash = generic_hash_std(self.get_packets());
 
@@ -121,14 +156,14 @@ impl PacketObserver {
         )?;
         let pcap = BASE64_STANDARD.encode(writer);
 
-        let states = self
-            .states
-            .iter()
-            .map(|s| format!("{:?}", s))
-            .collect::<Vec<_>>();
+        // one digit per entry: 0 if unset, otherwise log2 + 1 of the classified hitcount, so binary maps stay 0/1
         let state_map = self
             .state_map
             .iter()
+            .map(|s| match s {
+                0 => 0,
+                s => s.trailing_zeros() + 1,
+            })
             .map(|s| s.to_string())
             .collect::<Vec<_>>()
             .join("");
@@ -136,18 +171,30 @@ impl PacketObserver {
         Ok(PacketMetadata {
             hash,
             pcap,
-            states,
+            states: self.states.clone(),
+            prefix_lens: self.prefix_lens.clone(),
             state_map,
+            execution: self.execution,
+            mutations: self.mutations.clone(),
         })
     }
 }
 
-impl<I, S> Observer<I, S> for PacketObserver {
-    fn pre_exec(&mut self, _state: &mut S, _input: &I) -> Result<(), Error> {
+impl<I, S> Observer<I, S> for PacketObserver
+where
+    S: HasMetadata + HasExecutions,
+{
+    fn pre_exec(&mut self, state: &mut S, _input: &I) -> Result<(), Error> {
         self.packets.clear();
         self.states.clear();
+        self.prefix_lens.clear();
+        self.input_parts_sent = 0;
         self.state_map.fill(0);
         self.start_time = SystemTime::now();
+        self.mutations = state
+            .metadata::<MutationLogMetadata>()
+            .map(|log| log.entries().to_vec())
+            .unwrap_or_default();
 
         Ok(())
     }
@@ -156,13 +203,19 @@ impl<I, S> Observer<I, S> for PacketObserver {
         self.pre_exec(state, input)
     }
 
+    /// The executor counts the execution once it starts running the target, i.e. after [`Observer::pre_exec`].
+    fn post_exec(&mut self, state: &mut S, _input: &I, _exit_kind: &ExitKind) -> Result<(), Error> {
+        self.execution = *state.executions();
+        Ok(())
+    }
+
     fn post_exec_child(
         &mut self,
-        _state: &mut S,
-        _input: &I,
-        _exit_kind: &ExitKind,
+        state: &mut S,
+        input: &I,
+        exit_kind: &ExitKind,
     ) -> Result<(), Error> {
-        Ok(())
+        self.post_exec(state, input, exit_kind)
     }
 }
 
@@ -176,8 +229,147 @@ impl Named for PacketObserver {
 pub struct PacketMetadata {
     hash: u64,
     pcap: String,
-    states: Vec<String>,
+    states: Vec<Source<PacketState>>,
+    /// Number of input parts sent before each entry in `states`
+    #[serde(default)]
+    prefix_lens: Vec<usize>,
     state_map: String,
+    /// Execution count of the client when the packets were captured
+    #[serde(default)]
+    execution: u64,
+    /// Mutators that produced the input, along with the index of the input part they changed
+    #[serde(default)]
+    mutations: Vec<(usize, String)>,
+}
+
+impl PacketMetadata {
+    /// Distinct states sent by Zephyr, along with the number of input parts needed to first reach them.
+    pub fn reached_states(&self) -> Vec<(StateKey, usize)> {
+        Self::first_server_states(self.states.iter().zip(self.prefix_lens.iter().copied()))
+    }
+
+    /// States of all captured packets, along with who sent them.
+    pub fn states(&self) -> &[Source<PacketState>] {
+        &self.states
+    }
+
+    /// State of the last packet Zephyr sent, `None` if it did not send any.
+    pub fn last_server_state(&self) -> Option<&PacketState> {
+        self.states
+            .iter()
+            .rev()
+            .find(|s| matches!(s, Source::Server(..)))
+            .map(Deref::deref)
+    }
+
+    /// Distinct pairs of consecutive states ending in a state sent by Zephyr, like the entries of the state diff map.
+    pub fn transitions(&self) -> Vec<(StateKey, StateKey)> {
+        let mut transitions = vec![];
+        for pair in self.states.windows(2) {
+            if let Source::Server(state) = &pair[1] {
+                let transition = (StateKey::from(&*pair[0]), StateKey::from(state));
+                if !transitions.contains(&transition) {
+                    transitions.push(transition);
+                }
+            }
+        }
+        transitions
+    }
+
+    /// One digit per entry of the state map, see [`PacketObserver::get_metadata`].
+    pub fn state_map(&self) -> &str {
+        &self.state_map
+    }
+
+    /// Indices of the entries set in the state map.
+    pub fn state_map_entries(&self) -> Vec<usize> {
+        self.state_map
+            .chars()
+            .enumerate()
+            .filter(|(_, c)| *c != '0')
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// The captured packets along with their timestamps, as decoded from the stored pcap.
+    pub fn packets(&self) -> Result<Vec<(Duration, Vec<u8>)>, Error> {
+        let pcap = BASE64_STANDARD
+            .decode(&self.pcap)
+            .map_err(|e| Error::serialize(format!("Could not decode pcap: {e:?}")))?;
+        read_pcap(&pcap)
+    }
+
+    /// The captured packets with their direction, along with comments on their state, the execution and the mutators that changed them.
+    pub fn annotated_packets(
+        &self,
+    ) -> Result<Vec<(Duration, Source<Vec<u8>>, Vec<String>)>, Error> {
+        let annotated = self
+            .packets()?
+            .into_iter()
+            .enumerate()
+            .map(|(i, (duration, packet))| {
+                let state = self.states.get(i);
+                let source = match state {
+                    Some(Source::Server(..)) => Source::Server(packet),
+                    _ => Source::Client(packet),
+                };
+
+                let mut comments = vec![
+                    state.map_or("state: unknown".to_string(), |s| {
+                        format!("state: {:?}", **s)
+                    }),
+                    format!("execution: {}", self.execution),
+                ];
+                if let Some(part) = self.input_part(i) {
+                    comments.push(format!("input part: {part}"));
+                    let mutators = self
+                        .mutations
+                        .iter()
+                        .filter(|(p, _)| *p == part)
+                        .map(|(_, m)| m.as_str())
+                        .collect::<Vec<_>>();
+                    if !mutators.is_empty() {
+                        comments.push(format!("mutators: {}", mutators.join(", ")));
+                    }
+                }
+                (duration, source, comments)
+            })
+            .collect();
+        Ok(annotated)
+    }
+
+    /// The captured packets as pcapng, see [`PacketMetadata::annotated_packets`].
+    pub fn pcapng(&self) -> Result<Vec<u8>, Error> {
+        let mut writer = Vec::new();
+        write_pcapng(&self.annotated_packets()?, &mut writer)?;
+        Ok(writer)
+    }
+
+    /// Index of the input part the `i`-th packet was, `None` if it was sent during setup or as auto-response.
+    fn input_part(&self, i: usize) -> Option<usize> {
+        let current = *self.prefix_lens.get(i)?;
+        let prev = i
+            .checked_sub(1)
+            .and_then(|p| self.prefix_lens.get(p))
+            .copied()
+            .unwrap_or(0);
+        (current > prev).then(|| current - 1)
+    }
+
+    fn first_server_states<'a>(
+        states: impl Iterator<Item = (&'a Source<PacketState>, usize)>,
+    ) -> Vec<(StateKey, usize)> {
+        let mut reached: Vec<(StateKey, usize)> = vec![];
+        for (state, prefix_len) in states {
+            if let Source::Server(state) = state {
+                let key = StateKey::from(state);
+                if !reached.iter().any(|(s, _)| *s == key) {
+                    reached.push((key, prefix_len));
+                }
+            }
+        }
+        reached
+    }
 }
 
 /// Feedback adding packets captured by a [`PacketObserver`] to a metadata field.
@@ -250,7 +442,50 @@ impl AsMut<Self> for PacketObserver {
 
 #[cfg(test)]
 mod tests {
-    use crate::runner::{observer::state::PacketState, PacketObserver};
+    use crate::{
+        direction::Source,
+        runner::{
+            observer::{
+                packet::PacketMetadata,
+                state::{PacketState, StateKey},
+            },
+            PacketObserver,
+        },
+    };
+
+    #[test]
+    fn hitcounts() {
+        for (use_hitcounts, expected) in [(false, 1), (true, 3)] {
+            let mut observer = PacketObserver::new(false, use_hitcounts);
+            for _ in 0..3 {
+                observer.add_packet(Source::Server(vec![0; 4]));
+            }
+            observer.add_packet(Source::Client(vec![0; 4]));
+            assert_eq!(observer.get_state_map().iter().max(), Some(&expected));
+            assert_eq!(
+                observer.get_state_map().iter().filter(|e| **e > 0).count(),
+                1
+            );
+        }
+    }
+
+    #[test]
+    fn first_server_states() {
+        let states = [
+            (Source::Client(PacketState::Tcp(2)), 1),
+            (Source::Server(PacketState::Tcp(18)), 1),
+            (Source::Client(PacketState::Tcp(16)), 2),
+            (Source::Server(PacketState::Tcp(18)), 2),
+            (Source::Server(PacketState::Tcp(17)), 3),
+        ];
+        assert_eq!(
+            PacketMetadata::first_server_states(states.iter().map(|(s, l)| (s, *l))),
+            vec![
+                (StateKey::from(&PacketState::Tcp(18)), 1),
+                (StateKey::from(&PacketState::Tcp(17)), 3)
+            ]
+        );
+    }
 
     #[test]
     fn calculate_combined_offset() {
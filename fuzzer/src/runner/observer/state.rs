@@ -58,6 +58,17 @@ impl From<&PacketState> for u16 {
     }
 }
 
+/// Identity of a [`PacketState`] for comparing and counting states, its index in the state map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct StateKey(u16);
+
+impl From<&PacketState> for StateKey {
+    fn from(state: &PacketState) -> Self {
+        Self(state.into())
+    }
+}
+
 impl PacketState {
     pub const fn array_size() -> usize {
         // max value + 1
@@ -0,0 +1,236 @@
+//! Zephyr processes booted ahead of time, see [`ZephyrPool`].
+
+use std::{process::Child, thread::sleep, time::Instant};
+
+use libafl::Error;
+use libafl_bolts::shmem::{MmapShMem, MmapShMemProvider, ShMem as _, ShMemProvider as _};
+
+use crate::{direction::Source, shmem::get_shmem, smoltcp::shmem_net_device::ShmemNetworkDevice};
+
+use super::{
+    executor::{ZephyrCommand, ZephyrShMem},
+    get_path, NetworkProfile, Timing,
+};
+
+/// Private copy of a shmem Zephyr writes to, copied into the one of the observers once the instance is used.
+struct MirroredShMem {
+    own: MmapShMem,
+    observed: MmapShMem,
+    keep_boot: bool,
+}
+
+impl MirroredShMem {
+    fn sync(&mut self) {
+        self.observed.copy_from_slice(&self.own);
+    }
+}
+
+/// A Zephyr process of the [`ZephyrPool`], with its own network device and copies of the other shmems.
+pub struct PrewarmedZephyr {
+    pub child: Child,
+    device: ShmemNetworkDevice,
+    mirrors: Vec<MirroredShMem>,
+    envs: Vec<(String, String)>,
+    started: Instant,
+    last_packet: Instant,
+    boot_packets: Vec<Source<Vec<u8>>>,
+}
+
+impl PrewarmedZephyr {
+    fn new(
+        slot: usize,
+        command: &ZephyrCommand,
+        envs: &[(String, String)],
+        shmems: &[ZephyrShMem],
+        network_buf_size: usize,
+        id: usize,
+        mtu: usize,
+    ) -> Result<Self, Error> {
+        let mut device =
+            ShmemNetworkDevice::with_prefix(network_buf_size, id, &format!("net-pool{slot}"))?;
+        device.set_mtu(mtu)?;
+        device.reset();
+        let net_shmem_desc = device.get_shmem_description();
+        let mut envs = envs.to_vec();
+        set_shmem_env(&mut envs, "SHMEM_ETH_INTERFACE", get_path(&net_shmem_desc)?);
+
+        let mut mirrors = vec![];
+        for shmem in shmems {
+            let name = shmem.prefix.trim_start_matches("SHMEM_").to_lowercase();
+            let own = get_shmem(shmem.description.size, id, &format!("{name}-pool{slot}"))?;
+            set_shmem_env(&mut envs, &shmem.prefix, get_path(&own.description())?);
+            mirrors.push(MirroredShMem {
+                own,
+                observed: MmapShMemProvider::default().shmem_from_description(shmem.description)?,
+                keep_boot: shmem.keep_boot,
+            });
+        }
+
+        Ok(Self {
+            child: command.spawn(&envs)?,
+            device,
+            mirrors,
+            envs,
+            started: Instant::now(),
+            last_packet: Instant::now(),
+            boot_packets: vec![],
+        })
+    }
+
+    pub fn device(&self) -> &ShmemNetworkDevice {
+        &self.device
+    }
+
+    /// Packets exchanged while Zephyr set up its network, to be added to those of the execution.
+    pub fn take_boot_packets(&mut self) -> Vec<Source<Vec<u8>>> {
+        std::mem::take(&mut self.boot_packets)
+    }
+
+    /// Booted like [`ShmemNetworkDevice::init_zephyr`] waits for it.
+    fn is_booted(&self, timing: Timing) -> bool {
+        self.started.elapsed() >= timing.setup_timeout
+            && self.last_packet.elapsed() >= timing.inter_send_wait
+    }
+
+    /// Answer the packets Zephyr sent so far, restarting it if it exited while booting.
+    fn pump(&mut self, command: &ZephyrCommand, network: &NetworkProfile) -> Result<(), Error> {
+        if self.child.try_wait()?.is_some() {
+            log::warn!("Prewarmed Zephyr exited while booting, restarting it");
+            return self.restart(command);
+        }
+        let boot_packets = &mut self.boot_packets;
+        while self
+            .device
+            .respond_to_setup(network, &mut |p| boot_packets.push(p))?
+        {
+            self.last_packet = Instant::now();
+        }
+        Ok(())
+    }
+
+    fn restart(&mut self, command: &ZephyrCommand) -> Result<(), Error> {
+        let _ = self.child.kill();
+        self.child.wait()?;
+        self.device.reset();
+        for mirror in &mut self.mirrors {
+            mirror.own.fill(0);
+        }
+        self.child = command.spawn(&self.envs)?;
+        self.started = Instant::now();
+        self.last_packet = Instant::now();
+        self.boot_packets.clear();
+        Ok(())
+    }
+}
+
+impl Drop for PrewarmedZephyr {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Keeps Zephyr processes booting in the background, so executions do not need to wait for the setup timeout.
+///
+/// Each instance has its own network device and copies of the other shmems passed to Zephyr, e.g. the coverage map. The copies are written to the shmems of the observers once the instance is used, and exited instances are replaced right away. The instances are only serviced while the executor runs, so the pool needs no threads.
+pub struct ZephyrPool {
+    instances: Vec<PrewarmedZephyr>,
+    command: ZephyrCommand,
+    timing: Timing,
+    network: NetworkProfile,
+}
+
+impl ZephyrPool {
+    #[expect(clippy::too_many_arguments)]
+    pub fn new(
+        size: usize,
+        command: ZephyrCommand,
+        envs: &[(String, String)],
+        shmems: &[ZephyrShMem],
+        network_buf_size: usize,
+        id: usize,
+        timing: Timing,
+        network: NetworkProfile,
+    ) -> Result<Self, Error> {
+        let instances = (0..size)
+            .map(|slot| {
+                PrewarmedZephyr::new(
+                    slot,
+                    &command,
+                    envs,
+                    shmems,
+                    network_buf_size,
+                    id,
+                    network.mtu(),
+                )
+            })
+            .collect::<Result<_, _>>()?;
+        log::info!("Started a pool of {size} prewarmed Zephyr instances");
+        Ok(Self {
+            instances,
+            command,
+            timing,
+            network,
+        })
+    }
+
+    /// Answer the packets the booting instances sent, should be called regularly.
+    pub fn pump(&mut self) -> Result<(), Error> {
+        for instance in &mut self.instances {
+            instance.pump(&self.command, &self.network)?;
+        }
+        Ok(())
+    }
+
+    /// Take the instance that has been booting for the longest time, waiting for it to finish if necessary.
+    ///
+    /// Its coverage and the other shmems not marked with [`ZephyrShMem::keep_boot`] are reset, the others are copied to the observers, e.g. for taking the baseline of the net stats.
+    pub fn take(&mut self) -> Result<PrewarmedZephyr, Error> {
+        self.pump()?;
+        let index = (0..self.instances.len())
+            .min_by_key(|&i| self.instances[i].started)
+            .ok_or(Error::illegal_state("The Zephyr pool is empty"))?;
+        let mut instance = self.instances.swap_remove(index);
+
+        while !instance.is_booted(self.timing) {
+            sleep(self.timing.inter_send_wait / 5);
+            if let Err(e) = instance
+                .pump(&self.command, &self.network)
+                .and_then(|()| self.pump())
+            {
+                // keep the instance, so the pool does not shrink
+                self.instances.push(instance);
+                return Err(e);
+            }
+        }
+
+        for mirror in &mut instance.mirrors {
+            if !mirror.keep_boot {
+                mirror.own.fill(0);
+            }
+            mirror.sync();
+        }
+        Ok(instance)
+    }
+
+    /// Return an instance after its execution: its shmems are copied to the observers, then it is replaced by a new Zephyr process.
+    pub fn give_back(&mut self, mut instance: PrewarmedZephyr) -> Result<(), Error> {
+        for mirror in &mut instance.mirrors {
+            mirror.sync();
+        }
+        let restarted = instance.restart(&self.command);
+        // even if Zephyr could not be restarted, pumping the instance tries again
+        self.instances.push(instance);
+        restarted
+    }
+}
+
+/// Point the env vars `<prefix>_NAME` to another shmem of the same size.
+fn set_shmem_env(envs: &mut [(String, String)], prefix: &str, name: &str) {
+    let key = format!("{prefix}_NAME");
+    for (k, v) in envs.iter_mut() {
+        if *k == key {
+            *v = name.to_string();
+        }
+    }
+}
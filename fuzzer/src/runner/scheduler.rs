@@ -0,0 +1,339 @@
+use std::{collections::BTreeMap, num::NonZero};
+
+use libafl::{
+    corpus::{Corpus, CorpusId, Testcase},
+    schedulers::{RemovableScheduler, Scheduler},
+    state::{HasCorpus, HasRand},
+    Error, HasMetadata, SerdeAny,
+};
+use libafl_bolts::{
+    rands::Rand,
+    tuples::{Handle, MatchName, MatchNameRef as _},
+};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    corpus::StoresMetadata,
+    observer::{
+        packet::{PacketMetadata, PacketObserver},
+        state::StateKey,
+    },
+};
+
+/// Bookkeeping for a single protocol state, i.e. a packet state sent by Zephyr.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ProtocolStateInfo {
+    /// Number of executions that reached this state
+    reached: u64,
+    /// Number of times this state was selected as target
+    selected: u64,
+    /// Number of corpus entries found while this state was the target
+    discovered: u64,
+    /// Corpus entries reaching this state, along with the number of input parts needed to do so
+    testcases: Vec<(CorpusId, usize)>,
+}
+
+impl ProtocolStateInfo {
+    /// AFLNet's score: favour rarely reached states that led to new corpus entries when targeted.
+    fn score(&self) -> f64 {
+        let exercised = ((self.reached + 1) as f64).log10() * self.selected as f64;
+        let rarity = 2f64.powf(-(exercised + 1.).log10());
+        let productivity = 2f64.powf(((self.discovered + 1) as f64).ln());
+        (1000. * rarity * productivity).ceil()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TargetState {
+    state: StateKey,
+    prefix_len: usize,
+}
+
+#[derive(Debug, Default, SerdeAny, Serialize, Deserialize)]
+pub struct StateSchedulerMetadata {
+    states: BTreeMap<StateKey, ProtocolStateInfo>,
+    target: Option<TargetState>,
+}
+
+impl StateSchedulerMetadata {
+    /// Number of input parts of the current testcase that lead to the targeted state and should not be mutated.
+    pub fn prefix_len(&self) -> usize {
+        self.target.as_ref().map_or(0, |t| t.prefix_len)
+    }
+
+    pub fn target(&self) -> Option<StateKey> {
+        self.target.as_ref().map(|t| t.state)
+    }
+
+    pub fn states(&self) -> &BTreeMap<StateKey, ProtocolStateInfo> {
+        &self.states
+    }
+
+    fn record(&mut self, id: CorpusId, reached: Vec<(StateKey, usize)>) {
+        for (reached_state, prefix_len) in reached {
+            self.states
+                .entry(reached_state)
+                .or_default()
+                .testcases
+                .push((id, prefix_len));
+        }
+    }
+
+    /// Keep the counters of the states, but forget which corpus entries reach them, e.g. when resuming with new corpus ids.
+    pub fn forget_testcases(&mut self) {
+        self.states
+            .values_mut()
+            .for_each(|info| info.testcases.clear());
+        self.target = None;
+    }
+
+    fn forget(&mut self, id: CorpusId) {
+        self.states
+            .values_mut()
+            .for_each(|info| info.testcases.retain(|(e, _)| *e != id));
+    }
+}
+
+/// AFLNet-style scheduler: First pick a protocol state, weighted by how rarely it is reached and how productive fuzzing towards it has been, then a corpus entry reaching that state.
+///
+/// The number of input parts needed to reach the state is stored in [`StateSchedulerMetadata::prefix_len`], so mutators can keep that prefix intact. Falls back to the inner scheduler if it is not active or no state is known yet.
+pub struct StateAwareScheduler<CS> {
+    inner: CS,
+    packet_observer: Handle<PacketObserver>,
+    active: bool,
+}
+
+impl<CS> StateAwareScheduler<CS> {
+    pub fn new<S: HasMetadata>(
+        state: &mut S,
+        inner: CS,
+        packet_observer: Handle<PacketObserver>,
+        active: bool,
+    ) -> Self {
+        if !state.has_metadata::<StateSchedulerMetadata>() {
+            state.add_metadata(StateSchedulerMetadata::default());
+        }
+        Self {
+            inner,
+            packet_observer,
+            active,
+        }
+    }
+
+    fn reached_states<I, S: HasCorpus<I>>(
+        state: &S,
+        id: CorpusId,
+    ) -> Result<Vec<(StateKey, usize)>, Error> {
+        let testcase = state.corpus().get(id)?.borrow();
+        Ok(testcase
+            .metadata::<PacketMetadata>()
+            .map(PacketMetadata::reached_states)
+            .unwrap_or_default())
+    }
+}
+
+/// Random choice among `candidates`, weighted by their scores. `None` if there are none or no candidate has a positive score.
+pub fn weighted_choice<R: Rand, T>(rand: &mut R, candidates: Vec<(T, f64)>) -> Option<T> {
+    let total = candidates.iter().map(|(_, score)| score).sum::<f64>();
+    if candidates.is_empty() || total <= 0. {
+        return None;
+    }
+
+    let mut threshold = rand.next_float() * total;
+    let last = candidates.len() - 1;
+    for (i, (candidate, score)) in candidates.into_iter().enumerate() {
+        if threshold < score || i == last {
+            return Some(candidate);
+        }
+        threshold -= score;
+    }
+    unreachable!()
+}
+
+impl<CS, I, S> Scheduler<I, S> for StateAwareScheduler<CS>
+where
+    CS: Scheduler<I, S>,
+    S: HasCorpus<I> + HasMetadata + HasRand,
+    S::Corpus: StoresMetadata,
+{
+    /// The inner scheduler adds its metadata to the testcase after the corpus stored it, so it is stored again.
+    fn on_add(&mut self, state: &mut S, id: CorpusId) -> Result<(), Error> {
+        let reached = Self::reached_states(state, id)?;
+
+        let metadata = state.metadata_mut::<StateSchedulerMetadata>()?;
+        if let Some(target) = &metadata.target {
+            if let Some(info) = metadata.states.get_mut(&target.state) {
+                info.discovered += 1;
+            }
+        }
+        metadata.record(id, reached);
+
+        self.inner.on_add(state, id)?;
+        state.corpus().store_metadata(id)
+    }
+
+    fn on_evaluation<OT>(&mut self, state: &mut S, input: &I, observers: &OT) -> Result<(), Error>
+    where
+        OT: MatchName,
+    {
+        let observer = observers
+            .get(&self.packet_observer)
+            .ok_or(Error::illegal_argument(
+            "Could not retrieve PacketObserver, make sure you pass it to the executor in the OT.",
+        ))?;
+        let metadata = state.metadata_mut::<StateSchedulerMetadata>()?;
+        for (reached_state, _) in observer.reached_states() {
+            metadata.states.entry(reached_state).or_default().reached += 1;
+        }
+
+        self.inner.on_evaluation(state, input, observers)
+    }
+
+    fn next(&mut self, state: &mut S) -> Result<CorpusId, Error> {
+        state.metadata_mut::<StateSchedulerMetadata>()?.target = None;
+        if !self.active {
+            return self.inner.next(state);
+        }
+
+        let candidates = state
+            .metadata::<StateSchedulerMetadata>()?
+            .states
+            .iter()
+            .filter(|(_, info)| !info.testcases.is_empty())
+            .map(|(key, info)| (*key, info.score()))
+            .collect::<Vec<_>>();
+        // only states that have at least one corpus entry reaching them
+        let selected = weighted_choice(state.rand_mut(), candidates);
+
+        let Some(selected) = selected else {
+            return self.inner.next(state);
+        };
+
+        let testcases_len = state.metadata::<StateSchedulerMetadata>()?.states[&selected]
+            .testcases
+            .len();
+        // testcases_len is non-zero, since only states with corpus entries are candidates
+        let index = state
+            .rand_mut()
+            .below(unsafe { NonZero::new_unchecked(testcases_len) });
+
+        let metadata = state.metadata_mut::<StateSchedulerMetadata>()?;
+        let info = metadata.states.get_mut(&selected).unwrap();
+        info.selected += 1;
+        let (id, prefix_len) = info.testcases[index];
+        metadata.target = Some(TargetState {
+            state: selected,
+            prefix_len,
+        });
+
+        self.set_current_scheduled(state, Some(id))?;
+        Ok(id)
+    }
+
+    fn set_current_scheduled(
+        &mut self,
+        state: &mut S,
+        next_id: Option<CorpusId>,
+    ) -> Result<(), Error> {
+        self.inner.set_current_scheduled(state, next_id)
+    }
+}
+
+impl<CS, I, S> RemovableScheduler<I, S> for StateAwareScheduler<CS>
+where
+    CS: RemovableScheduler<I, S>,
+    S: HasCorpus<I> + HasMetadata + HasRand,
+    S::Corpus: StoresMetadata,
+{
+    fn on_remove(
+        &mut self,
+        state: &mut S,
+        id: CorpusId,
+        testcase: &Option<Testcase<I>>,
+    ) -> Result<(), Error> {
+        state.metadata_mut::<StateSchedulerMetadata>()?.forget(id);
+        self.inner.on_remove(state, id, testcase)
+    }
+
+    /// The replacing testcase may reach different states or need fewer input parts to do so, e.g. after minimizing it.
+    fn on_replace(&mut self, state: &mut S, id: CorpusId, prev: &Testcase<I>) -> Result<(), Error> {
+        let reached = Self::reached_states(state, id)?;
+        let metadata = state.metadata_mut::<StateSchedulerMetadata>()?;
+        metadata.forget(id);
+        metadata.record(id, reached);
+        self.inner.on_replace(state, id, prev)?;
+        state.corpus().store_metadata(id)
+    }
+}
+
+/// One of two schedulers, chosen at runtime, e.g. by the campaign config.
+pub enum EitherScheduler<A, B> {
+    Left(A),
+    Right(B),
+}
+
+impl<A, B, I, S> Scheduler<I, S> for EitherScheduler<A, B>
+where
+    A: Scheduler<I, S>,
+    B: Scheduler<I, S>,
+{
+    fn on_add(&mut self, state: &mut S, id: CorpusId) -> Result<(), Error> {
+        match self {
+            Self::Left(s) => s.on_add(state, id),
+            Self::Right(s) => s.on_add(state, id),
+        }
+    }
+
+    fn on_evaluation<OT>(&mut self, state: &mut S, input: &I, observers: &OT) -> Result<(), Error>
+    where
+        OT: MatchName,
+    {
+        match self {
+            Self::Left(s) => s.on_evaluation(state, input, observers),
+            Self::Right(s) => s.on_evaluation(state, input, observers),
+        }
+    }
+
+    fn next(&mut self, state: &mut S) -> Result<CorpusId, Error> {
+        match self {
+            Self::Left(s) => s.next(state),
+            Self::Right(s) => s.next(state),
+        }
+    }
+
+    fn set_current_scheduled(
+        &mut self,
+        state: &mut S,
+        next_id: Option<CorpusId>,
+    ) -> Result<(), Error> {
+        match self {
+            Self::Left(s) => s.set_current_scheduled(state, next_id),
+            Self::Right(s) => s.set_current_scheduled(state, next_id),
+        }
+    }
+}
+
+impl<A, B, I, S> RemovableScheduler<I, S> for EitherScheduler<A, B>
+where
+    A: RemovableScheduler<I, S>,
+    B: RemovableScheduler<I, S>,
+{
+    fn on_remove(
+        &mut self,
+        state: &mut S,
+        id: CorpusId,
+        testcase: &Option<Testcase<I>>,
+    ) -> Result<(), Error> {
+        match self {
+            Self::Left(s) => s.on_remove(state, id, testcase),
+            Self::Right(s) => s.on_remove(state, id, testcase),
+        }
+    }
+
+    fn on_replace(&mut self, state: &mut S, id: CorpusId, prev: &Testcase<I>) -> Result<(), Error> {
+        match self {
+            Self::Left(s) => s.on_replace(state, id, prev),
+            Self::Right(s) => s.on_replace(state, id, prev),
+        }
+    }
+}
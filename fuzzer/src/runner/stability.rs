@@ -0,0 +1,329 @@
+//! Edges of the coverage map that differ between executions of the same input, see [`UnstableEdges`].
+
+use std::{
+    borrow::Cow,
+    fmt::Write as _,
+    fs,
+    marker::PhantomData,
+    path::{Path, PathBuf},
+};
+
+use libafl::{
+    corpus::{Corpus as _, HasCurrentCorpusId},
+    events::{CustomBufEventResult, Event, EventFirer},
+    executors::{Executor, HasObservers},
+    monitors::{AggregatorOps, UserStats, UserStatsValue},
+    observers::{MapObserver as _, ObserversTuple},
+    stages::{RetryCountRestartHelper, Stage},
+    state::HasCorpus,
+    Error, HasMetadata, HasNamedMetadata, SerdeAny,
+};
+use libafl_bolts::tuples::{Handle, MatchName};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    corpus::StoresMetadata,
+    coverage::{guard_index, CoverageObserver},
+    minimizer::run_observed,
+};
+
+/// Guards whose coverage is unstable, masked out of the coverage feedback by the [`super::coverage::CoverageMaskObserver`].
+///
+/// Kept in the metadata of each client's state. Newly found guards are broadcast to the other clients with [`broadcast_unstable_edges`] and merged by the handler of [`unstable_edges_handler`].
+#[derive(Debug, Clone, SerdeAny, Serialize, Deserialize)]
+pub struct UnstableEdges {
+    /// One flag per guard, set once the guard is found to be unstable
+    flags: Vec<bool>,
+    /// The guards with a flag set, in ascending order, so the observer does not need to collect them after each execution
+    unstable: Vec<usize>,
+}
+
+impl UnstableEdges {
+    pub fn new(guards: usize) -> Self {
+        Self {
+            flags: vec![false; guards],
+            unstable: vec![],
+        }
+    }
+
+    pub fn guards(&self) -> usize {
+        self.flags.len()
+    }
+
+    pub fn is_unstable(&self, guard: usize) -> bool {
+        self.flags.get(guard).is_some_and(|&f| f)
+    }
+
+    /// Add a guard to the set, returns whether it was stable so far.
+    pub fn mark(&mut self, guard: usize) -> bool {
+        match self.flags.get_mut(guard) {
+            Some(flag) if !*flag => {
+                *flag = true;
+                let index = self.unstable.partition_point(|&g| g < guard);
+                self.unstable.insert(index, guard);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// All unstable guards, in ascending order.
+    pub fn unstable_guards(&self) -> &[usize] {
+        &self.unstable
+    }
+
+    /// Add the guards stored by a previous campaign. Returns the number of guards loaded, the file is ignored if it was written for a Zephyr build with a different number of guards.
+    pub fn load<P: AsRef<Path>>(&mut self, path: P) -> Result<usize, Error> {
+        let content = fs::read_to_string(path.as_ref()).map_err(|e| {
+            Error::os_error(
+                e,
+                format!("Could not read unstable edges {:?}", path.as_ref()),
+            )
+        })?;
+        let mut lines = content.lines();
+        let header = format!("# guards: {}", self.guards());
+        if lines.next() != Some(header.as_str()) {
+            log::warn!(
+                "Ignoring unstable edges {:?}, they were stored for a different Zephyr build",
+                path.as_ref()
+            );
+            return Ok(0);
+        }
+
+        let mut loaded = 0;
+        for line in lines.filter(|l| !l.trim().is_empty()) {
+            let guard = line.trim().parse::<usize>().map_err(|e| {
+                Error::serialize(format!("Invalid guard {line:?} in unstable edges: {e}"))
+            })?;
+            self.mark(guard);
+            loaded += 1;
+        }
+        Ok(loaded)
+    }
+
+    /// Write the set, one guard per line in the format of `unstable-coverage.txt`, after a header with the number of guards of the Zephyr build.
+    pub fn store<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let mut content = format!("# guards: {}\n", self.guards());
+        for guard in &self.unstable {
+            writeln!(content, "{guard}").unwrap();
+        }
+        // other clients may store the set concurrently, renaming keeps the file complete
+        let tmp_path = path
+            .as_ref()
+            .with_extension(format!("{}.tmp", std::process::id()));
+        fs::write(&tmp_path, content)?;
+        fs::rename(&tmp_path, path.as_ref()).map_err(|e| {
+            Error::os_error(
+                e,
+                format!("Could not write unstable edges {:?}", path.as_ref()),
+            )
+        })
+    }
+}
+
+/// Tag of the [`Event::CustomBuf`] with newly found unstable guards.
+const UNSTABLE_EDGES_TAG: &str = "unstable_edges";
+
+/// Send newly found unstable guards to the other clients.
+pub fn broadcast_unstable_edges<EM, I, S>(
+    manager: &mut EM,
+    state: &mut S,
+    guards: &[usize],
+) -> Result<(), Error>
+where
+    EM: EventFirer<I, S>,
+{
+    let buf = serde_json::to_vec(guards)
+        .map_err(|e| Error::serialize(format!("Could not serialize unstable edges: {e}")))?;
+    manager.fire(
+        state,
+        Event::CustomBuf {
+            buf,
+            tag: UNSTABLE_EDGES_TAG.to_string(),
+        },
+    )
+}
+
+/// Handler for [`libafl::events::HasCustomBufHandlers`] merging the guards of [`broadcast_unstable_edges`] into the [`UnstableEdges`] of the state, which are stored to `path` if they changed.
+#[allow(clippy::type_complexity)]
+pub fn unstable_edges_handler<S: HasMetadata>(
+    path: PathBuf,
+) -> Box<dyn FnMut(&mut S, &str, &[u8]) -> Result<CustomBufEventResult, Error>> {
+    Box::new(move |state, tag, buf| {
+        if tag != UNSTABLE_EDGES_TAG {
+            return Ok(CustomBufEventResult::Next);
+        }
+        let guards: Vec<usize> = serde_json::from_slice(buf)
+            .map_err(|e| Error::serialize(format!("Invalid unstable edges event: {e}")))?;
+        let unstable_edges = state.metadata_mut::<UnstableEdges>()?;
+        let new = guards
+            .into_iter()
+            .filter(|&g| unstable_edges.mark(g))
+            .count();
+        if new > 0 {
+            log::debug!("Received {new} new unstable edges from another client");
+            unstable_edges.store(&path)?;
+        }
+        Ok(CustomBufEventResult::Handled)
+    })
+}
+
+/// Marks corpus entries already calibrated by the [`UnstableEdgesStage`].
+#[derive(Debug, Clone, SerdeAny, Serialize, Deserialize)]
+pub struct CalibratedMetadata;
+
+/// Compare the coverage maps of repeated executions, returns the entries that differ in any of them.
+pub fn unstable_entries(maps: &[Vec<u8>]) -> Vec<usize> {
+    let Some((first, rest)) = maps.split_first() else {
+        return vec![];
+    };
+    (0..first.len())
+        .filter(|&i| rest.iter().any(|m| m.get(i) != Some(&first[i])))
+        .collect()
+}
+
+/// Stage executing each corpus entry several times the first time it is scheduled, adding the guards whose coverage differs to the [`UnstableEdges`].
+///
+/// New unstable guards are stored to disk right away, broadcast to the other clients and reported as the `unstable_edges` user stat.
+pub struct UnstableEdgesStage {
+    cov_observer: Handle<CoverageObserver>,
+    runs: usize,
+    path: PathBuf,
+    name: Cow<'static, str>,
+}
+
+impl UnstableEdgesStage {
+    /// Disabled if `runs` is less than 2.
+    pub fn new(cov_observer: Handle<CoverageObserver>, runs: usize, path: PathBuf) -> Self {
+        Self {
+            cov_observer,
+            runs,
+            path,
+            name: Cow::Borrowed("UnstableEdgesStage"),
+        }
+    }
+}
+
+impl<E, EM, I, S, Z> Stage<E, EM, S, Z> for UnstableEdgesStage
+where
+    E: Executor<EM, I, S, Z> + HasObservers,
+    E::Observers: ObserversTuple<I, S> + MatchName,
+    EM: EventFirer<I, S>,
+    I: Clone,
+    S: HasCorpus<I> + HasCurrentCorpusId + HasMetadata + HasNamedMetadata,
+    S::Corpus: StoresMetadata,
+{
+    fn should_restart(&mut self, state: &mut S) -> Result<bool, Error> {
+        RetryCountRestartHelper::no_retry(state, &self.name)
+    }
+
+    fn clear_progress(&mut self, state: &mut S) -> Result<(), Error> {
+        RetryCountRestartHelper::clear_progress(state, &self.name)
+    }
+
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut S,
+        manager: &mut EM,
+    ) -> Result<(), Error> {
+        if self.runs < 2 {
+            return Ok(());
+        }
+        let Some(id) = state.current_corpus_id()? else {
+            return Ok(());
+        };
+
+        let input = {
+            let mut testcase = state.corpus().get(id)?.borrow_mut();
+            if testcase.has_metadata::<CalibratedMetadata>() {
+                return Ok(());
+            }
+            testcase.add_metadata(CalibratedMetadata);
+            testcase
+                .input()
+                .clone()
+                .ok_or(Error::empty_optional("Corpus entry has no input"))?
+        };
+        state.corpus().store_metadata(id)?;
+
+        let mut maps = Vec::with_capacity(self.runs);
+        for _ in 0..self.runs {
+            run_observed(fuzzer, executor, state, manager, &input)?;
+            maps.push(executor.observers()[&self.cov_observer].to_vec());
+        }
+
+        let unstable_edges = state.metadata_mut::<UnstableEdges>()?;
+        let new = unstable_entries(&maps)
+            .into_iter()
+            .map(guard_index)
+            .filter(|&guard| unstable_edges.mark(guard))
+            .collect::<Vec<_>>();
+        if new.is_empty() {
+            return Ok(());
+        }
+
+        let total = unstable_edges.unstable_guards().len();
+        log::info!(
+            "Found {} new unstable edges in corpus entry {id}, {total} in total",
+            new.len()
+        );
+        unstable_edges.store(&self.path)?;
+        broadcast_unstable_edges(manager, state, &new)?;
+        manager.fire(
+            state,
+            Event::UpdateUserStats {
+                name: Cow::Borrowed("unstable_edges"),
+                value: UserStats::new(UserStatsValue::Number(total as u64), AggregatorOps::Max),
+                phantom: PhantomData,
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libafl::{events::CustomBufEventResult, state::NopState, HasMetadata as _};
+
+    use super::{unstable_edges_handler, unstable_entries, UnstableEdges, UNSTABLE_EDGES_TAG};
+
+    #[test]
+    fn unstable_entries_differ_in_any_run() {
+        let maps = [vec![1, 0, 2, 0], vec![1, 1, 2, 0], vec![1, 0, 4, 0]];
+        assert_eq!(unstable_entries(&maps), vec![1, 2]);
+        assert!(unstable_entries(&maps[..1]).is_empty());
+    }
+
+    #[test]
+    fn unstable_edges_are_merged_and_persisted() {
+        let path = std::env::temp_dir().join(format!("unstable-edges-{}.txt", std::process::id()));
+        let mut edges = UnstableEdges::new(8);
+        assert!(edges.mark(5));
+        assert!(!edges.mark(5));
+        assert!(!edges.mark(8));
+
+        // guards broadcast by another client
+        let mut state = NopState::<()>::new();
+        state.add_metadata(edges);
+        let mut handler = unstable_edges_handler(path.clone());
+        let buf = serde_json::to_vec(&[3, 5]).unwrap();
+        assert!(matches!(
+            handler(&mut state, "other", &buf),
+            Ok(CustomBufEventResult::Next)
+        ));
+        assert!(matches!(
+            handler(&mut state, UNSTABLE_EDGES_TAG, &buf),
+            Ok(CustomBufEventResult::Handled)
+        ));
+        let edges = state.metadata::<UnstableEdges>().unwrap();
+        assert_eq!(edges.unstable_guards(), [3, 5]);
+
+        let mut reloaded = UnstableEdges::new(8);
+        assert_eq!(reloaded.load(&path).unwrap(), 2);
+        assert_eq!(reloaded.unstable_guards(), [3, 5]);
+        // a different build invalidates the guard indices
+        assert_eq!(UnstableEdges::new(9).load(&path).unwrap(), 0);
+        std::fs::remove_file(path).unwrap();
+    }
+}
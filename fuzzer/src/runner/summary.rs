@@ -0,0 +1,164 @@
+//! Summary of a bounded campaign, see [`CampaignSummary`].
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use libafl::{corpus::Testcase, Error, HasMetadata as _};
+use serde::Serialize;
+
+use crate::commands::collect_testcase_paths;
+
+use super::{
+    input::deserialize_input,
+    limits::CampaignLimits,
+    objective::objective_kind,
+    observer::{packet::PacketMetadata, state::StateKey},
+};
+
+/// Solutions of the same kind, after which Zephyr last sent a packet in the same state.
+#[derive(Debug, Serialize)]
+pub struct CrashBucket {
+    kind: String,
+    last_state: String,
+    count: usize,
+    example: PathBuf,
+}
+
+/// Inputs of the testcases stored before a campaign started, e.g. when resuming it.
+///
+/// Identified by their inputs, as resumed testcases are stored again under the ids of the new corpus.
+#[derive(Debug, Default)]
+pub struct PreviousTestcases {
+    corpus: BTreeSet<String>,
+    solutions: BTreeSet<String>,
+}
+
+impl PreviousTestcases {
+    pub fn collect(corpus_dir: &Path, solutions_dir: &Path) -> Result<Self, Error> {
+        let inputs = |dir: &Path| -> Result<BTreeSet<String>, Error> {
+            Ok(load_all(dir)?
+                .iter()
+                .map(|(_, testcase)| input_key(testcase))
+                .collect())
+        };
+        Ok(Self {
+            corpus: inputs(corpus_dir)?,
+            solutions: inputs(solutions_dir)?,
+        })
+    }
+}
+
+/// Results of a campaign stopped by one of its [`CampaignLimits`], written as JSON once all clients exited.
+#[derive(Debug, Serialize)]
+pub struct CampaignSummary {
+    limit: String,
+    run_time_secs: f64,
+    executions: u64,
+    executions_per_sec: f64,
+    /// Inputs not executed because they exceed the MTU, see [`super::network::OversizePolicy::Drop`]
+    oversize_drops: u64,
+    /// Edges covered by the corpora of all clients
+    coverage_edges: usize,
+    /// Distinct states sent by Zephyr in the corpus and solutions found in this campaign
+    states: usize,
+    /// Distinct transitions into states sent by Zephyr in the corpus and solutions found in this campaign
+    transitions: usize,
+    /// Corpus entries added in this campaign
+    corpus_size: usize,
+    /// Solutions found in this campaign
+    solutions: usize,
+    crash_buckets: Vec<CrashBucket>,
+}
+
+impl CampaignSummary {
+    /// Collect the counters shared by the clients along with the testcases they stored, skipping the `previous` ones.
+    pub fn collect(
+        limits: &CampaignLimits,
+        corpus_dir: &Path,
+        solutions_dir: &Path,
+        previous: &PreviousTestcases,
+    ) -> Result<Self, Error> {
+        let mut corpus = load_all(corpus_dir)?;
+        corpus.retain(|(_, testcase)| !previous.corpus.contains(&input_key(testcase)));
+        let mut solutions = load_all(solutions_dir)?;
+        solutions.retain(|(_, testcase)| !previous.solutions.contains(&input_key(testcase)));
+
+        let mut states = BTreeSet::new();
+        let mut transitions = BTreeSet::new();
+        for (_, testcase) in corpus.iter().chain(&solutions) {
+            if let Ok(metadata) = testcase.metadata::<PacketMetadata>() {
+                states.extend(metadata.reached_states().into_iter().map(|(s, _)| s));
+                transitions.extend(metadata.transitions());
+            }
+        }
+
+        let mut buckets: BTreeMap<(String, Option<StateKey>), CrashBucket> = BTreeMap::new();
+        for (path, testcase) in &solutions {
+            let kind = objective_kind(testcase);
+            let last_state = testcase
+                .metadata::<PacketMetadata>()
+                .ok()
+                .and_then(PacketMetadata::last_server_state);
+            buckets
+                .entry((kind.clone(), last_state.map(StateKey::from)))
+                .or_insert_with(|| CrashBucket {
+                    kind,
+                    last_state: last_state.map_or("None".to_string(), |s| format!("{s:?}")),
+                    count: 0,
+                    example: path.clone(),
+                })
+                .count += 1;
+        }
+
+        let run_time = limits.elapsed();
+        Ok(Self {
+            limit: limits
+                .reached()
+                .map_or_else(|| "none".to_string(), |l| l.to_string()),
+            run_time_secs: run_time.as_secs_f64(),
+            executions: limits.executions(),
+            executions_per_sec: limits.executions() as f64 / run_time.as_secs_f64(),
+            oversize_drops: limits.oversize_drops(),
+            coverage_edges: limits.coverage_edges(),
+            states: states.len(),
+            transitions: transitions.len(),
+            corpus_size: corpus.len(),
+            solutions: solutions.len(),
+            crash_buckets: buckets.into_values().collect(),
+        })
+    }
+
+    pub fn store<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let content = serde_json::to_string_pretty(self)
+            .map_err(|e| Error::serialize(format!("Could not serialize summary: {e}")))?;
+        fs::write(path.as_ref(), content)
+            .map_err(|e| Error::os_error(e, format!("Could not write summary {:?}", path.as_ref())))
+    }
+}
+
+/// Compares testcases by their input.
+fn input_key(testcase: &Testcase<serde_json::Value>) -> String {
+    testcase
+        .input()
+        .as_ref()
+        .map_or_else(String::new, serde_json::Value::to_string)
+}
+
+/// All testcases in `dir`, independent of the input model they were stored with.
+fn load_all(dir: &Path) -> Result<Vec<(PathBuf, Testcase<serde_json::Value>)>, Error> {
+    if !dir.exists() {
+        return Ok(vec![]);
+    }
+    collect_testcase_paths(dir)?
+        .into_iter()
+        .map(|path| {
+            let testcase = deserialize_input(&fs::read(&path)?).map_err(|e| {
+                Error::serialize(format!("Could not load testcase {path:?}: {e:?}"))
+            })?;
+            Ok((path, testcase))
+        })
+        .collect()
+}
@@ -15,40 +15,88 @@ use crate::{
         },
         upper::UpperLayerPacket,
     },
-    runner::{CLIENT_MAC_ADDR, INTER_SEND_WAIT, IPV6_LINK_LOCAL_ADDR, SETUP_TIMEOUT},
+    runner::{
+        network::{DEFAULT_MTU, ETHERNET_HEADER_LEN, MAX_FRAME_LEN, MIN_MTU},
+        NetworkProfile, Timing,
+    },
     shmem::get_shmem,
 };
 
-use super::shmem_net_device_buffers::ShmemNetDeviceBuffer;
+use super::shmem_net_device_buffers::{ring_size, Frame, ShmemNetDeviceBuffer};
 
+/// Both directions of the shared memory link.
+///
+/// Clones share the same underlying shmem, so a clone can e.g. be handed to smoltcp temporarily.
+#[derive(Clone)]
 pub struct ShmemNetworkDevice {
     tx_shmem: ShmemNetDeviceBuffer<MmapShMem>,
     rx_shmem: ShmemNetDeviceBuffer<MmapShMem>,
+    mtu: usize,
 }
 
 impl ShmemNetworkDevice {
     pub fn new(buf_size: usize, id: usize) -> Result<Self, Error> {
-        let shmem = get_shmem(buf_size * 2 + 8, id, "net")?;
+        Self::with_prefix(buf_size, id, "net")
+    }
+
+    /// Name the shmem with a different prefix, so a client can own several devices.
+    ///
+    /// Each direction can hold `buf_size` bytes of frames, see [`ShmemNetDeviceBuffer`].
+    pub fn with_prefix(buf_size: usize, id: usize, prefix: &str) -> Result<Self, Error> {
+        let shmem = get_shmem(ring_size(buf_size) * 2, id, prefix)?;
 
         log::debug!("Created ShmemNetworkDevice");
         let (tx_shmem, rx_shmem) = ShmemNetDeviceBuffer::new(Rc::new(RefCell::new(shmem)));
-        let mut res = Self { tx_shmem, rx_shmem };
+        let mut res = Self {
+            tx_shmem,
+            rx_shmem,
+            mtu: DEFAULT_MTU,
+        };
         res.reset();
         Ok(res)
     }
 
     pub fn try_recv(&mut self) -> Option<Vec<u8>> {
-        let res = self.rx_shmem.get_data_and_set_empty();
-        if let Some(p) = res.as_ref() {
-            log::debug!("Received packet of len: {}", p.len());
+        self.try_recv_frame().map(|frame| frame.data)
+    }
+
+    /// Like [`Self::try_recv`], also returning the time Zephyr sent the packet at.
+    pub fn try_recv_frame(&mut self) -> Option<Frame> {
+        let res = self.rx_shmem.pop();
+        if let Some(frame) = res.as_ref() {
+            log::debug!(
+                "Received packet of len: {}, sent at {:?}ns",
+                frame.data.len(),
+                frame.timestamp
+            );
         }
         res
     }
 
-    pub fn send(&mut self, data: &[u8]) {
-        self.tx_shmem.prep_data(data.len()).copy_from_slice(data);
-        self.tx_shmem.send(data.len());
-        log::debug!("Sent packet of len: {}", data.len());
+    /// Drops the packet if Zephyr did not make enough space in the ring, like a congested link would.
+    ///
+    /// Frames longer than the ring can hold are an error, [`NetworkProfile::fit_to_link`] keeps inputs below [`MAX_FRAME_LEN`].
+    pub fn send(&mut self, data: &[u8]) -> Result<(), Error> {
+        if data.len() > self.tx_shmem.max_frame_len() {
+            return Err(Error::illegal_argument(format!(
+                "Packet of len {} does not fit into the ring towards Zephyr",
+                data.len()
+            )));
+        }
+        if self.tx_shmem.push(data, None) {
+            log::debug!("Sent packet of len: {}", data.len());
+        } else {
+            log::warn!(
+                "Ring towards Zephyr is full, dropped packet of len: {}",
+                data.len()
+            );
+        }
+        Ok(())
+    }
+
+    /// Whether Zephyr wrote an invalid frame into the ring towards the fuzzer since the last [`Self::reset`], see [`ShmemNetDeviceBuffer::is_corrupted`].
+    pub fn rx_corrupted(&self) -> bool {
+        self.rx_shmem.is_corrupted()
     }
 
     pub fn copy_of_tx_buffer(&self) -> ShmemNetDeviceBuffer<MmapShMem> {
@@ -59,27 +107,52 @@ impl ShmemNetworkDevice {
     ///
     /// This empties both buffers and puts them into a ready state.
     pub fn reset(&mut self) {
-        self.tx_shmem.reset();
-        self.rx_shmem.reset();
+        self.tx_shmem.reset(self.mtu);
+        self.rx_shmem.reset(self.mtu);
+    }
+
+    /// Set the MTU Zephyr configures its interface with, written to the shmem on the next [`Self::reset`].
+    pub fn set_mtu(&mut self, mtu: usize) -> Result<(), Error> {
+        if mtu < MIN_MTU {
+            return Err(Error::illegal_argument(format!(
+                "MTU {mtu} is below the {MIN_MTU} bytes IPv6 needs"
+            )));
+        }
+        if mtu + ETHERNET_HEADER_LEN > self.tx_shmem.max_frame_len() {
+            return Err(Error::illegal_argument(format!(
+                "MTU {mtu} exceeds the {} bytes of the network shmem",
+                self.tx_shmem.max_frame_len()
+            )));
+        }
+        self.mtu = mtu;
+        Ok(())
+    }
+
+    pub fn mtu(&self) -> usize {
+        self.mtu
     }
 
     pub fn get_shmem_description(&self) -> ShMemDescription {
         self.rx_shmem.description()
     }
 
-    pub fn respond_manually(parsed: DataLinkLayerPacket) -> Option<Result<Vec<u8>, Error>> {
+    /// Answer ARP and ICMPv6 neighbor or router solicitations on behalf of the client in `network`.
+    pub fn respond_manually(
+        parsed: DataLinkLayerPacket,
+        network: &NetworkProfile,
+    ) -> Option<Result<Vec<u8>, Error>> {
         if let Some(icmpv6) = parsed.upper().and_then(UpperLayerPacket::get_icmpv6) {
             match icmpv6.icmpv6_type {
                 Icmpv6Types::NeighborSolicit => {
                     log::debug!("Manually responding to icmpv6 NeighborSolicit");
-                    let res = create_response_to_icmpv6_neighbor_solicitation(&parsed, CLIENT_MAC_ADDR, *IPV6_LINK_LOCAL_ADDR).ok_or({
+                    let res = create_response_to_icmpv6_neighbor_solicitation(&parsed, network.client_mac(), network.client_link_local()).ok_or({
                         Error::illegal_argument(format!("Could not calculate return package for an incoming icmpv6 message:\n{:?}", parsed))
                     });
                     Some(res)
                 }
                 Icmpv6Types::RouterSolicit => {
                     log::debug!("Manually responding to icmpv6 RouterSolicit");
-                    let res = create_response_to_icmpv6_router_solicitation(&parsed, CLIENT_MAC_ADDR, *IPV6_LINK_LOCAL_ADDR).ok_or({
+                    let res = create_response_to_icmpv6_router_solicitation(&parsed, network.client_mac(), network.client_link_local()).ok_or({
                         Error::illegal_argument(format!("Could not calculate return package for an incoming icmpv6 message:\n{:?}", parsed))
                     });
                     Some(res)
@@ -94,37 +167,50 @@ impl ShmemNetworkDevice {
             }
         } else if parsed.net().is_arp() {
             log::debug!("Manually responding to arp");
-            let res = respond_to_arp(&parsed, CLIENT_MAC_ADDR);
+            let res = respond_to_arp(&parsed, network.client_mac(), network.router_mac());
             Some(Ok(res))
         } else {
             None
         }
     }
+    /// Answer Zephyr's packets until it booted and stopped sending, see [`Timing`].
     pub fn init_zephyr(
         &mut self,
+        timing: Timing,
+        network: &NetworkProfile,
         mut package_logger: impl FnMut(Source<Vec<u8>>),
     ) -> Result<(), Error> {
         let start = Instant::now();
         let mut last_packet_time = Instant::now();
-        while start.elapsed() < SETUP_TIMEOUT || last_packet_time.elapsed() < INTER_SEND_WAIT {
-            if let Some(p) = self.try_recv() {
-                let parsed = parse_eth(&p)
-                    .map_err(|e| format!("{e:?}"))
-                    .map_err(Error::illegal_argument)?;
-                package_logger(Source::Server(p));
-                if let Some(res) = Self::respond_manually(parsed) {
-                    match res {
-                        Ok(response) => {
-                            self.send(&response);
-                            package_logger(Source::Client(response));
-                        }
-                        Err(e) => return Err(e),
-                    }
-                }
+        while start.elapsed() < timing.setup_timeout
+            || last_packet_time.elapsed() < timing.inter_send_wait
+        {
+            if self.respond_to_setup(network, &mut package_logger)? {
                 last_packet_time = Instant::now();
             }
-            sleep(INTER_SEND_WAIT / 5);
+            sleep(timing.inter_send_wait / 5);
         }
         Ok(())
     }
+
+    /// Handle the next packet Zephyr sent while setting up its network, answering it if necessary. Returns whether there was one.
+    pub fn respond_to_setup(
+        &mut self,
+        network: &NetworkProfile,
+        package_logger: &mut impl FnMut(Source<Vec<u8>>),
+    ) -> Result<bool, Error> {
+        let Some(p) = self.try_recv() else {
+            return Ok(false);
+        };
+        let parsed = parse_eth(&p)
+            .map_err(|e| format!("{e:?}"))
+            .map_err(Error::illegal_argument)?;
+        package_logger(Source::Server(p));
+        if let Some(res) = Self::respond_manually(parsed, network) {
+            let response = res?;
+            self.send(&response)?;
+            package_logger(Source::Client(response));
+        }
+        Ok(true)
+    }
 }
@@ -1,7 +1,54 @@
-use std::{cell::RefCell, ptr::slice_from_raw_parts_mut, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    ptr,
+    rc::Rc,
+    sync::atomic::{AtomicU64, Ordering},
+};
 
 use libafl_bolts::shmem::{ShMem, ShMemDescription};
 
+/// "FTZR", needs to match `SHMEM_RING_MAGIC` in `eth_shmem_priv.h`.
+pub const RING_MAGIC: u32 = 0x525a_5446;
+/// Needs to match `SHMEM_RING_VERSION` in `eth_shmem_priv.h`, increment it on any change of the layout.
+pub const RING_VERSION: u32 = 2;
+/// Size of `struct shmem_ring_header`.
+pub const RING_HEADER_SIZE: usize = 32;
+
+const MAGIC_OFFSET: usize = 0;
+const VERSION_OFFSET: usize = 4;
+const CAPACITY_OFFSET: usize = 8;
+const MTU_OFFSET: usize = 12;
+const HEAD_OFFSET: usize = 16;
+const TAIL_OFFSET: usize = 24;
+
+/// Size of `struct shmem_ring_frame`.
+const FRAME_HEADER_SIZE: usize = 16;
+const FRAME_ALIGN: usize = 16;
+/// The rest of the ring is padding, the next frame starts at its beginning.
+const FRAME_WRAP: u32 = 1 << 0;
+const FRAME_TIMESTAMP: u32 = 1 << 1;
+
+/// Size of one direction of the shmem holding `capacity` bytes of frames.
+pub fn ring_size(capacity: usize) -> usize {
+    RING_HEADER_SIZE + capacity.next_multiple_of(FRAME_ALIGN)
+}
+
+fn record_size(len: usize) -> usize {
+    FRAME_HEADER_SIZE + len.next_multiple_of(FRAME_ALIGN)
+}
+
+/// A frame read from the ring, with the time it was sent in nanoseconds since the sender booted if the sender provided it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub data: Vec<u8>,
+    pub timestamp: Option<u64>,
+}
+
+/// One direction of the shared memory link: a lock-free single producer single consumer ring of frames.
+///
+/// The header holds magic, version, capacity and the MTU of the link followed by the positions written by the producer (`head`) and the consumer (`tail`), in bytes since the last reset. Each frame is prefixed by its length, flags and an optional timestamp and padded to 16 bytes. Frames do not wrap around, the producer marks the remaining space as padding instead.
+///
+/// Clones share the shmem and whether the producer wrote an invalid frame header, see [`Self::is_corrupted`].
 #[derive(Clone)]
 pub struct ShmemNetDeviceBuffer<S>
 where
@@ -9,6 +56,7 @@ where
 {
     shmem: Rc<RefCell<S>>,
     offset: usize,
+    corrupted: Rc<Cell<bool>>,
 }
 
 impl<S> ShmemNetDeviceBuffer<S>
@@ -22,67 +70,160 @@ where
             Self {
                 shmem: shmem.clone(),
                 offset: 0,
+                corrupted: Rc::default(),
             },
             Self {
                 shmem,
                 offset: directional_size / 2,
+                corrupted: Rc::default(),
             },
         )
     }
 
-    pub fn reset(&mut self) {
+    /// Empty the ring and write its header, Zephyr refuses to start without it.
+    pub fn reset(&mut self, mtu: usize) {
         let directional_len = self.shmem.borrow().len() / 2;
         self.shmem.borrow_mut()[self.offset..(self.offset + directional_len)].fill(0);
-        self.set_empty();
+        self.write_u32(CAPACITY_OFFSET, self.capacity().try_into().unwrap());
+        self.write_u32(MTU_OFFSET, mtu.try_into().unwrap());
+        self.write_u32(VERSION_OFFSET, RING_VERSION);
+        self.write_u32(MAGIC_OFFSET, RING_MAGIC);
+        self.corrupted.set(false);
     }
 
-    fn get_ptr(&mut self) -> *mut u8 {
-        let res = self
-            .shmem
+    fn get_ptr(&self) -> *mut u8 {
+        self.shmem
             .borrow_mut()
             .as_mut_ptr()
-            .wrapping_byte_add(self.offset);
-        log::trace!(
-            "value at ptr for offset {}: {}",
-            self.offset,
-            *cast_to_i32(res)
-        );
-        res
+            .wrapping_byte_add(self.offset)
     }
 
-    pub fn is_empty(&mut self) -> bool {
-        *cast_to_i32(self.get_ptr()) < 0
+    fn capacity(&self) -> usize {
+        (self.shmem.borrow().len() / 2 - RING_HEADER_SIZE) / FRAME_ALIGN * FRAME_ALIGN
     }
 
-    pub fn set_empty(&mut self) {
-        *cast_to_i32(self.get_ptr()) = -1
+    /// Largest frame the ring can hold.
+    pub fn max_frame_len(&self) -> usize {
+        self.capacity() - FRAME_HEADER_SIZE
     }
 
-    pub fn get_size(&mut self) -> &mut i32 {
-        cast_to_i32(self.get_ptr())
+    fn write_u32(&self, offset: usize, value: u32) {
+        unsafe { ptr::write_volatile(self.get_ptr().add(offset).cast::<u32>(), value) }
     }
 
-    pub fn send(&mut self, len: usize) {
-        *cast_to_i32(self.get_ptr()) = len.try_into().unwrap();
+    fn position(&self, offset: usize) -> &AtomicU64 {
+        unsafe { AtomicU64::from_ptr(self.get_ptr().add(offset).cast::<u64>()) }
     }
 
-    pub fn prep_data(&mut self, len: usize) -> &mut [u8] {
-        if len > self.shmem.borrow().len() / 2 - 4 {
-            panic!("Attempting to prepare a slice larger than the shmem");
+    fn frame_ptr(&self, index: u64) -> *mut u8 {
+        self.get_ptr()
+            .wrapping_byte_add(RING_HEADER_SIZE + usize::try_from(index).unwrap())
+    }
+
+    fn write_frame_header(&self, index: u64, len: usize, flags: u32, timestamp: u64) {
+        let frame = self.frame_ptr(index);
+        unsafe {
+            ptr::write_volatile(frame.cast::<u32>(), len.try_into().unwrap());
+            ptr::write_volatile(frame.add(4).cast::<u32>(), flags);
+            ptr::write_volatile(frame.add(8).cast::<u64>(), timestamp);
         }
-        let ptr = self.get_ptr().wrapping_byte_add(4);
-        unsafe { slice_from_raw_parts_mut(ptr, len).as_mut() }.unwrap()
     }
 
-    pub fn get_data_and_set_empty(&mut self) -> Option<Vec<u8>> {
-        (*self.get_size() >= 0).then(|| {
-            let ptr = self.get_ptr().wrapping_byte_add(4);
-            let size = (*self.get_size()).try_into().unwrap();
-            let slice = unsafe { slice_from_raw_parts_mut(ptr, size).as_mut() }.unwrap();
-            let vec = slice.to_vec();
-            self.set_empty();
-            vec
-        })
+    /// Whether [`Self::pop`] found a frame header the producer can not have written correctly, e.g. because the target overwrote the ring, since the last [`Self::reset`].
+    pub fn is_corrupted(&self) -> bool {
+        self.corrupted.get()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.position(HEAD_OFFSET).load(Ordering::Acquire)
+            == self.position(TAIL_OFFSET).load(Ordering::Acquire)
+    }
+
+    /// Append a frame, returns `false` if the consumer has not made enough space for it yet.
+    pub fn push(&mut self, data: &[u8], timestamp: Option<u64>) -> bool {
+        let capacity = self.capacity() as u64;
+        let record = record_size(data.len()) as u64;
+        if record > capacity {
+            panic!("Attempting to send a frame larger than the ring");
+        }
+
+        let mut head = self.position(HEAD_OFFSET).load(Ordering::Relaxed);
+        let tail = self.position(TAIL_OFFSET).load(Ordering::Acquire);
+        let contiguous = capacity - head % capacity;
+        if record > contiguous {
+            if head - tail + contiguous > capacity {
+                return false;
+            }
+            self.write_frame_header(head % capacity, 0, FRAME_WRAP, 0);
+            head += contiguous;
+            self.position(HEAD_OFFSET).store(head, Ordering::Release);
+        }
+        if head - tail + record > capacity {
+            return false;
+        }
+
+        let index = head % capacity;
+        let flags = if timestamp.is_some() {
+            FRAME_TIMESTAMP
+        } else {
+            0
+        };
+        self.write_frame_header(index, data.len(), flags, timestamp.unwrap_or_default());
+        unsafe {
+            ptr::copy_nonoverlapping(
+                data.as_ptr(),
+                self.frame_ptr(index).add(FRAME_HEADER_SIZE),
+                data.len(),
+            );
+        }
+        self.position(HEAD_OFFSET)
+            .store(head + record, Ordering::Release);
+        true
+    }
+
+    /// Take the oldest frame from the ring, if there is one.
+    ///
+    /// A frame extending past the end of the ring marks it as corrupted, it is not read and neither are the following ones.
+    pub fn pop(&mut self) -> Option<Frame> {
+        let capacity = self.capacity() as u64;
+        loop {
+            let tail = self.position(TAIL_OFFSET).load(Ordering::Relaxed);
+            let head = self.position(HEAD_OFFSET).load(Ordering::Acquire);
+            if tail == head {
+                return None;
+            }
+
+            let index = tail % capacity;
+            let frame = self.frame_ptr(index);
+            let (len, flags, timestamp) = unsafe {
+                (
+                    ptr::read_volatile(frame.cast::<u32>()) as usize,
+                    ptr::read_volatile(frame.add(4).cast::<u32>()),
+                    ptr::read_volatile(frame.add(8).cast::<u64>()),
+                )
+            };
+            if flags & FRAME_WRAP != 0 {
+                self.position(TAIL_OFFSET)
+                    .store(tail + capacity - index, Ordering::Release);
+                continue;
+            }
+            if index + record_size(len) as u64 > capacity {
+                log::warn!("Received a frame of len {len} at {index}, which does not fit into the ring of {capacity} bytes");
+                self.corrupted.set(true);
+                return None;
+            }
+
+            let mut data = vec![0; len];
+            unsafe {
+                ptr::copy_nonoverlapping(frame.add(FRAME_HEADER_SIZE), data.as_mut_ptr(), len);
+            }
+            self.position(TAIL_OFFSET)
+                .store(tail + record_size(len) as u64, Ordering::Release);
+            return Some(Frame {
+                data,
+                timestamp: (flags & FRAME_TIMESTAMP != 0).then_some(timestamp),
+            });
+        }
     }
 
     pub fn description(&self) -> ShMemDescription {
@@ -90,6 +231,66 @@ where
     }
 }
 
-fn cast_to_i32<'a>(ptr: *mut u8) -> &'a mut i32 {
-    unsafe { ptr.cast::<i32>().as_mut() }.unwrap()
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use libafl_bolts::shmem::{MmapShMemProvider, ShMemProvider as _};
+
+    use super::{ring_size, Frame, ShmemNetDeviceBuffer};
+
+    #[test]
+    fn ring_keeps_frames_in_order_across_wraps() {
+        let shmem = MmapShMemProvider::default()
+            .new_shmem(ring_size(128) * 2)
+            .unwrap();
+        let (mut tx, _) = ShmemNetDeviceBuffer::new(Rc::new(RefCell::new(shmem)));
+        tx.reset(1500);
+        let mut rx = tx.clone();
+
+        // 3 frames of 48 bytes each need 144 bytes, so only two fit at once
+        assert!(tx.push(&[1; 20], None));
+        assert!(tx.push(&[2; 30], Some(42)));
+        assert!(!tx.push(&[3; 17], None));
+        assert_eq!(
+            rx.pop(),
+            Some(Frame {
+                data: vec![1; 20],
+                timestamp: None
+            })
+        );
+
+        // does not fit into the remaining 32 bytes at the end, wraps to the beginning
+        assert!(tx.push(&[3; 17], None));
+        assert_eq!(
+            rx.pop(),
+            Some(Frame {
+                data: vec![2; 30],
+                timestamp: Some(42)
+            })
+        );
+        assert_eq!(rx.pop().map(|f| f.data), Some(vec![3; 17]));
+        assert_eq!(rx.pop(), None);
+        assert!(rx.is_empty());
+    }
+
+    #[test]
+    fn frame_past_the_end_corrupts_the_ring() {
+        let shmem = MmapShMemProvider::default()
+            .new_shmem(ring_size(128) * 2)
+            .unwrap();
+        let (mut tx, _) = ShmemNetDeviceBuffer::new(Rc::new(RefCell::new(shmem)));
+        tx.reset(1500);
+        let mut rx = tx.clone();
+
+        assert!(tx.push(&[1; 20], None));
+        // the length of the frame as the target could overwrite it
+        tx.write_frame_header(0, 200, 0, 0);
+        assert_eq!(rx.pop(), None);
+        assert!(rx.is_corrupted());
+        assert!(!rx.is_empty());
+
+        rx.reset(1500);
+        assert!(!rx.is_corrupted());
+    }
 }
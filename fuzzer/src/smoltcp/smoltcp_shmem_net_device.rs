@@ -1,10 +1,8 @@
-use std::{thread::sleep, time::Duration};
-
 use libafl_bolts::shmem::MmapShMem;
 use smoltcp::phy::{self, Device, DeviceCapabilities};
 
 use crate::{
-    direction::Direction, layers::data_link::parse_eth,
+    direction::Direction, layers::data_link::parse_eth, runner::network::ETHERNET_HEADER_LEN,
     smoltcp::shmem_net_device::ShmemNetworkDevice,
 };
 
@@ -67,7 +65,8 @@ impl Device for SmoltcpShmemNetworkDevice {
 
     fn capabilities(&self) -> DeviceCapabilities {
         let mut res = DeviceCapabilities::default();
-        res.max_transmission_unit = 1500;
+        // smoltcp counts the Ethernet header for Ethernet devices
+        res.max_transmission_unit = self.device.mtu() + ETHERNET_HEADER_LEN;
         res.medium = phy::Medium::Ethernet;
         res
     }
@@ -100,19 +99,19 @@ impl phy::TxToken for TxToken<'_> {
         F: FnOnce(&mut [u8]) -> R,
     {
         log::debug!("Sending {len} bytes");
-        while !self.shmem.is_empty() {
-            log::info!("not ready");
-            sleep(Duration::from_millis(1));
-        }
-
         let mut buf = vec![0; len];
         let res = f(&mut buf);
 
-        self.shmem.prep_data(len).copy_from_slice(&buf);
-        (self.packet_logger)(Direction::Outgoing(buf.clone()));
-        self.shmem.send(len);
-        log::debug!("Sent packet of len: {}", len);
-        log::debug!("tx is empty: {}", self.shmem.is_empty());
+        if self.shmem.push(&buf, None) {
+            (self.packet_logger)(Direction::Outgoing(buf));
+            log::debug!("Sent packet of len: {}", len);
+        } else {
+            // smoltcp retransmits like it would on a congested link
+            log::warn!(
+                "Ring towards Zephyr is full, dropped packet of len: {}",
+                len
+            );
+        }
         res
     }
 }